@@ -1,12 +1,13 @@
 use crate::protocol::config_dir;
-use crate::types::{ProcessDef, Service, ServiceType};
-use serde::Deserialize;
+use crate::types::{default_ready_timeout, default_restart_backoff, default_stop_signals, default_success_exit_codes, DrainConfig, ProcessDef, ReloadPolicy, Service, ServiceType};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
 use std::collections::{BTreeMap, HashMap};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 // ── Global config (~/.config/ubermind/config.toml) ──────────────────────────
 
-#[derive(Debug, Clone, Deserialize, Default)]
+#[derive(Debug, Clone, Deserialize, Serialize, Default, JsonSchema)]
 pub struct GlobalConfig {
 	#[serde(default)]
 	pub daemon: DaemonConfig,
@@ -16,7 +17,7 @@ pub struct GlobalConfig {
 	pub defaults: DefaultsConfig,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
 #[allow(dead_code)]
 pub struct DaemonConfig {
 	#[serde(default = "default_idle_timeout")]
@@ -24,18 +25,42 @@ pub struct DaemonConfig {
 	pub log_dir: Option<String>,
 	#[serde(default = "default_port")]
 	pub port: u16,
+	/// Origins allowed to call the HTTP API. Empty allows any origin.
+	#[serde(default)]
+	pub cors_origins: Vec<String>,
+	/// Send a keepalive on streaming connections after this many idle seconds: a
+	/// websocket ping, or a `keepalive` response on the socket. 0 disables.
+	#[serde(default)]
+	pub keepalive_secs: u64,
+	/// Serve the HTTP API on this Unix socket instead of the TCP port.
+	#[serde(default)]
+	pub http_unix_socket: Option<String>,
 }
 
 impl Default for DaemonConfig {
 	fn default() -> Self {
-		Self { idle_timeout: default_idle_timeout(), log_dir: None, port: default_port() }
+		Self {
+			idle_timeout: default_idle_timeout(),
+			log_dir: None,
+			port: default_port(),
+			cors_origins: Vec::new(),
+			keepalive_secs: 0,
+			http_unix_socket: None,
+		}
+	}
+}
+
+impl DaemonConfig {
+	/// `keepalive_secs` as a duration, if enabled.
+	pub fn keepalive_interval(&self) -> Option<std::time::Duration> {
+		(self.keepalive_secs > 0).then(|| std::time::Duration::from_secs(self.keepalive_secs))
 	}
 }
 
 fn default_idle_timeout() -> u64 { 300 }
 fn default_port() -> u16 { 13369 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
 pub struct LogsConfig {
 	#[serde(default = "default_max_size")]
 	pub max_size_bytes: u64,
@@ -43,6 +68,14 @@ pub struct LogsConfig {
 	pub max_age_days: u32,
 	#[serde(default = "default_max_files")]
 	pub max_files: u32,
+	/// Regex for the level token used by `--color-levels`; first capture group is the level.
+	pub level_pattern: Option<String>,
+	/// Collapse runs of identical lines in log files. Live output is unaffected.
+	#[serde(default)]
+	pub dedupe: bool,
+	/// Also send captured lines to the local syslog (facility user, ident `service.process`).
+	#[serde(default)]
+	pub syslog: bool,
 }
 
 impl Default for LogsConfig {
@@ -51,6 +84,9 @@ impl Default for LogsConfig {
 			max_size_bytes: default_max_size(),
 			max_age_days: default_max_age_days(),
 			max_files: default_max_files(),
+			level_pattern: None,
+			dedupe: false,
+			syslog: false,
 		}
 	}
 }
@@ -59,7 +95,7 @@ fn default_max_size() -> u64 { 10 * 1024 * 1024 }
 fn default_max_age_days() -> u32 { 7 }
 fn default_max_files() -> u32 { 5 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
 pub struct DefaultsConfig {
 	#[serde(default = "default_true")]
 	pub restart: bool,
@@ -67,8 +103,28 @@ pub struct DefaultsConfig {
 	pub max_retries: u32,
 	#[serde(default = "default_restart_delay")]
 	pub restart_delay: u64,
+	/// Adds a random 0..jitter fraction to each restart delay.
+	#[serde(default)]
+	pub restart_jitter: f64,
+	/// Multiplies the delay for each consecutive restart; 1 keeps it fixed.
+	#[serde(default = "default_restart_backoff")]
+	pub restart_backoff: f64,
+	/// Only crashes within this many seconds count towards `max_retries`.
+	/// Also accepted as `max_retries_window_secs`.
+	#[serde(default, skip_serializing_if = "Option::is_none", alias = "max_retries_window_secs")]
+	pub max_retries_window: Option<u64>,
+	/// A process that stayed up this many seconds gets a fresh retry count when it crashes.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub healthy_after: Option<u64>,
 	#[serde(default = "default_env")]
 	pub env: HashMap<String, String>,
+	/// A .env-style file of variables shared by every process. Values set in
+	/// `env` above, and each process's own env, take precedence.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub env_file: Option<String>,
+	/// Start processes without the daemon's environment; see `env_clear` on a service.
+	#[serde(default)]
+	pub env_clear: bool,
 }
 
 impl Default for DefaultsConfig {
@@ -77,7 +133,13 @@ impl Default for DefaultsConfig {
 			restart: true,
 			max_retries: default_max_retries(),
 			restart_delay: default_restart_delay(),
+			restart_jitter: 0.0,
+			restart_backoff: default_restart_backoff(),
+			max_retries_window: None,
+			healthy_after: None,
 			env: default_env(),
+			env_file: None,
+			env_clear: false,
 		}
 	}
 }
@@ -96,8 +158,15 @@ pub fn load_global_config() -> GlobalConfig {
 	let path = config_dir().join("config.toml");
 	if path.exists() {
 		match std::fs::read_to_string(&path) {
-			Ok(content) => match toml::from_str(&content) {
-				Ok(config) => return config,
+			Ok(content) => match toml::from_str::<GlobalConfig>(&content) {
+				Ok(mut config) => {
+					load_defaults_env_file(&mut config.defaults);
+					let defaults = &mut config.defaults;
+					defaults.restart_jitter = resolve_factor("defaults", "restart_jitter", Some(defaults.restart_jitter), 0.0, 0.0);
+					defaults.restart_backoff =
+						resolve_factor("defaults", "restart_backoff", Some(defaults.restart_backoff), 1.0, default_restart_backoff());
+					return config;
+				}
 				Err(e) => eprintln!("warning: failed to parse {}: {}", path.display(), e),
 			},
 			Err(e) => eprintln!("warning: failed to read {}: {}", path.display(), e),
@@ -106,11 +175,26 @@ pub fn load_global_config() -> GlobalConfig {
 	GlobalConfig::default()
 }
 
+/// Merges `defaults.env_file` into `defaults.env`, under any value already set there.
+fn load_defaults_env_file(defaults: &mut DefaultsConfig) {
+	let Some(ref file) = defaults.env_file else { return };
+	let path = expand_tilde(file);
+	match std::fs::read_to_string(&path) {
+		Ok(content) => {
+			for (key, value) in parse_env_file(&content) {
+				defaults.env.entry(key).or_insert(value);
+			}
+		}
+		Err(e) => eprintln!("warning: defaults.env_file {}: {}", path.display(), e),
+	}
+}
+
 // ── services.toml format ─────────────────────────────────────────────────────
 
 /// A single service definition — either a bare command string or a full table.
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
 #[serde(untagged)]
+#[allow(clippy::large_enum_variant)] // short-lived, only exists while parsing
 enum ServiceDef {
 	Simple(String),
 	Full {
@@ -120,13 +204,114 @@ enum ServiceDef {
 		restart: Option<bool>,
 		max_retries: Option<u32>,
 		restart_delay: Option<u64>,
+		/// Count only crashes within this many seconds against `max_retries`.
+		/// Also accepted as `max_retries_window_secs`.
+		#[serde(alias = "max_retries_window_secs")]
+		max_retries_window: Option<u64>,
+		/// Adds a random 0..jitter fraction to each restart delay, e.g. 0.5.
+		restart_jitter: Option<f64>,
+		/// Multiplies the delay for each consecutive restart, e.g. 2 for 1s, 2s, 4s.
+		restart_backoff: Option<f64>,
+		/// Seconds of uptime after which a crash no longer counts against earlier ones.
+		healthy_after: Option<u64>,
+		/// Exit codes treated as success, e.g. [0, 2]. Defaults to [0].
+		success_exit_codes: Option<Vec<i32>>,
+		/// Stop sequence as [signal, seconds to wait] pairs, e.g.
+		/// [["SIGTERM", 5], ["SIGINT", 3], ["SIGKILL", 0]].
+		stop_signals: Option<Vec<(String, u64)>>,
+		/// Seconds between SIGTERM and SIGKILL on stop (default 3). Shorthand
+		/// for the common two-step `stop_signals`.
+		stop_timeout: Option<u64>,
+		/// Signal sent first on stop instead of SIGTERM, e.g. "SIGQUIT".
+		stop_signal: Option<String>,
 		#[serde(default)]
 		env: HashMap<String, String>,
+		/// Start without the daemon's environment: only `env` (merged over the
+		/// defaults) and PATH are set. Setting PATH = "" leaves it empty too.
+		env_clear: Option<bool>,
 		autostart: Option<bool>,
+		#[serde(default)]
+		reload_policy: ReloadPolicy,
+		ready_port: Option<u16>,
+		ready_timeout: Option<u64>,
+		/// Starting several services at once brings up lower values first,
+		/// waiting for each tier to be ready. Defaults to 0.
+		start_priority: Option<i32>,
+		reload_signal: Option<String>,
+		#[serde(default)]
+		ports: Vec<u16>,
+		capture: Option<bool>,
+		/// Run attached to a pseudo-terminal instead of pipes.
+		pty: Option<bool>,
+		log_file: Option<String>,
+		/// Caps the service's log directory as a whole. When several processes
+		/// set it, the largest value applies.
+		max_log_total_bytes: Option<u64>,
+		max_log_size: Option<u64>,
+		/// Scheduling niceness, -20 (highest priority) to 19 (lowest).
+		nice: Option<i32>,
+		/// Octal file mode creation mask, e.g. "0022".
+		umask: Option<String>,
+		/// `ub drain` runs `drain.run`, waits `drain.wait_secs`, then stops the process.
+		drain: Option<DrainConfig>,
+		/// Run once a process gives up, with UBERMIND_SERVICE, UBERMIND_PROCESS
+		/// and UBERMIND_EXIT_CODE set, e.g. to send a notification. A task is
+		/// never retried, so for a task that is any failed run.
+		on_failure: Option<String>,
+		os: Option<OsFilter>,
+		/// Expands into `name-0` .. `name-{count-1}` when the config is loaded, with
+		/// `${index}` substituted in `run` and env values. These are ordinary
+		/// processes afterwards; changing the count takes a config reload.
+		count: Option<u32>,
 	},
 }
 
+/// `os = "linux"` or `os = ["macos", "linux"]`, matched against `std::env::consts::OS`.
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(untagged)]
+enum OsFilter {
+	One(String),
+	Many(Vec<String>),
+}
+
+impl OsFilter {
+	fn matches(&self, os: &str) -> bool {
+		match self {
+			OsFilter::One(name) => name.eq_ignore_ascii_case(os),
+			OsFilter::Many(names) => names.iter().any(|n| n.eq_ignore_ascii_case(os)),
+		}
+	}
+}
+
 impl ServiceDef {
+	/// Instantiates a `count = N` template; anything else is returned as is.
+	fn expand(self, name: String) -> Vec<(String, ServiceDef)> {
+		let ServiceDef::Full { count: Some(count), .. } = self else {
+			return vec![(name, self)];
+		};
+		(0..count)
+			.map(|index| {
+				let mut def = self.clone();
+				if let ServiceDef::Full { run, env, count, .. } = &mut def {
+					let index = index.to_string();
+					*run = run.replace("${index}", &index);
+					for value in env.values_mut() {
+						*value = value.replace("${index}", &index);
+					}
+					*count = None;
+				}
+				(format!("{}-{}", name, index), def)
+			})
+			.collect()
+	}
+
+	fn runs_on(&self, os: &str) -> bool {
+		match self {
+			ServiceDef::Full { os: Some(filter), .. } => filter.matches(os),
+			_ => true,
+		}
+	}
+
 	fn into_process_def(self, name: String, defaults: &DefaultsConfig) -> ProcessDef {
 		match self {
 			ServiceDef::Simple(cmd) => ProcessDef {
@@ -136,32 +321,240 @@ impl ServiceDef {
 				restart: defaults.restart,
 				max_retries: defaults.max_retries,
 				restart_delay_secs: defaults.restart_delay,
+				max_retries_window_secs: defaults.max_retries_window.filter(|&secs| secs > 0),
+				restart_jitter: defaults.restart_jitter,
+				restart_backoff: defaults.restart_backoff,
+				healthy_after_secs: defaults.healthy_after.filter(|&secs| secs > 0),
+				success_exit_codes: default_success_exit_codes(),
+				stop_signals: default_stop_signals(),
 				env: defaults.env.clone(),
+				env_clear: defaults.env_clear,
 				autostart: true,
+				reload_policy: ReloadPolicy::Restart,
+				ready_port: None,
+				ready_timeout_secs: default_ready_timeout(),
+				start_priority: 0,
+				reload_signal: None,
+				ports: vec![],
+				capture: true,
+				pty: false,
+				log_file: None,
+				max_log_total_bytes: None,
+				max_log_size: None,
+				nice: None,
+				umask: None,
+				drain: None,
+				on_failure: None,
 			},
-			ServiceDef::Full { run, service_type, restart, max_retries, restart_delay, env, autostart } => {
+			ServiceDef::Full {
+				run,
+				service_type,
+				restart,
+				max_retries,
+				restart_delay,
+				max_retries_window,
+				restart_jitter,
+				restart_backoff,
+				healthy_after,
+				success_exit_codes,
+				stop_signals,
+				stop_timeout,
+				stop_signal,
+				env,
+				env_clear,
+				autostart,
+				reload_policy,
+				ready_port,
+				ready_timeout,
+				start_priority,
+				reload_signal,
+				ports,
+				capture,
+				pty,
+				log_file,
+				max_log_total_bytes,
+				max_log_size,
+				nice,
+				umask,
+				drain,
+				on_failure,
+				os: _,
+				count: _,
+			} => {
 				let is_task = service_type == ServiceType::Task;
 				let mut merged_env = defaults.env.clone();
 				merged_env.extend(env);
+				let env_clear = env_clear.unwrap_or(defaults.env_clear);
+				if env_clear && merged_env.get("PATH").is_some_and(|path| path.is_empty()) {
+					eprintln!("warning: '{}' clears PATH; its commands need absolute paths", name);
+				}
+				let reload_signal = reload_signal.and_then(|sig| match parse_signal(&sig) {
+					Ok(signal) => Some(signal.as_str().to_string()),
+					Err(e) => {
+						eprintln!("warning: ignoring reload_signal for '{}': {}", name, e);
+						None
+					}
+				});
+				let restart_jitter = resolve_factor(&name, "restart_jitter", restart_jitter, 0.0, defaults.restart_jitter);
+				let restart_backoff = resolve_factor(&name, "restart_backoff", restart_backoff, 1.0, defaults.restart_backoff);
+				let stop_signal = stop_signal.and_then(|signal| match parse_signal(&signal) {
+					Ok(signal) => Some(signal.as_str().to_string()),
+					Err(e) => {
+						eprintln!("warning: ignoring stop_signal for '{}': {}, using SIGTERM", name, e);
+						None
+					}
+				});
+				let stop_signals = match stop_signals.map(|s| parse_stop_signals(&s)) {
+					Some(Ok(signals)) => {
+						if stop_timeout.is_some() || stop_signal.is_some() {
+							eprintln!(
+								"warning: ignoring stop_signal and stop_timeout for '{}': stop_signals sets the whole sequence",
+								name
+							);
+						}
+						signals
+					}
+					Some(Err(e)) => {
+						eprintln!("warning: ignoring stop_signals for '{}': {}", name, e);
+						stop_sequence(stop_signal, stop_timeout)
+					}
+					None => stop_sequence(stop_signal, stop_timeout),
+				};
+				let nice = nice.filter(|n| {
+					let valid = (-20..=19).contains(n);
+					if !valid {
+						eprintln!("warning: ignoring nice for '{}': {} is outside -20..19", name, n);
+					}
+					valid
+				});
+				let umask = umask.and_then(|m| match parse_umask(&m) {
+					Ok(mask) => Some(mask),
+					Err(e) => {
+						eprintln!("warning: ignoring umask for '{}': {}", name, e);
+						None
+					}
+				});
+				let capture = capture.unwrap_or(true);
+				let pty = pty.unwrap_or(false);
+				let log_file = log_file.map(|p| expand_tilde(&p)).filter(|p| {
+					if !p.is_absolute() {
+						eprintln!("warning: ignoring log_file for '{}': {} is not an absolute path", name, p.display());
+					}
+					p.is_absolute()
+				});
 				ProcessDef {
+					restart: resolve_restart(&name, is_task, restart, defaults.restart),
 					name,
 					command: run,
 					service_type,
-					restart: restart.unwrap_or(if is_task { false } else { defaults.restart }),
 					max_retries: max_retries.unwrap_or(defaults.max_retries),
 					restart_delay_secs: restart_delay.unwrap_or(defaults.restart_delay),
+					max_retries_window_secs: max_retries_window.or(defaults.max_retries_window).filter(|&secs| secs > 0),
+					restart_jitter,
+					restart_backoff,
+					healthy_after_secs: healthy_after.or(defaults.healthy_after).filter(|&secs| secs > 0),
+					success_exit_codes: success_exit_codes.unwrap_or_else(default_success_exit_codes),
+					stop_signals,
 					env: merged_env,
+					env_clear,
 					autostart: autostart.unwrap_or(!is_task),
+					reload_policy,
+					ready_port,
+					ready_timeout_secs: ready_timeout.unwrap_or(default_ready_timeout()),
+					start_priority: start_priority.unwrap_or(0),
+					reload_signal,
+					ports,
+					capture,
+					pty,
+					log_file,
+					max_log_total_bytes,
+					max_log_size,
+					nice,
+					umask,
+					drain,
+					on_failure,
 				}
 			}
 		}
 	}
 }
 
+/// The default stop sequence, starting with `signal` instead of SIGTERM and
+/// waiting `timeout` seconds instead of 3 before SIGKILL.
+fn stop_sequence(signal: Option<String>, timeout: Option<u64>) -> Vec<(String, u64)> {
+	let mut signals = default_stop_signals();
+	if let Some(signal) = signal {
+		signals[0].0 = signal;
+	}
+	if let Some(timeout) = timeout {
+		signals[0].1 = timeout;
+	}
+	signals
+}
+
+/// Validates a stop sequence, normalising signal names to their `SIGxxx` form.
+fn parse_stop_signals(signals: &[(String, u64)]) -> Result<Vec<(String, u64)>, String> {
+	if signals.is_empty() {
+		return Err("the sequence is empty".to_string());
+	}
+	signals
+		.iter()
+		.map(|(name, wait)| Ok((parse_signal(name)?.as_str().to_string(), *wait)))
+		.collect()
+}
+
+/// Parses an octal umask such as "022" or "0o027".
+fn parse_umask(value: &str) -> Result<u32, String> {
+	let digits = value.trim().trim_start_matches("0o");
+	u32::from_str_radix(digits, 8)
+		.ok()
+		.filter(|mask| *mask <= 0o777)
+		.ok_or_else(|| format!("'{}' is not an octal mode between 000 and 777", value))
+}
+
+/// Tasks never restart, so an explicit `restart = true` on one is ignored.
+fn task_restart_warning(name: &str, is_task: bool, restart: Option<bool>) -> Option<String> {
+	(is_task && restart == Some(true)).then(|| {
+		format!("warning: ignoring restart = true for '{}': tasks run once and are never restarted", name)
+	})
+}
+
+/// `value` when it is a finite number of at least `min`, else `default` (with a
+/// warning if it was set).
+fn resolve_factor(name: &str, field: &str, value: Option<f64>, min: f64, default: f64) -> f64 {
+	match value {
+		Some(v) if !(v.is_finite() && v >= min) => {
+			eprintln!("warning: ignoring {} for '{}': {} is not a number of at least {}", field, name, v, min);
+			default
+		}
+		Some(v) => v,
+		None => default,
+	}
+}
+
+fn resolve_restart(name: &str, is_task: bool, restart: Option<bool>, default: bool) -> bool {
+	if let Some(warning) = task_restart_warning(name, is_task, restart) {
+		warn_once(warning);
+	}
+	!is_task && restart.unwrap_or(default)
+}
+
+/// JSON Schema for one of the config files ("projects", "services" or "config"),
+/// derived from the structs above so editors validate against what actually parses.
+pub fn schema(file: &str) -> Option<serde_json::Value> {
+	let schema = match file {
+		"projects" => schemars::schema_for!(BTreeMap<String, ProjectDef>),
+		"services" => schemars::schema_for!(BTreeMap<String, ServiceDef>),
+		"config" => schemars::schema_for!(GlobalConfig),
+		_ => return None,
+	};
+	serde_json::to_value(schema).ok()
+}
+
 // ── projects.toml format ──────────────────────────────────────────────────────
 
 /// An entry in projects.toml — either a directory path or a standalone command.
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
 #[serde(untagged)]
 enum ProjectDef {
 	Dir(String),
@@ -235,6 +628,10 @@ pub fn load_projects() -> BTreeMap<String, ServiceEntry> {
 			ProjectDef::Command { run, service_type, restart, max_retries, restart_delay, env } => {
 				// Standalone commands get a synthetic dir under ~/.config/ubermind/_commands/
 				let dir = config_dir().join("_commands").join(&name);
+				let lookup = |var: &str| std::env::var(var).ok();
+				let env: HashMap<String, String> =
+					env.into_iter().map(|(k, v)| (k, interpolate(&v, &lookup))).collect();
+				let run = interpolate(&run, &|var| env.get(var).cloned().or_else(|| lookup(var)));
 				let _ = std::fs::create_dir_all(&dir);
 				services.insert(
 					name.clone(),
@@ -274,50 +671,233 @@ pub fn load_service(entry: &ServiceEntry, defaults: &DefaultsConfig) -> Service
 			name: entry.name.clone(),
 			command: cmd.run.clone(),
 			service_type: cmd.service_type.clone(),
-			restart: cmd.restart.unwrap_or(if is_task { false } else { defaults.restart }),
+			restart: resolve_restart(&entry.name, is_task, cmd.restart, defaults.restart),
 			max_retries: cmd.max_retries.unwrap_or(defaults.max_retries),
 			restart_delay_secs: cmd.restart_delay.unwrap_or(defaults.restart_delay),
+			max_retries_window_secs: defaults.max_retries_window.filter(|&secs| secs > 0),
+			restart_jitter: defaults.restart_jitter,
+			restart_backoff: defaults.restart_backoff,
+			healthy_after_secs: defaults.healthy_after.filter(|&secs| secs > 0),
+			success_exit_codes: default_success_exit_codes(),
+			stop_signals: default_stop_signals(),
 			env,
+			env_clear: defaults.env_clear,
 			autostart: !is_task,
+			reload_policy: ReloadPolicy::Restart,
+			ready_port: None,
+			ready_timeout_secs: default_ready_timeout(),
+			start_priority: 0,
+			reload_signal: None,
+			ports: vec![],
+			capture: true,
+			pty: false,
+			log_file: None,
+			max_log_total_bytes: None,
+			max_log_size: None,
+			nice: None,
+			umask: None,
+			drain: None,
+			on_failure: None,
 		};
 		return Service { name: entry.name.clone(), dir: entry.dir.clone(), processes: vec![proc] };
 	}
 
 	// Project with services.toml
-	let services_path = entry.dir.join("services.toml");
-	let content = match std::fs::read_to_string(&services_path) {
-		Ok(c) => c,
-		Err(_) => {
-			return Service { name: entry.name.clone(), dir: entry.dir.clone(), processes: vec![] };
+	match read_services_toml(entry, defaults) {
+		Ok((service, skipped)) => {
+			for msg in skipped {
+				eprintln!("warning: {}", msg);
+			}
+			service
 		}
-	};
-
-	let raw: BTreeMap<String, toml::Value> = match toml::from_str(&content) {
-		Ok(v) => v,
 		Err(e) => {
-			eprintln!("warning: failed to parse {}: {}", services_path.display(), e);
-			return Service { name: entry.name.clone(), dir: entry.dir.clone(), processes: vec![] };
+			eprintln!("warning: {}", e);
+			Service { name: entry.name.clone(), dir: entry.dir.clone(), processes: vec![] }
 		}
+	}
+}
+
+/// Like `load_service`, but a services.toml that fails to parse, or has an entry
+/// that does, is an error rather than a service with processes missing. Used on
+/// reload, where a half-saved file must not stop what is running.
+pub fn try_load_service(entry: &ServiceEntry, defaults: &DefaultsConfig) -> Result<Service, String> {
+	if entry.inline_command.is_some() {
+		return Ok(load_service(entry, defaults));
+	}
+	let (service, skipped) = read_services_toml(entry, defaults)?;
+	match skipped.into_iter().next() {
+		Some(msg) => Err(msg),
+		None => Ok(service),
+	}
+}
+
+/// The file a project's processes come from: services.toml when present,
+/// otherwise a Procfile. Names services.toml when neither exists.
+pub fn services_file(dir: &Path) -> PathBuf {
+	let services_path = dir.join("services.toml");
+	let procfile_path = dir.join("Procfile");
+	if services_path.exists() {
+		if procfile_path.exists() {
+			warn_once(format!(
+				"warning: {} has both services.toml and a Procfile; using services.toml",
+				dir.display()
+			));
+		}
+		services_path
+	} else if procfile_path.exists() {
+		procfile_path
+	} else {
+		services_path
+	}
+}
+
+/// Config is reloaded on every status refresh and request inside the daemon,
+/// so each distinct warning is printed once per process rather than each time.
+fn warn_once(message: String) {
+	static WARNED: std::sync::Mutex<Vec<String>> = std::sync::Mutex::new(Vec::new());
+	let Ok(mut warned) = WARNED.lock() else { return };
+	if !warned.contains(&message) {
+		eprintln!("{}", message);
+		warned.push(message);
+	}
+}
+
+/// `name: value` lines (Procfile and the old `projects`/`commands` files);
+/// blank lines and `#` comments are skipped.
+pub fn parse_procfile(content: &str) -> Vec<(String, String)> {
+	content
+		.lines()
+		.map(str::trim)
+		.filter(|line| !line.is_empty() && !line.starts_with('#'))
+		.filter_map(|line| {
+			let (name, command) = line.split_once(':')?;
+			let (name, command) = (name.trim(), command.trim());
+			(!name.is_empty() && !command.is_empty()).then(|| (name.to_string(), command.to_string()))
+		})
+		.collect()
+}
+
+/// The processes in an entry's services.toml (or Procfile), plus a message for
+/// each entry skipped because it didn't deserialize. A missing file is an empty service.
+fn read_services_toml(entry: &ServiceEntry, defaults: &DefaultsConfig) -> Result<(Service, Vec<String>), String> {
+	let services_path = services_file(&entry.dir);
+	let Ok(content) = std::fs::read_to_string(&services_path) else {
+		return Ok((Service { name: entry.name.clone(), dir: entry.dir.clone(), processes: vec![] }, vec![]));
 	};
 
+	if services_path.file_name().is_some_and(|n| n == "Procfile") {
+		let processes = parse_procfile(&content)
+			.into_iter()
+			.map(|(name, command)| ServiceDef::Simple(command).into_process_def(name, defaults))
+			.collect();
+		return Ok((Service { name: entry.name.clone(), dir: entry.dir.clone(), processes }, vec![]));
+	}
+
+	let raw: BTreeMap<String, toml::Value> =
+		toml::from_str(&content).map_err(|e| format!("failed to parse {}: {}", services_path.display(), e))?;
+
+	let mut skipped = Vec::new();
 	let processes = raw
 		.into_iter()
 		.filter_map(|(name, value)| {
 			let def: ServiceDef = match value.try_into() {
 				Ok(d) => d,
 				Err(e) => {
-					eprintln!("warning: skipping '{}' in services.toml: {}", name, e);
+					skipped.push(format!("skipping '{}' in {}: {}", name, services_path.display(), e));
 					return None;
 				}
 			};
-			Some(def.into_process_def(name, defaults))
+			// The terminal is read to keep the process from blocking, so there is no discarding it
+			if let ServiceDef::Full { pty: Some(true), capture: Some(false), .. } = def {
+				skipped.push(format!("skipping '{}' in {}: pty needs capture, remove capture = false", name, services_path.display()));
+				return None;
+			}
+			if !def.runs_on(std::env::consts::OS) {
+				tracing::debug!("{}: skipping '{}', not enabled on {}", entry.name, name, std::env::consts::OS);
+				return None;
+			}
+			Some(def.expand(name))
 		})
+		.flatten()
+		.map(|(name, def)| def.into_process_def(name, defaults))
 		.collect();
 
-	Service { name: entry.name.clone(), dir: entry.dir.clone(), processes }
+	Ok((Service { name: entry.name.clone(), dir: entry.dir.clone(), processes }, skipped))
 }
 
-fn expand_tilde(path: &str) -> PathBuf {
+// ── Editing projects.toml ────────────────────────────────────────────────────
+// These work on the document text so comments, ordering and formatting survive.
+
+fn parse_projects_doc(content: &str) -> Result<toml_edit::DocumentMut, String> {
+	content.parse().map_err(|e| format!("failed to parse projects.toml: {}", e))
+}
+
+/// Adds `name = "dir"` as a top-level entry.
+pub fn add_project(content: &str, name: &str, dir: &str) -> Result<String, String> {
+	let mut doc = parse_projects_doc(content)?;
+	if doc.contains_key(name) {
+		return Err(format!("{}: already registered", name));
+	}
+	doc.insert(name, toml_edit::value(dir));
+	Ok(doc.to_string())
+}
+
+/// Removes a project entry, whether a plain directory or a `[name]` command table.
+pub fn remove_project(content: &str, name: &str) -> Result<String, String> {
+	let mut doc = parse_projects_doc(content)?;
+	doc.remove(name).ok_or_else(|| format!("{}: not registered", name))?;
+	Ok(doc.to_string())
+}
+
+/// Renames a project entry in place, keeping its position and any comment above it.
+pub fn rename_project(content: &str, old: &str, new: &str) -> Result<String, String> {
+	let mut doc = parse_projects_doc(content)?;
+	if !doc.contains_key(old) {
+		return Err(format!("{}: not registered", old));
+	}
+	if doc.contains_key(new) {
+		return Err(format!("{}: already registered", new));
+	}
+
+	let table = doc.as_table_mut();
+	let keys: Vec<toml_edit::Key> = table.iter().filter_map(|(k, _)| table.key(k).cloned()).collect();
+	for key in keys {
+		let Some(item) = table.remove(key.get()) else { continue };
+		let key = if key.get() == old {
+			toml_edit::Key::new(new).with_leaf_decor(key.leaf_decor().clone())
+		} else {
+			key
+		};
+		table.insert_formatted(&key, item);
+	}
+	Ok(doc.to_string())
+}
+
+/// Parses a signal name such as "SIGHUP" or "HUP".
+pub fn parse_signal(name: &str) -> Result<nix::sys::signal::Signal, String> {
+	let upper = name.trim().to_uppercase();
+	let full = if upper.starts_with("SIG") { upper } else { format!("SIG{}", upper) };
+	full.parse().map_err(|_| format!("unknown signal: {}", name))
+}
+
+/// Parses `KEY=VALUE` lines as found in .env files. Blank lines, `#` comments and
+/// a leading `export` are ignored; matching quotes around the value are removed.
+pub fn parse_env_file(content: &str) -> Vec<(String, String)> {
+	content
+		.lines()
+		.map(str::trim)
+		.filter(|line| !line.is_empty() && !line.starts_with('#'))
+		.filter_map(|line| {
+			let line = line.strip_prefix("export ").unwrap_or(line);
+			let (key, value) = line.split_once('=')?;
+			let value = value.trim();
+			let unquoted = ['"', '\''].iter().find_map(|q| value.strip_prefix(*q)?.strip_suffix(*q));
+			Some((key.trim().to_string(), unquoted.unwrap_or(value).to_string()))
+		})
+		.collect()
+}
+
+pub fn expand_tilde(path: &str) -> PathBuf {
 	if let Some(rest) = path.strip_prefix("~/") {
 		if let Ok(home) = std::env::var("HOME") {
 			return PathBuf::from(home).join(rest);
@@ -325,3 +905,316 @@ fn expand_tilde(path: &str) -> PathBuf {
 	}
 	PathBuf::from(path)
 }
+
+/// Expands `~/` at the start of a word and `${VAR}` using `lookup`. Unknown
+/// variables are left for the shell to deal with.
+fn interpolate(value: &str, lookup: &dyn Fn(&str) -> Option<String>) -> String {
+	let home = std::env::var("HOME").ok();
+	let mut out = String::with_capacity(value.len());
+	let mut rest = value;
+	let mut word_start = true;
+	while let Some(c) = rest.chars().next() {
+		if c == '~' && word_start && (rest.len() == 1 || rest[1..].starts_with('/')) {
+			if let Some(ref home) = home {
+				out.push_str(home);
+				rest = &rest[1..];
+				word_start = false;
+				continue;
+			}
+		}
+		if let Some(after) = rest.strip_prefix("${") {
+			if let Some(end) = after.find('}') {
+				if let Some(val) = lookup(&after[..end]) {
+					out.push_str(&val);
+					rest = &after[end + 1..];
+					word_start = false;
+					continue;
+				}
+			}
+		}
+		out.push(c);
+		word_start = c.is_whitespace() || c == '=' || c == ':';
+		rest = &rest[c.len_utf8()..];
+	}
+	out
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const PROJECTS: &str = "\
+# my projects
+web = \"~/dev/web\"   # main site
+
+# backend
+api = \"~/dev/api\"
+
+# a standalone command
+[tunnel]
+run = \"ssh -N myserver\" # keep open
+";
+
+	#[test]
+	fn test_defaults_env_file_merges_under_env() {
+		let path = std::env::temp_dir().join(format!("ubermind-env-file-test-{}", std::process::id()));
+		std::fs::write(&path, "AWS_REGION=eu-west-1\nFORCE_COLOR=0\n").unwrap();
+		let mut defaults = DefaultsConfig { env_file: Some(path.to_string_lossy().to_string()), ..Default::default() };
+		load_defaults_env_file(&mut defaults);
+		let _ = std::fs::remove_file(&path);
+		assert_eq!(defaults.env["AWS_REGION"], "eu-west-1");
+		assert_eq!(defaults.env["FORCE_COLOR"], "1");
+	}
+
+	#[test]
+	fn test_parse_env_file() {
+		let vars = parse_env_file("# comment\n\nA=1\nexport B = two words\nC=\"quoted = yes\"\nD='x'\nnot a pair\n");
+		assert_eq!(
+			vars,
+			vec![
+				("A".to_string(), "1".to_string()),
+				("B".to_string(), "two words".to_string()),
+				("C".to_string(), "quoted = yes".to_string()),
+				("D".to_string(), "x".to_string()),
+			]
+		);
+	}
+
+	#[test]
+	fn test_add_remove_preserves_comments() {
+		let added = add_project(PROJECTS, "docs", "/home/me/docs").unwrap();
+		assert!(added.contains("docs = \"/home/me/docs\""));
+		assert!(added.find("docs =").unwrap() < added.find("[tunnel]").unwrap());
+		assert!(add_project(&added, "docs", "/elsewhere").is_err());
+
+		let removed = remove_project(&added, "docs").unwrap();
+		assert_eq!(removed, PROJECTS);
+	}
+
+	#[test]
+	fn test_remove_table_entry() {
+		let removed = remove_project(PROJECTS, "tunnel").unwrap();
+		assert!(!removed.contains("[tunnel]"));
+		assert!(removed.contains("# backend\napi = \"~/dev/api\""));
+		assert!(remove_project(PROJECTS, "missing").is_err());
+	}
+
+	#[test]
+	fn test_rename_keeps_position_and_comments() {
+		let renamed = rename_project(PROJECTS, "web", "site").unwrap();
+		assert_eq!(renamed, PROJECTS.replace("web = ", "site = "));
+		assert!(rename_project(PROJECTS, "web", "api").is_err());
+	}
+
+	#[test]
+	fn test_restart_policy_falls_back_to_defaults() {
+		let defaults = DefaultsConfig {
+			restart: false,
+			max_retries: 7,
+			restart_delay: 2,
+			restart_jitter: 0.25,
+			restart_backoff: 2.0,
+			max_retries_window: Some(60),
+			healthy_after: Some(30),
+			..Default::default()
+		};
+
+		let unset: ServiceDef = toml::from_str("run = \"./web\"\n").unwrap();
+		let proc = unset.into_process_def("web".to_string(), &defaults);
+		assert!(!proc.restart);
+		assert_eq!(proc.max_retries, 7);
+		assert_eq!(proc.restart_delay_secs, 2);
+		assert_eq!(proc.restart_jitter, 0.25);
+		assert_eq!(proc.restart_backoff, 2.0);
+		assert_eq!(proc.max_retries_window_secs, Some(60));
+		assert_eq!(proc.healthy_after_secs, Some(30));
+
+		let set: ServiceDef = toml::from_str(
+			"run = \"./web\"\nrestart = true\nmax_retries = 1\nrestart_delay = 5\nrestart_jitter = 0.5\n\
+			 restart_backoff = 1.5\nmax_retries_window = 10\nhealthy_after = 0\n",
+		)
+		.unwrap();
+		let proc = set.into_process_def("web".to_string(), &defaults);
+		assert!(proc.restart);
+		assert_eq!(proc.max_retries, 1);
+		assert_eq!(proc.restart_delay_secs, 5);
+		assert_eq!(proc.restart_jitter, 0.5);
+		assert_eq!(proc.restart_backoff, 1.5);
+		assert_eq!(proc.max_retries_window_secs, Some(10));
+		// 0 turns off a default
+		assert_eq!(proc.healthy_after_secs, None);
+
+		let secs: ServiceDef = toml::from_str("run = \"./web\"\nmax_retries_window_secs = 20\n").unwrap();
+		assert_eq!(secs.into_process_def("web".to_string(), &defaults).max_retries_window_secs, Some(20));
+		let secs: DefaultsConfig = toml::from_str("max_retries_window_secs = 45\n").unwrap();
+		assert_eq!(secs.max_retries_window, Some(45));
+
+		let invalid: ServiceDef = toml::from_str("run = \"./web\"\nrestart_backoff = 0.5\n").unwrap();
+		assert_eq!(invalid.into_process_def("web".to_string(), &defaults).restart_backoff, 2.0);
+
+		let simple = ServiceDef::Simple("./web".to_string()).into_process_def("web".to_string(), &defaults);
+		assert_eq!((simple.restart_backoff, simple.healthy_after_secs), (2.0, Some(30)));
+	}
+
+	#[test]
+	fn test_stop_timeout_sets_the_wait_before_sigkill() {
+		let defaults = DefaultsConfig::default();
+		let def: ServiceDef = toml::from_str("run = \"./db\"\nstop_timeout = 30\n").unwrap();
+		let proc = def.into_process_def("db".to_string(), &defaults);
+		assert_eq!(proc.stop_signals, vec![("SIGTERM".to_string(), 30), ("SIGKILL".to_string(), 0)]);
+
+		let def: ServiceDef = toml::from_str("run = \"./db\"\n").unwrap();
+		assert_eq!(def.into_process_def("db".to_string(), &defaults).stop_signals, default_stop_signals());
+
+		// An explicit sequence wins
+		let def: ServiceDef = toml::from_str("run = \"./db\"\nstop_timeout = 30\nstop_signals = [[\"INT\", 5]]\n").unwrap();
+		let proc = def.into_process_def("db".to_string(), &defaults);
+		assert_eq!(proc.stop_signals, vec![("SIGINT".to_string(), 5)]);
+	}
+
+	#[test]
+	fn test_stop_signal_replaces_sigterm() {
+		let defaults = DefaultsConfig::default();
+		let def: ServiceDef = toml::from_str("run = \"nginx\"\nstop_signal = \"QUIT\"\nstop_timeout = 10\n").unwrap();
+		let proc = def.into_process_def("nginx".to_string(), &defaults);
+		assert_eq!(proc.stop_signals, vec![("SIGQUIT".to_string(), 10), ("SIGKILL".to_string(), 0)]);
+
+		let def: ServiceDef = toml::from_str("run = \"nginx\"\nstop_signal = \"SIGNOPE\"\n").unwrap();
+		assert_eq!(def.into_process_def("nginx".to_string(), &defaults).stop_signals, default_stop_signals());
+	}
+
+	#[test]
+	fn test_largest_log_cap_applies_to_the_service() {
+		let defaults = DefaultsConfig::default();
+		let process = |name: &str, toml: &str| {
+			let def: ServiceDef = toml::from_str(toml).unwrap();
+			def.into_process_def(name.to_string(), &defaults)
+		};
+		let mut service = Service {
+			name: "app".to_string(),
+			dir: PathBuf::from("/tmp"),
+			processes: vec![
+				process("web", "run = \"./web\"\nmax_log_total_bytes = 1000\n"),
+				process("worker", "run = \"./worker\"\nmax_log_total_bytes = 5000\n"),
+				process("cron", "run = \"./cron\"\n"),
+			],
+		};
+		assert_eq!(service.max_log_total_bytes(), Some(5000));
+
+		service.processes.retain(|p| p.name == "cron");
+		assert_eq!(service.max_log_total_bytes(), None);
+	}
+
+	#[test]
+	fn test_task_ignores_restart() {
+		assert!(task_restart_warning("migrate", true, Some(true)).is_some());
+		assert!(task_restart_warning("migrate", true, None).is_none());
+		assert!(task_restart_warning("web", false, Some(true)).is_none());
+
+		let def: ServiceDef = toml::from_str("run = \"./migrate\"\ntype = \"task\"\nrestart = true\n").unwrap();
+		let proc = def.into_process_def("migrate".to_string(), &DefaultsConfig::default());
+		assert!(!proc.restart);
+	}
+
+	#[test]
+	fn test_os_filter() {
+		let def: ServiceDef = toml::from_str("run = \"caffeinate\"\nos = \"macos\"\n").unwrap();
+		assert!(def.runs_on("macos"));
+		assert!(!def.runs_on("linux"));
+
+		let def: ServiceDef = toml::from_str("run = \"x\"\nos = [\"linux\", \"macOS\"]\n").unwrap();
+		assert!(def.runs_on("macos") && def.runs_on("linux"));
+		assert!(ServiceDef::Simple("x".to_string()).runs_on("linux"));
+	}
+
+	#[test]
+	fn test_count_expands_template() {
+		let def: ServiceDef =
+			toml::from_str("run = \"worker --shard ${index}\"\ncount = 2\nenv = { SHARD = \"${index}\" }\n").unwrap();
+		let procs: Vec<ProcessDef> = def
+			.expand("worker".to_string())
+			.into_iter()
+			.map(|(name, def)| def.into_process_def(name, &DefaultsConfig::default()))
+			.collect();
+		assert_eq!(procs.len(), 2);
+		assert_eq!(procs[1].name, "worker-1");
+		assert_eq!(procs[1].command, "worker --shard 1");
+		assert_eq!(procs[1].env["SHARD"], "1");
+	}
+
+	#[test]
+	fn test_schema_covers_config_files() {
+		let services = schema("services").unwrap();
+		assert_eq!(services["$defs"]["ServiceType"]["enum"], serde_json::json!(["service", "task"]));
+		assert!(schema("projects").is_some());
+		assert!(schema("config").unwrap()["properties"]["daemon"].is_object());
+		assert!(schema("procfile").is_none());
+	}
+
+	#[test]
+	fn test_interpolate_inline_command() {
+		let lookup = |var: &str| (var == "REMOTE_HOST").then(|| "db.example.com".to_string());
+		assert_eq!(
+			interpolate("ssh -N -L 5432:localhost:5432 ${REMOTE_HOST}", &lookup),
+			"ssh -N -L 5432:localhost:5432 db.example.com"
+		);
+		assert_eq!(interpolate("echo ${UNSET} a~b", &lookup), "echo ${UNSET} a~b");
+		if let Ok(home) = std::env::var("HOME") {
+			assert_eq!(interpolate("~/bin/tool --dir=~/x", &lookup), format!("{home}/bin/tool --dir={home}/x"));
+		}
+	}
+
+	#[test]
+	fn test_parse_umask() {
+		assert_eq!(parse_umask("0022"), Ok(0o022));
+		assert_eq!(parse_umask("0o027"), Ok(0o027));
+		assert!(parse_umask("0088").is_err());
+		assert!(parse_umask("1777").is_err());
+		assert!(parse_umask("").is_err());
+	}
+
+	fn load_from(files: &[(&str, &str)]) -> Vec<(String, String)> {
+		static COUNTER: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+		let n = COUNTER.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+		let dir = std::env::temp_dir().join(format!("ubermind-procfile-test-{}-{}", std::process::id(), n));
+		std::fs::create_dir_all(&dir).unwrap();
+		for (name, content) in files {
+			std::fs::write(dir.join(name), content).unwrap();
+		}
+		let entry = ServiceEntry { name: "app".to_string(), dir: dir.clone(), inline_command: None };
+		let service = load_service(&entry, &DefaultsConfig::default());
+		let _ = std::fs::remove_dir_all(&dir);
+		service.processes.into_iter().map(|p| (p.name, p.command)).collect()
+	}
+
+	#[test]
+	fn test_procfile_only() {
+		let procs = load_from(&[("Procfile", "# dev\nweb: npm run dev -- --port 3000\n\nworker:  node worker.js\nbroken line\n")]);
+		assert_eq!(
+			procs,
+			vec![
+				("web".to_string(), "npm run dev -- --port 3000".to_string()),
+				("worker".to_string(), "node worker.js".to_string()),
+			]
+		);
+	}
+
+	#[test]
+	fn test_services_toml_only() {
+		let procs = load_from(&[("services.toml", "web = \"npm run dev\"\n")]);
+		assert_eq!(procs, vec![("web".to_string(), "npm run dev".to_string())]);
+	}
+
+	#[test]
+	fn test_services_toml_wins_over_procfile() {
+		let procs = load_from(&[("services.toml", "api = \"cargo run\"\n"), ("Procfile", "web: npm run dev\n")]);
+		assert_eq!(procs, vec![("api".to_string(), "cargo run".to_string())]);
+	}
+
+	#[test]
+	fn test_pty_without_capture_is_rejected() {
+		let toml = "[shell]\nrun = \"bash\"\npty = true\ncapture = false\n\n[web]\nrun = \"npm run dev\"\npty = true\n";
+		let procs = load_from(&[("services.toml", toml)]);
+		assert_eq!(procs, vec![("web".to_string(), "npm run dev".to_string())]);
+	}
+}
@@ -1,16 +1,19 @@
-use crate::daemon::supervisor::Supervisor;
-use crate::types::{ProcessState, ServiceType};
+use crate::daemon::output::{Chunk, Stream};
+use crate::daemon::supervisor::{Supervisor, SupervisorError};
+use crate::protocol::ErrorCode;
+use crate::types::{Health, ProcessState, ServiceType, StopReason};
 use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
-use axum::extract::{Path, State};
-use axum::http::{header, StatusCode, Uri};
+use axum::extract::{Path, Query, State};
+use axum::http::{header, HeaderMap, HeaderValue, Method, StatusCode, Uri};
 use axum::response::{IntoResponse, Response};
 use axum::routing::{get, post};
 use axum::{Json, Router};
 use rust_embed::RustEmbed;
-use serde::Serialize;
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, VecDeque};
 use std::sync::Arc;
-use tower_http::cors::CorsLayer;
+use tower_http::compression::CompressionLayer;
+use tower_http::cors::{Any, CorsLayer};
 
 #[derive(RustEmbed)]
 #[folder = "../../ui/build/"]
@@ -22,11 +25,14 @@ pub struct AppState {
 }
 
 pub fn router(supervisor: Arc<Supervisor>) -> Router {
+	let cors = cors_layer(&supervisor.config.daemon.cors_origins);
 	let state = AppState { supervisor };
 
 	Router::new()
+		.route("/api/version", get(version))
 		.route("/api/services", get(list_services))
 		.route("/api/services/{name}", get(service_detail))
+		.route("/api/batch", post(batch))
 		.route("/api/services/{name}/start", post(start_service))
 		.route("/api/services/{name}/stop", post(stop_service))
 		.route("/api/services/{name}/reload", post(reload_service))
@@ -39,12 +45,38 @@ pub fn router(supervisor: Arc<Supervisor>) -> Router {
 			post(kill_process),
 		)
 		.route("/api/services/{name}/echo", get(echo_service))
-		.route("/ws/echo/{name}", get(ws_echo))
+		.route("/api/services/{name}/snapshot", get(snapshot_service))
 		.fallback(static_handler)
-		.layer(CorsLayer::permissive())
+		.layer(CompressionLayer::new())
+		// Added after the compression layer so websocket upgrades are left alone.
+		.route("/ws/echo/{name}", get(ws_echo))
+		.route("/ws/events", get(ws_events))
+		.route("/ws/logs", get(ws_logs))
+		.layer(cors)
 		.with_state(state)
 }
 
+/// Allows any origin unless `daemon.cors_origins` lists specific ones.
+fn cors_layer(origins: &[String]) -> CorsLayer {
+	if origins.is_empty() {
+		return CorsLayer::permissive();
+	}
+	let origins: Vec<HeaderValue> = origins
+		.iter()
+		.filter_map(|o| match o.parse() {
+			Ok(v) => Some(v),
+			Err(_) => {
+				tracing::warn!("ignoring invalid cors origin: {}", o);
+				None
+			}
+		})
+		.collect();
+	CorsLayer::new()
+		.allow_origin(origins)
+		.allow_methods([Method::GET, Method::POST])
+		.allow_headers(Any)
+}
+
 #[derive(Serialize)]
 struct ServiceInfo {
 	name: String,
@@ -69,6 +101,57 @@ struct ProcessInfo {
 	#[serde(rename = "type")]
 	service_type: String,
 	ports: Vec<u16>,
+	restart_count: u32,
+	last_restart: Option<u64>,
+	health: Option<Health>,
+}
+
+#[derive(Serialize)]
+struct VersionInfo {
+	version: &'static str,
+	started_at: u64,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+enum BatchAction {
+	Start,
+	Stop,
+	Reload,
+	RestartAll,
+}
+
+#[derive(Deserialize)]
+struct BatchRequest {
+	action: BatchAction,
+	services: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct BatchResult {
+	service: String,
+	ok: bool,
+	message: String,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	code: Option<ErrorCode>,
+}
+
+#[derive(Deserialize)]
+struct EchoQuery {
+	/// Prefix each frame with its stream: 0 for stdout, 1 for stderr.
+	#[serde(default)]
+	tagged: bool,
+}
+
+#[derive(Deserialize)]
+struct LogsQuery {
+	/// Only this service's processes.
+	service: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct SnapshotQuery {
+	bytes: Option<usize>,
 }
 
 #[derive(Serialize)]
@@ -79,6 +162,46 @@ struct ActionResponse {
 #[derive(Serialize)]
 struct ErrorResponse {
 	error: String,
+	code: ErrorCode,
+}
+
+/// A failed request, sent as `{ "error": message, "code": code }`. Codes are
+/// the socket protocol's `ErrorCode`s, which the UI can branch on; the
+/// message is for people.
+struct ApiError {
+	status: StatusCode,
+	code: ErrorCode,
+	message: String,
+}
+
+/// Gives each of the supervisor's error codes its HTTP status, so every
+/// handler reports the same failure the same way.
+impl From<SupervisorError> for ApiError {
+	fn from(e: SupervisorError) -> Self {
+		let code = e.code();
+		let status = match code {
+			ErrorCode::UnknownService | ErrorCode::ProcessNotFound => StatusCode::NOT_FOUND,
+			ErrorCode::NotRunning | ErrorCode::AlreadyRunning | ErrorCode::PortInUse => StatusCode::CONFLICT,
+			ErrorCode::NotReady => StatusCode::SERVICE_UNAVAILABLE,
+			ErrorCode::InvalidConfig | ErrorCode::SecretUnavailable => StatusCode::UNPROCESSABLE_ENTITY,
+			ErrorCode::BadRequest => StatusCode::BAD_REQUEST,
+			ErrorCode::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+		};
+		Self { status, code, message: e.to_string() }
+	}
+}
+
+impl IntoResponse for ApiError {
+	fn into_response(self) -> Response {
+		(self.status, Json(ErrorResponse { error: self.message, code: self.code })).into_response()
+	}
+}
+
+async fn version(State(state): State<AppState>) -> Json<VersionInfo> {
+	Json(VersionInfo {
+		version: env!("CARGO_PKG_VERSION"),
+		started_at: state.supervisor.started_at,
+	})
 }
 
 async fn list_services(State(state): State<AppState>) -> Json<Vec<ServiceInfo>> {
@@ -97,19 +220,12 @@ async fn list_services(State(state): State<AppState>) -> Json<Vec<ServiceInfo>>
 async fn service_detail(
 	State(state): State<AppState>,
 	Path(name): Path<String>,
-) -> Result<Json<ServiceDetail>, (StatusCode, Json<ErrorResponse>)> {
+) -> Result<Json<ServiceDetail>, ApiError> {
 	let statuses = state.supervisor.status().await;
 	let status = statuses
 		.into_iter()
 		.find(|s| s.name == name)
-		.ok_or_else(|| {
-			(
-				StatusCode::NOT_FOUND,
-				Json(ErrorResponse {
-					error: format!("service not found: {}", name),
-				}),
-			)
-		})?;
+		.ok_or_else(|| ApiError::from(SupervisorError::UnknownService(name.clone())))?;
 
 	let running = status.is_running();
 	let processes = status
@@ -120,12 +236,19 @@ async fn service_detail(
 				ProcessState::Running { pid, uptime_secs } => {
 					format!("running (pid {}, {}s)", pid, uptime_secs)
 				}
-				ProcessState::Stopped => "stopped".to_string(),
+				ProcessState::Stopped { reason } => match reason {
+					StopReason::NotStarted => "stopped".to_string(),
+					StopReason::Manual => "stopped (manual)".to_string(),
+					StopReason::CleanExit => "stopped (exited)".to_string(),
+				},
 				ProcessState::Crashed { exit_code, retries } => {
 					format!("crashed (exit {}, retry {})", exit_code, retries)
 				}
-				ProcessState::Failed { exit_code } => {
-					format!("failed (exit {})", exit_code)
+				ProcessState::Failed { exit_code, finished_at } => {
+					format!("failed (exit {}, at {})", exit_code, finished_at)
+				}
+				ProcessState::Completed { exit_code, finished_at } => {
+					format!("completed (exit {}, at {})", exit_code, finished_at)
 				}
 			};
 			ProcessInfo {
@@ -138,6 +261,9 @@ async fn service_detail(
 					ServiceType::Service => "service".to_string(),
 				},
 				ports: p.ports,
+				restart_count: p.restart_count,
+				last_restart: p.last_restart,
+				health: p.health,
 			}
 		})
 		.collect();
@@ -154,99 +280,111 @@ async fn start_service(
 	State(state): State<AppState>,
 	Path(name): Path<String>,
 	axum::extract::Query(params): axum::extract::Query<HashMap<String, String>>,
-) -> Result<Json<ActionResponse>, (StatusCode, Json<ErrorResponse>)> {
+) -> Result<Json<ActionResponse>, ApiError> {
 	let all = params.get("all").map(|v| v == "true" || v == "1").unwrap_or(false);
 	state
 		.supervisor
 		.start_service_filtered(&name, all, &[])
 		.await
 		.map(|msg| Json(ActionResponse { message: msg }))
-		.map_err(|e| {
-			(
-				StatusCode::BAD_REQUEST,
-				Json(ErrorResponse { error: e }),
-			)
-		})
+		.map_err(ApiError::from)
 }
 
 async fn stop_service(
 	State(state): State<AppState>,
 	Path(name): Path<String>,
-) -> Result<Json<ActionResponse>, (StatusCode, Json<ErrorResponse>)> {
+) -> Result<Json<ActionResponse>, ApiError> {
 	state
 		.supervisor
-		.stop_service(&name)
+		.stop_service(&name, None)
 		.await
 		.map(|msg| Json(ActionResponse { message: msg }))
-		.map_err(|e| {
-			(
-				StatusCode::BAD_REQUEST,
-				Json(ErrorResponse { error: e }),
-			)
-		})
+		.map_err(ApiError::from)
 }
 
 async fn reload_service(
 	State(state): State<AppState>,
 	Path(name): Path<String>,
-) -> Result<Json<ActionResponse>, (StatusCode, Json<ErrorResponse>)> {
+) -> Result<Json<ActionResponse>, ApiError> {
 	state
 		.supervisor
-		.reload_service_filtered(&name, false, &[])
+		.reload_service_filtered(&name, false, &[], None)
 		.await
 		.map(|msg| Json(ActionResponse { message: msg }))
-		.map_err(|e| {
-			(
-				StatusCode::BAD_REQUEST,
-				Json(ErrorResponse { error: e }),
-			)
+		.map_err(ApiError::from)
+}
+
+/// Applies one action to several services concurrently, reporting each outcome.
+async fn batch(State(state): State<AppState>, Json(req): Json<BatchRequest>) -> Json<Vec<BatchResult>> {
+	let handles: Vec<_> = req
+		.services
+		.into_iter()
+		.map(|name| {
+			let sup = Arc::clone(&state.supervisor);
+			let action = req.action;
+			let service = name.clone();
+			let handle = tokio::spawn(async move {
+				let result = match action {
+					BatchAction::Start => sup.start_service_filtered(&name, false, &[]).await,
+					BatchAction::Stop => sup.stop_service(&name, None).await,
+					BatchAction::Reload => sup.reload_service_filtered(&name, false, &[], None).await,
+					BatchAction::RestartAll => sup.reload_service_filtered(&name, true, &[], None).await,
+				};
+				match result {
+					Ok(message) => BatchResult { service: name, ok: true, message, code: None },
+					Err(e) => {
+						let e = ApiError::from(e);
+						BatchResult { service: name, ok: false, message: e.message, code: Some(e.code) }
+					}
+				}
+			});
+			(service, handle)
 		})
+		.collect();
+
+	let mut results = Vec::with_capacity(handles.len());
+	for (service, handle) in handles {
+		results.push(handle.await.unwrap_or_else(|e| BatchResult {
+			service,
+			ok: false,
+			message: e.to_string(),
+			code: Some(ErrorCode::Internal),
+		}));
+	}
+	Json(results)
 }
 
 async fn restart_process(
 	State(state): State<AppState>,
 	Path((name, process)): Path<(String, String)>,
-) -> Result<Json<ActionResponse>, (StatusCode, Json<ErrorResponse>)> {
+) -> Result<Json<ActionResponse>, ApiError> {
 	state
 		.supervisor
 		.restart_process(&name, &process)
 		.await
 		.map(|msg| Json(ActionResponse { message: msg }))
-		.map_err(|e| {
-			(
-				StatusCode::BAD_REQUEST,
-				Json(ErrorResponse { error: e }),
-			)
-		})
+		.map_err(ApiError::from)
 }
 
 async fn kill_process(
 	State(state): State<AppState>,
 	Path((name, process)): Path<(String, String)>,
-) -> Result<Json<ActionResponse>, (StatusCode, Json<ErrorResponse>)> {
+) -> Result<Json<ActionResponse>, ApiError> {
 	state
 		.supervisor
 		.kill_process(&name, &process)
 		.await
 		.map(|msg| Json(ActionResponse { message: msg }))
-		.map_err(|e| {
-			(
-				StatusCode::BAD_REQUEST,
-				Json(ErrorResponse { error: e }),
-			)
-		})
+		.map_err(ApiError::from)
 }
 
+/// Every process's full scrollback as one plain-text blob. Kept for older
+/// clients; prefer `/snapshot`, which is per process and size-bounded.
 async fn echo_service(
 	State(state): State<AppState>,
 	Path(name): Path<String>,
-) -> Result<String, (StatusCode, Json<ErrorResponse>)> {
-	let outputs = state.supervisor.get_all_outputs(&name).await.map_err(|e| {
-		(
-			StatusCode::NOT_FOUND,
-			Json(ErrorResponse { error: e }),
-		)
-	})?;
+) -> Result<String, ApiError> {
+	let outputs = state.supervisor.get_all_outputs(&name).await.map_err(ApiError::from)?;
 
 	let mut result = String::new();
 	for (proc_name, capture) in outputs {
@@ -259,15 +397,47 @@ async fn echo_service(
 	Ok(result)
 }
 
+const SNAPSHOT_DEFAULT_BYTES: usize = 64 * 1024;
+
+/// `GET /api/services/{name}/snapshot?bytes=N`: process name → the last N bytes
+/// of its scrollback (64 KiB by default), cut on a character boundary.
+async fn snapshot_service(
+	State(state): State<AppState>,
+	Path(name): Path<String>,
+	Query(query): Query<SnapshotQuery>,
+) -> Result<Json<BTreeMap<String, String>>, ApiError> {
+	let outputs = state.supervisor.get_all_outputs(&name).await.map_err(ApiError::from)?;
+
+	let limit = query.bytes.unwrap_or(SNAPSHOT_DEFAULT_BYTES);
+	let mut result = BTreeMap::new();
+	for (proc_name, capture) in outputs {
+		let snapshot = capture.snapshot().await;
+		let tail = utf8_tail(&snapshot, limit);
+		result.insert(proc_name, String::from_utf8_lossy(tail).into_owned());
+	}
+	Ok(Json(result))
+}
+
+/// The last `limit` bytes of `data`, moved forward past any partial UTF-8
+/// sequence so the cut never splits a character.
+fn utf8_tail(data: &[u8], limit: usize) -> &[u8] {
+	let mut start = data.len().saturating_sub(limit);
+	while start < data.len() && data[start] & 0xC0 == 0x80 {
+		start += 1;
+	}
+	&data[start..]
+}
+
 async fn ws_echo(
 	State(state): State<AppState>,
 	Path(name): Path<String>,
+	Query(query): Query<EchoQuery>,
 	ws: WebSocketUpgrade,
 ) -> impl IntoResponse {
-	ws.on_upgrade(move |socket| handle_ws_echo(socket, state, name))
+	ws.on_upgrade(move |socket| handle_ws_echo(socket, state, name, query.tagged))
 }
 
-async fn handle_ws_echo(mut socket: WebSocket, state: AppState, name: String) {
+async fn handle_ws_echo(mut socket: WebSocket, state: AppState, name: String, tagged: bool) {
 	let outputs = match state.supervisor.get_all_outputs(&name).await {
 		Ok(o) => o,
 		Err(_) => {
@@ -281,44 +451,236 @@ async fn handle_ws_echo(mut socket: WebSocket, state: AppState, name: String) {
 			let header = format!("\x1b[1m--- {} ---\x1b[0m\r\n", proc_name);
 			let mut data = header.into_bytes();
 			data.extend_from_slice(&snapshot);
-			let _ = socket.send(Message::Binary(data.into())).await;
+			// Scrollback is stored merged, so it goes out as stdout
+			for frame in echo_frames(vec![(Stream::Stdout, data)], tagged) {
+				let _ = socket.send(Message::Binary(frame.into())).await;
+			}
 		}
 	}
 
-	let mut receivers: Vec<(String, tokio::sync::broadcast::Receiver<Vec<u8>>)> = outputs
+	let mut receivers: Vec<(String, tokio::sync::broadcast::Receiver<Chunk>)> = outputs
 		.iter()
 		.map(|(name, capture)| (name.clone(), capture.subscribe()))
 		.collect();
 
+	let keepalive = keepalive_interval(&state);
+	let mut last_sent = std::time::Instant::now();
+	let mut pending = EchoBuffer::default();
 	loop {
-		let mut any = false;
+		// Everything that arrived while the last send was in flight goes out as one frame
 		for (_proc_name, rx) in &mut receivers {
-			match rx.try_recv() {
-				Ok(data) => {
-					any = true;
-					let _ = socket.send(Message::Binary(data.into())).await;
+			loop {
+				match rx.try_recv() {
+					Ok(chunk) => pending.push(chunk.stream, &chunk.data),
+					Err(tokio::sync::broadcast::error::TryRecvError::Lagged(_)) => pending.mark_gap(),
+					Err(_) => break,
 				}
-				Err(tokio::sync::broadcast::error::TryRecvError::Lagged(_)) => {}
-				Err(tokio::sync::broadcast::error::TryRecvError::Empty) => {}
-				Err(tokio::sync::broadcast::error::TryRecvError::Closed) => {}
 			}
 		}
-		if !any {
+		if let Some(segments) = pending.take() {
+			for frame in echo_frames(segments, tagged) {
+				if socket.send(Message::Binary(frame.into())).await.is_err() {
+					return;
+				}
+			}
+			last_sent = std::time::Instant::now();
+		} else {
+			if keepalive.is_some_and(|every| last_sent.elapsed() >= every) {
+				if socket.send(Message::Ping(Vec::new().into())).await.is_err() {
+					return;
+				}
+				last_sent = std::time::Instant::now();
+			}
 			tokio::time::sleep(std::time::Duration::from_millis(50)).await;
 		}
 	}
 }
 
-async fn static_handler(uri: Uri) -> impl IntoResponse {
+/// Most a slow echo client can fall behind before older output is dropped.
+const ECHO_MAX_PENDING: usize = 256 * 1024;
+const ECHO_GAP_MARKER: &[u8] = "\r\n\x1b[2m[…]\x1b[0m\r\n".as_bytes();
+
+/// Output waiting to be sent to an echo websocket, as runs of the same stream.
+/// It is bounded: past `ECHO_MAX_PENDING` the oldest bytes are dropped and a
+/// gap marker is shown.
+#[derive(Default)]
+struct EchoBuffer {
+	segments: VecDeque<(Stream, Vec<u8>)>,
+	len: usize,
+	gap: bool,
+}
+
+impl EchoBuffer {
+	fn push(&mut self, stream: Stream, chunk: &[u8]) {
+		match self.segments.back_mut() {
+			Some((last, data)) if *last == stream => data.extend_from_slice(chunk),
+			_ => self.segments.push_back((stream, chunk.to_vec())),
+		}
+		self.len += chunk.len();
+		while self.len > ECHO_MAX_PENDING {
+			let excess = self.len - ECHO_MAX_PENDING;
+			let Some((_, front)) = self.segments.front_mut() else { break };
+			if front.len() <= excess {
+				self.len -= front.len();
+				self.segments.pop_front();
+			} else {
+				front.drain(..excess);
+				self.len -= excess;
+			}
+			self.gap = true;
+		}
+	}
+
+	/// Output was lost upstream (the broadcast channel lagged).
+	fn mark_gap(&mut self) {
+		self.gap = true;
+	}
+
+	fn take(&mut self) -> Option<Vec<(Stream, Vec<u8>)>> {
+		if self.segments.is_empty() && !self.gap {
+			return None;
+		}
+		if std::mem::take(&mut self.gap) {
+			match self.segments.front_mut() {
+				Some((Stream::Stdout, data)) => {
+					data.splice(0..0, ECHO_GAP_MARKER.iter().copied());
+				}
+				_ => self.segments.push_front((Stream::Stdout, ECHO_GAP_MARKER.to_vec())),
+			}
+		}
+		self.len = 0;
+		Some(self.segments.drain(..).collect())
+	}
+}
+
+/// Websocket frames for pending output. Untagged clients get everything merged
+/// into one frame; tagged clients get one frame per run, led by a stream byte.
+fn echo_frames(segments: Vec<(Stream, Vec<u8>)>, tagged: bool) -> Vec<Vec<u8>> {
+	if !tagged {
+		return vec![segments.into_iter().flat_map(|(_, data)| data).collect()];
+	}
+	segments
+		.into_iter()
+		.map(|(stream, data)| {
+			let mut frame = Vec::with_capacity(data.len() + 1);
+			frame.push(match stream {
+				Stream::Stdout => 0,
+				Stream::Stderr => 1,
+			});
+			frame.extend_from_slice(&data);
+			frame
+		})
+		.collect()
+}
+
+/// `daemon.keepalive_secs` as a duration, if enabled. Clients answer pings
+/// automatically, so idle streams stay visibly alive without extra frames.
+fn keepalive_interval(state: &AppState) -> Option<std::time::Duration> {
+	state.supervisor.config.daemon.keepalive_interval()
+}
+
+async fn ws_events(State(state): State<AppState>, ws: WebSocketUpgrade) -> impl IntoResponse {
+	ws.on_upgrade(move |socket| handle_ws_events(socket, state))
+}
+
+/// Pushes a JSON frame per process state transition until the client goes away.
+async fn handle_ws_events(mut socket: WebSocket, state: AppState) {
+	let mut events = state.supervisor.events.subscribe();
+	let keepalive = keepalive_interval(&state);
+	loop {
+		let idle = async {
+			match keepalive {
+				Some(every) => tokio::time::sleep(every).await,
+				None => std::future::pending().await,
+			}
+		};
+		tokio::select! {
+			_ = idle => {
+				if socket.send(Message::Ping(Vec::new().into())).await.is_err() {
+					return;
+				}
+			}
+			event = events.recv() => match event {
+				Ok(change) => {
+					let frame = serde_json::to_string(&change).unwrap_or_default();
+					if socket.send(Message::Text(frame.into())).await.is_err() {
+						return;
+					}
+				}
+				Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => {}
+				Err(tokio::sync::broadcast::error::RecvError::Closed) => return,
+			},
+			msg = socket.recv() => match msg {
+				Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return,
+				Some(Ok(_)) => {}
+			},
+		}
+	}
+}
+
+async fn ws_logs(State(state): State<AppState>, Query(query): Query<LogsQuery>, ws: WebSocketUpgrade) -> impl IntoResponse {
+	ws.on_upgrade(move |socket| handle_ws_logs(socket, state, query.service))
+}
+
+#[derive(Serialize)]
+struct LogFrame {
+	service: String,
+	process: String,
+	line: String,
+}
+
+/// Pushes a JSON frame per chunk of output from every process (or every process
+/// of `service`), tagged with where it came from, until the client goes away.
+async fn handle_ws_logs(mut socket: WebSocket, state: AppState, service: Option<String>) {
+	let mut logs = super::follow_all_logs(Arc::clone(&state.supervisor), service);
+	let keepalive = keepalive_interval(&state);
+	loop {
+		let idle = async {
+			match keepalive {
+				Some(every) => tokio::time::sleep(every).await,
+				None => std::future::pending().await,
+			}
+		};
+		tokio::select! {
+			_ = idle => {
+				if socket.send(Message::Ping(Vec::new().into())).await.is_err() {
+					return;
+				}
+			}
+			log = logs.recv() => match log {
+				Some(crate::protocol::Response::Log { line, process, service }) => {
+					let frame = LogFrame {
+						service: service.unwrap_or_default(),
+						process: process.unwrap_or_default(),
+						line,
+					};
+					let frame = serde_json::to_string(&frame).unwrap_or_default();
+					if socket.send(Message::Text(frame.into())).await.is_err() {
+						return;
+					}
+				}
+				Some(_) => {}
+				None => return,
+			},
+			msg = socket.recv() => match msg {
+				Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return,
+				Some(Ok(_)) => {}
+			},
+		}
+	}
+}
+
+async fn static_handler(uri: Uri, headers: HeaderMap) -> impl IntoResponse {
 	let path = uri.path().trim_start_matches('/');
+	let if_none_match = headers.get(header::IF_NONE_MATCH).and_then(|v| v.to_str().ok());
 
 	if let Some(content) = UiAssets::get(path) {
-		return serve_asset(path, content);
+		return serve_asset(path, content, if_none_match);
 	}
 
 	if !path.starts_with("_app/") && !path.contains('.') {
 		if let Some(content) = UiAssets::get("index.html") {
-			return serve_asset("index.html", content);
+			return serve_asset("index.html", content, if_none_match);
 		}
 	}
 
@@ -328,12 +690,60 @@ async fn static_handler(uri: Uri) -> impl IntoResponse {
 		.unwrap()
 }
 
-fn serve_asset(path: &str, content: rust_embed::EmbeddedFile) -> Response {
+fn serve_asset(path: &str, content: rust_embed::EmbeddedFile, if_none_match: Option<&str>) -> Response {
 	let mime = mime_guess::from_path(path).first_or_octet_stream();
+	let etag = format!(
+		"\"{}\"",
+		content.metadata.sha256_hash().iter().map(|b| format!("{:02x}", b)).collect::<String>()
+	);
+	// Files under _app/ have content hashes in their names, so they never change.
+	let cache_control = if path.starts_with("_app/") {
+		"public, max-age=31536000, immutable"
+	} else {
+		"no-cache"
+	};
+
+	let not_modified = if_none_match.is_some_and(|v| v.split(',').any(|tag| tag.trim() == etag || tag.trim() == "*"));
+	if not_modified {
+		return Response::builder()
+			.status(StatusCode::NOT_MODIFIED)
+			.header(header::ETAG, &etag)
+			.header(header::CACHE_CONTROL, cache_control)
+			.body(axum::body::Body::empty())
+			.unwrap();
+	}
 
 	Response::builder()
 		.status(StatusCode::OK)
 		.header(header::CONTENT_TYPE, mime.as_ref())
+		.header(header::ETAG, &etag)
+		.header(header::CACHE_CONTROL, cache_control)
 		.body(content.data.into())
 		.unwrap()
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_api_error_codes() {
+		let code = |e: SupervisorError| {
+			let e = ApiError::from(e);
+			(e.status, e.code, e.message)
+		};
+		let unknown = code(SupervisorError::UnknownService("web".to_string()));
+		assert_eq!(unknown, (StatusCode::NOT_FOUND, ErrorCode::UnknownService, "unknown service: web".to_string()));
+		let missing = code(SupervisorError::ProcessNotFound { service: "demo".to_string(), process: "web".to_string() });
+		assert_eq!(missing, (StatusCode::NOT_FOUND, ErrorCode::ProcessNotFound, "demo/web: not found".to_string()));
+		let stopped = code(SupervisorError::NotRunning("demo".to_string()));
+		assert_eq!(stopped, (StatusCode::CONFLICT, ErrorCode::NotRunning, "demo: not running".to_string()));
+		assert_eq!(code(SupervisorError::AlreadyRunning("demo/web".to_string())).1, ErrorCode::AlreadyRunning);
+		assert_eq!(code(SupervisorError::PortInUse("demo.web: port 3000 already in use".to_string())).1, ErrorCode::PortInUse);
+		assert_eq!(code(SupervisorError::InvalidConfig("demo: not reloaded".to_string())).0, StatusCode::UNPROCESSABLE_ENTITY);
+		let other = code(SupervisorError::from("pid 12 is not a process group leader (its group is 1)".to_string()));
+		assert_eq!((other.0, other.1), (StatusCode::BAD_REQUEST, ErrorCode::BadRequest));
+		let body = serde_json::to_value(ErrorResponse { error: other.2, code: other.1 }).unwrap();
+		assert_eq!(body["code"], "bad_request");
+	}
+}
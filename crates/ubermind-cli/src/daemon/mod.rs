@@ -1,23 +1,40 @@
 pub mod api;
+pub mod once;
 pub mod output;
+pub mod resources;
 pub mod supervisor;
 
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
 use tokio::net::UnixListener;
+use tokio::signal::unix::{signal, SignalKind};
 use crate::config;
 use crate::protocol::{self, Request, Response};
 
 pub async fn run(args: &[String]) {
-	tracing_subscriber::fmt().init();
+	// RUST_LOG overrides, e.g. RUST_LOG=info,ubermind::socket=debug for per-request timings
+	let filter = tracing_subscriber::EnvFilter::try_from_default_env()
+		.unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("info"));
+	// Started in the background, output goes to the daemon log
+	let ansi = std::io::IsTerminal::is_terminal(&std::io::stdout());
+	tracing_subscriber::fmt().with_env_filter(filter).with_ansi(ansi).init();
 
 	let _foreground = args.iter().any(|a| a == "--foreground" || a == "-f");
 	let enable_http = args.iter().any(|a| a == "--http");
+	let attach = match parse_attach(args) {
+		Ok(attach) => attach,
+		Err(e) => {
+			eprintln!("error: {}", e);
+			std::process::exit(1);
+		}
+	};
 
 	let global_config = config::load_global_config();
 	let port = global_config.daemon.port;
-	let http_port = if enable_http { Some(port) } else { None };
-	let supervisor = supervisor::Supervisor::new(global_config.clone(), http_port);
+	let http_socket = global_config.daemon.http_unix_socket.as_deref().map(config::expand_tilde);
+	let http_port = if enable_http && http_socket.is_none() { Some(port) } else { None };
+	let supervisor = supervisor::Supervisor::new(global_config.clone(), http_port, attach);
 
 	let state_dir = protocol::state_dir();
 	let _ = std::fs::create_dir_all(&state_dir);
@@ -30,14 +47,36 @@ pub async fn run(args: &[String]) {
 		let _ = std::fs::remove_file(&socket_path);
 	}
 
-	output::expire_logs(global_config.logs.max_age_days, global_config.logs.max_files);
+	output::expire_logs(global_config.logs.max_age_days, global_config.logs.max_files, &log_caps(&global_config));
+
+	{
+		let config = global_config.clone();
+		tokio::task::spawn_blocking(move || warn_orphans(&config));
+	}
 
 	{
 		let config = global_config.clone();
 		tokio::spawn(async move {
 			loop {
 				tokio::time::sleep(std::time::Duration::from_secs(3600)).await;
-				output::expire_logs(config.logs.max_age_days, config.logs.max_files);
+				output::expire_logs(config.logs.max_age_days, config.logs.max_files, &log_caps(&config));
+			}
+		});
+	}
+
+	{
+		let supervisor = Arc::clone(&supervisor);
+		tokio::spawn(async move {
+			let mut last_open = None;
+			loop {
+				tokio::time::sleep(std::time::Duration::from_secs(60)).await;
+				close_idle_logs(&supervisor).await;
+				cap_daemon_log(supervisor.config.logs.max_size_bytes);
+				let open = output::open_log_files();
+				if last_open != Some(open) {
+					tracing::info!("{} log files open (fd limit {})", open, fd_limit().map_or("unknown".to_string(), |l| l.to_string()));
+					last_open = Some(open);
+				}
 			}
 		});
 	}
@@ -47,46 +86,176 @@ pub async fn run(args: &[String]) {
 		run_socket_server(sup_socket, &socket_path).await;
 	});
 
+	// Set once the HTTP unix socket is ours, so shutdown never unlinks someone else's file
+	let http_socket_bound = Arc::new(std::sync::atomic::AtomicBool::new(false));
 	let http_handle = if enable_http {
 		let sup_http = Arc::clone(&supervisor);
+		let http_socket = http_socket.clone();
+		let bound = Arc::clone(&http_socket_bound);
 		Some(tokio::spawn(async move {
-			run_http_server(sup_http, port).await;
+			run_http_server(sup_http, port, http_socket, bound).await;
 		}))
 	} else {
 		None
 	};
 
 	tracing::info!("daemon started (pid {})", std::process::id());
-	if enable_http {
-		tracing::info!("HTTP server on port {}", port);
-	}
 
-	tokio::select! {
-		_ = socket_handle => {},
-		_ = async {
-			if let Some(h) = http_handle { h.await.ok(); }
-			else { std::future::pending::<()>().await; }
-		} => {},
-		_ = tokio::signal::ctrl_c() => {
-			tracing::info!("shutting down");
+	let mut socket_handle = socket_handle;
+	let http_wait = async {
+		if let Some(h) = http_handle { h.await.ok(); }
+		else { std::future::pending::<()>().await; }
+	};
+	tokio::pin!(http_wait);
+	// Without the handler the daemon still runs; config reloads then take `ub daemon reload`
+	let mut sighup = signal(SignalKind::hangup())
+		.inspect_err(|e| tracing::error!("failed to install SIGHUP handler, SIGHUP won't reload config: {}", e))
+		.ok();
+
+	loop {
+		tokio::select! {
+			_ = &mut socket_handle => break,
+			_ = &mut http_wait => break,
+			Some(_) = async {
+				match &mut sighup {
+					Some(sighup) => sighup.recv().await,
+					None => std::future::pending().await,
+				}
+			} => {
+				tracing::info!("SIGHUP: reloading config");
+				let changes = supervisor.reconcile().await;
+				if changes.is_empty() {
+					tracing::info!("reconcile: no changes");
+				}
+			}
+			_ = tokio::signal::ctrl_c() => {
+				tracing::info!("shutting down");
+				break;
+			}
 		}
 	}
 
+	flush_logs(&supervisor).await;
+
 	let _ = std::fs::remove_file(protocol::socket_path());
 	let _ = std::fs::remove_file(protocol::pid_path());
+	if let (true, Some(path)) = (http_socket_bound.load(std::sync::atomic::Ordering::Relaxed), &http_socket) {
+		if is_socket(path) {
+			let _ = std::fs::remove_file(path);
+		}
+	}
 }
 
-async fn run_socket_server(supervisor: Arc<supervisor::Supervisor>, socket_path: &std::path::Path) {
-	let listener = match UnixListener::bind(socket_path) {
-		Ok(l) => l,
-		Err(e) => {
-			tracing::error!("failed to bind socket: {}", e);
-			return;
+fn is_socket(path: &std::path::Path) -> bool {
+	use std::os::unix::fs::FileTypeExt;
+	std::fs::symlink_metadata(path).is_ok_and(|m| m.file_type().is_socket())
+}
+
+/// `--attach service.process` (repeatable, or `--attach=...`): processes whose
+/// output is also written to the daemon's stdout.
+fn parse_attach(args: &[String]) -> Result<Vec<(String, String)>, String> {
+	let mut targets = Vec::new();
+	let mut iter = args.iter();
+	while let Some(arg) = iter.next() {
+		let target = if arg == "--attach" {
+			iter.next().ok_or("--attach needs a service.process")?
+		} else if let Some(value) = arg.strip_prefix("--attach=") {
+			value
+		} else {
+			continue;
+		};
+		match target.split_once('.') {
+			Some((service, process)) if !service.is_empty() && !process.is_empty() => {
+				targets.push((service.to_string(), process.to_string()));
+			}
+			_ => return Err(format!("--attach expects service.process, got '{}'", target)),
+		}
+	}
+	Ok(targets)
+}
+
+/// Called after each socket request is handled, with the request and how long it took.
+pub type RequestHook = Arc<dyn Fn(&Request, Duration) + Send + Sync>;
+
+/// Per-service `max_log_total_bytes`, read from the current config.
+fn log_caps(global_config: &config::GlobalConfig) -> std::collections::HashMap<String, u64> {
+	config::load_service_entries()
+		.values()
+		.filter_map(|entry| {
+			let service = config::load_service(entry, &global_config.defaults);
+			Some((service.name.clone(), service.max_log_total_bytes()?))
+		})
+		.collect()
+}
+
+/// Nothing is running yet when the daemon boots, so a process group whose leader
+/// runs a configured command was most likely left behind by a daemon that died.
+/// Read-only: this only logs what it finds.
+fn warn_orphans(global_config: &config::GlobalConfig) {
+	let mut commands: Vec<(String, String)> = Vec::new();
+	for entry in config::load_service_entries().values() {
+		for process in config::load_service(entry, &global_config.defaults).processes {
+			commands.push((format!("{}.{}", entry.name, process.name), process.command));
 		}
+	}
+	if commands.is_empty() {
+		return;
+	}
+
+	let Ok(output) = std::process::Command::new("ps").args(["-axo", "pid=,ppid=,pgid=,args="]).output() else {
+		return;
 	};
+	let own_pgid = nix::unistd::getpgrp().as_raw() as u32;
+	let own_pid = std::process::id();
+	for line in String::from_utf8_lossy(&output.stdout).lines() {
+		let mut fields = line.split_whitespace();
+		let (Some(pid), Some(ppid), Some(pgid)) = (
+			fields.next().and_then(|f| f.parse::<u32>().ok()),
+			fields.next().and_then(|f| f.parse::<u32>().ok()),
+			fields.next().and_then(|f| f.parse::<u32>().ok()),
+		) else {
+			continue;
+		};
+		// Supervised processes lead their own group (process_group(0)). The scan
+		// runs alongside the first requests, so this daemon's children are skipped.
+		if pid != pgid || pgid == own_pgid || ppid == own_pid {
+			continue;
+		}
+		let args = fields.collect::<Vec<_>>().join(" ");
+		let matched = commands.iter().find(|(_, command)| supervisor::command_matches(&args, command));
+		if let Some((name, _)) = matched {
+			tracing::warn!(
+				"possible orphan of {} from a previous daemon: pid {} (parent {}) runs `{}`; take it over with `ub adopt {} {}` or stop it with `kill -- -{}`",
+				name,
+				pid,
+				ppid,
+				args,
+				name,
+				pid,
+				pgid
+			);
+		}
+	}
+}
+
+async fn run_socket_server(supervisor: Arc<supervisor::Supervisor>, socket_path: &std::path::Path) {
+	if let Err(e) = run_socket_server_with_error(supervisor, socket_path, None).await {
+		tracing::error!("failed to bind socket: {}", e);
+	}
+}
+
+/// Serves the socket protocol on `socket_path`, returning only if it can't be
+/// bound. `hook`, when given, is called after each request with how long it took.
+pub async fn run_socket_server_with_error(
+	supervisor: Arc<supervisor::Supervisor>,
+	socket_path: &std::path::Path,
+	hook: Option<RequestHook>,
+) -> std::io::Result<()> {
+	let listener = UnixListener::bind(socket_path)?;
 
 	tracing::info!("listening on {}", socket_path.display());
 
+	let mut next_conn: u64 = 0;
 	loop {
 		let (stream, _) = match listener.accept().await {
 			Ok(s) => s,
@@ -96,101 +265,571 @@ async fn run_socket_server(supervisor: Arc<supervisor::Supervisor>, socket_path:
 			}
 		};
 
+		next_conn += 1;
+		let span = tracing::debug_span!(target: "ubermind::socket", "conn", id = next_conn);
 		let sup = Arc::clone(&supervisor);
-		tokio::spawn(async move {
+		let hook = hook.clone();
+		let conn = async move {
+			tracing::debug!(target: "ubermind::socket", "connected");
 			let (reader, mut writer) = stream.into_split();
 			let mut lines = BufReader::new(reader).lines();
 
 			while let Ok(Some(line)) = lines.next_line().await {
-				let request: Request = match serde_json::from_str(&line) {
+				let request = match Request::decode(&line) {
 					Ok(r) => r,
-					Err(e) => {
-						let resp = Response::Error {
-							message: format!("invalid request: {}", e),
-						};
+					Err(message) => {
+						let resp = Response::error(message);
 						let _ = write_response(&mut writer, &resp).await;
 						continue;
 					}
 				};
 
-				let response = handle_request(&sup, request).await;
+				let kind = request.kind();
+				let started = Instant::now();
+				// A follow keeps the connection for itself until the client goes away
+				if let Request::Logs { service, process, follow: true } = &request {
+					stream_logs(&sup, service, process.as_deref(), &mut writer, &mut lines).await;
+					if let Some(hook) = &hook {
+						hook(&request, started.elapsed());
+					}
+					break;
+				}
+				if let Request::Connect { service, process, rows, cols } = &request {
+					let size = rows.zip(*cols);
+					connect_terminal(&sup, service, process, size, &mut writer, &mut lines).await;
+					if let Some(hook) = &hook {
+						hook(&request, started.elapsed());
+					}
+					break;
+				}
+				if let Request::LogsAll { service } = &request {
+					let mut rx = follow_all_logs(Arc::clone(&sup), service.clone());
+					let keepalive = sup.config.daemon.keepalive_interval();
+					forward_stream(&mut rx, &mut writer, &mut lines, keepalive).await;
+					if let Some(hook) = &hook {
+						hook(&request, started.elapsed());
+					}
+					break;
+				}
+				let response = match &hook {
+					Some(hook) => {
+						let response = handle_request(&sup, request.clone()).await;
+						hook(&request, started.elapsed());
+						response
+					}
+					None => handle_request(&sup, request).await,
+				};
+				tracing::debug!(target: "ubermind::socket", request = kind, elapsed = ?started.elapsed(), "handled");
 				if write_response(&mut writer, &response).await.is_err() {
 					break;
 				}
 			}
-		});
+			tracing::debug!(target: "ubermind::socket", "disconnected");
+		};
+		tokio::spawn(tracing::Instrument::instrument(conn, span));
 	}
 }
 
-async fn handle_request(supervisor: &Arc<supervisor::Supervisor>, request: Request) -> Response {
-	match request {
-		Request::Ping => Response::Pong,
-		Request::Status => {
-			let services = supervisor.status().await;
-			Response::Status { services, http_port: supervisor.http_port }
+/// Answers a follow request: the buffered output of the process, or of every
+/// process in the service, then new output as it arrives. Ends when the client
+/// disconnects or the service stops.
+async fn stream_logs(
+	supervisor: &Arc<supervisor::Supervisor>,
+	service: &str,
+	process: Option<&str>,
+	writer: &mut tokio::net::unix::OwnedWriteHalf,
+	lines: &mut ClientLines,
+) {
+	let outputs = match process {
+		Some(name) => supervisor.get_output(service, Some(name)).await.map(|capture| vec![(name.to_string(), capture)]),
+		None => supervisor.get_all_outputs(service).await,
+	};
+	let outputs: Vec<_> = match outputs {
+		Ok(outputs) => outputs.into_iter().filter(|(_, capture)| capture.is_enabled()).collect(),
+		Err(e) => {
+			let _ = write_response(writer, &Response::from(e)).await;
+			return;
 		}
-		Request::Start { names, all, processes } => {
-			let mut messages = Vec::new();
-			for name in &names {
-				match supervisor.start_service_filtered(name, all, &processes).await {
-					Ok(msg) => messages.push(msg),
-					Err(e) => return Response::Error { message: e },
+	};
+	if outputs.is_empty() {
+		let message = format!("{}: output capture disabled (capture = false)", service);
+		let _ = write_response(writer, &Response::Ok { message: Some(message) }).await;
+		return;
+	}
+
+	let (tx, mut rx) = tokio::sync::mpsc::channel(256);
+	for (name, capture) in outputs {
+		tokio::spawn(follow_output(Arc::clone(supervisor), service.to_string(), name, capture, tx.clone()));
+	}
+	drop(tx);
+	if !forward_stream(&mut rx, writer, lines, supervisor.config.daemon.keepalive_interval()).await {
+		return;
+	}
+	let message = format!("{}: stopped", service);
+	let _ = write_response(writer, &Response::Ok { message: Some(message) }).await;
+}
+
+type ClientLines = tokio::io::Lines<BufReader<tokio::net::unix::OwnedReadHalf>>;
+
+/// Writes streamed responses until the stream ends (true) or the client goes
+/// away (false). Quiet output never fails a write, so the client's end of the
+/// connection is watched too; otherwise its followers would linger. With a
+/// `keepalive` interval, an idle stream gets a `Keepalive` response that often.
+async fn forward_stream(
+	rx: &mut tokio::sync::mpsc::Receiver<Response>,
+	writer: &mut tokio::net::unix::OwnedWriteHalf,
+	lines: &mut ClientLines,
+	keepalive: Option<Duration>,
+) -> bool {
+	loop {
+		let idle = async {
+			match keepalive {
+				Some(every) => tokio::time::sleep(every).await,
+				None => std::future::pending().await,
+			}
+		};
+		tokio::select! {
+			_ = idle => {
+				if write_response(writer, &Response::Keepalive).await.is_err() {
+					return false;
 				}
 			}
-			Response::Ok {
-				message: Some(messages.join("\n")),
+			response = rx.recv() => {
+				let Some(response) = response else { return true };
+				if write_response(writer, &response).await.is_err() {
+					return false;
+				}
+			}
+			line = lines.next_line() => {
+				if !matches!(line, Ok(Some(_))) {
+					return false;
+				}
 			}
 		}
-		Request::Stop { names } => {
-			let mut messages = Vec::new();
-			for name in &names {
-				match supervisor.stop_service(name).await {
-					Ok(msg) => messages.push(msg),
-					Err(e) => return Response::Error { message: e },
+	}
+}
+
+/// Follows the output of every process, or of every process in `service`,
+/// until the receiver is dropped. Processes that start later are picked up on
+/// their state change; each is followed the same way as for a single service.
+pub(crate) fn follow_all_logs(
+	supervisor: Arc<supervisor::Supervisor>,
+	service: Option<String>,
+) -> tokio::sync::mpsc::Receiver<Response> {
+	let (tx, rx) = tokio::sync::mpsc::channel(256);
+	tokio::spawn(async move {
+		use tokio::sync::broadcast::error::RecvError;
+		let mut changes = supervisor.events.subscribe();
+		let mut followed = std::collections::HashMap::<(String, String), tokio::task::JoinHandle<()>>::new();
+		loop {
+			followed.retain(|_, task| !task.is_finished());
+			let outputs: Vec<_> = {
+				let services = supervisor.services.read().await;
+				services
+					.iter()
+					.filter(|(name, _)| service.as_ref().is_none_or(|s| s == *name))
+					.flat_map(|(name, managed)| {
+						managed.processes.iter().map(move |(proc_name, mp)| ((name.clone(), proc_name.clone()), mp.output.clone()))
+					})
+					.filter(|(_, capture)| capture.is_enabled())
+					.collect()
+			};
+			for ((svc, proc_name), capture) in outputs {
+				if followed.contains_key(&(svc.clone(), proc_name.clone())) {
+					continue;
 				}
+				let task = tokio::spawn(follow_output(Arc::clone(&supervisor), svc.clone(), proc_name.clone(), capture, tx.clone()));
+				followed.insert((svc, proc_name), task);
 			}
-			Response::Ok {
-				message: Some(messages.join("\n")),
+			tokio::select! {
+				change = changes.recv() => {
+					// A lagged receiver rescans too; only a daemon going away ends it
+					if matches!(change, Err(RecvError::Closed)) {
+						return;
+					}
+				}
+				_ = tx.closed() => return,
 			}
 		}
-		Request::Reload { names, all, processes } => {
-			let mut messages = Vec::new();
-			for name in &names {
-				match supervisor.reload_service_filtered(name, all, &processes).await {
-					Ok(msg) => messages.push(msg),
-					Err(e) => return Response::Error { message: e },
+	});
+	rx
+}
+
+/// Stands in for output a follower fell too far behind to receive.
+const FOLLOW_GAP_MARKER: &[u8] = "\n[…]\n".as_bytes();
+
+/// `FOLLOW_GAP_MARKER` for a raw-mode terminal, which needs the carriage return.
+const TERMINAL_GAP_MARKER: &[u8] = "\r\n[…]\r\n".as_bytes();
+
+/// Forwards one process's output to a follower. A restart gives the process a
+/// fresh capture, so when the old one closes the current one is picked up; its
+/// buffer holds only the new instance's output, which the follower hasn't seen.
+async fn follow_output(
+	supervisor: Arc<supervisor::Supervisor>,
+	service: String,
+	process: String,
+	capture: output::OutputCapture,
+	tx: tokio::sync::mpsc::Sender<Response>,
+) {
+	use tokio::sync::broadcast::error::RecvError;
+	let mut pending = Vec::new();
+	let mut next = Some(capture);
+	while let Some(capture) = next.take() {
+		let (snapshot, mut rx) = capture.follow().await;
+		pending.extend(snapshot);
+		// Holding the capture would keep its channel open past a restart
+		drop(capture);
+		loop {
+			if !pending.is_empty() {
+				let line = take_utf8(&mut pending);
+				if !line.is_empty() {
+					let response = Response::Log { line, process: Some(process.clone()), service: Some(service.clone()) };
+					if tx.send(response).await.is_err() {
+						return;
+					}
 				}
 			}
-			Response::Ok {
-				message: Some(messages.join("\n")),
+			tokio::select! {
+				received = rx.recv() => match received {
+					Ok(chunk) => pending.extend(chunk.data),
+					Err(RecvError::Lagged(_)) => pending.extend_from_slice(FOLLOW_GAP_MARKER),
+					Err(RecvError::Closed) => break,
+				},
+				_ = tx.closed() => return,
+			}
+		}
+		tokio::time::sleep(Duration::from_millis(200)).await;
+		next = supervisor.get_output(&service, Some(&process)).await.ok();
+	}
+}
+
+/// Answers a connect request: relays the process's terminal output and feeds
+/// `Input` and `Resize` from the client into its terminal. Ends when the client
+/// detaches, leaving the process running, or when this instance of it is gone.
+async fn connect_terminal(
+	supervisor: &Arc<supervisor::Supervisor>,
+	service: &str,
+	process: &str,
+	size: Option<(u16, u16)>,
+	writer: &mut tokio::net::unix::OwnedWriteHalf,
+	lines: &mut ClientLines,
+) {
+	use tokio::sync::broadcast::error::RecvError;
+	let (master, capture) = match supervisor.terminal(service, process).await {
+		Ok(terminal) => terminal,
+		Err(e) => {
+			let _ = write_response(writer, &Response::from(e)).await;
+			return;
+		}
+	};
+	if let Some((rows, cols)) = size {
+		let _ = supervisor::resize_terminal(&master, rows, cols);
+	}
+	// The recent output shows where the program is, e.g. its prompt
+	let (mut pending, mut rx) = capture.follow().await;
+	drop(capture);
+	if write_response(writer, &Response::Ok { message: None }).await.is_err() {
+		return;
+	}
+
+	let mut check = tokio::time::interval(Duration::from_secs(1));
+	let message = loop {
+		if !pending.is_empty() {
+			let line = take_utf8(&mut pending);
+			if !line.is_empty() {
+				let response = Response::Log { line, process: Some(process.to_string()), service: Some(service.to_string()) };
+				if write_response(writer, &response).await.is_err() {
+					return;
+				}
+			}
+		}
+		tokio::select! {
+			received = rx.recv() => match received {
+				Ok(chunk) => pending.extend(chunk.data),
+				Err(RecvError::Lagged(_)) => pending.extend_from_slice(TERMINAL_GAP_MARKER),
+				Err(RecvError::Closed) => break format!("{}/{}: stopped", service, process),
+			},
+			line = lines.next_line() => {
+				let Ok(Some(line)) = line else { return };
+				let result = match Request::decode(&line) {
+					Ok(Request::Input { data }) => {
+						let master = Arc::clone(&master);
+						tokio::task::spawn_blocking(move || std::io::Write::write_all(&mut &*master, &data))
+							.await
+							.map_err(|e| e.to_string())
+							.and_then(|written| written.map_err(|e| format!("write failed: {}", e)))
+					}
+					Ok(Request::Resize { rows, cols }) => supervisor::resize_terminal(&master, rows, cols),
+					Ok(other) => Err(format!("{} is not part of a connect session", other.kind())),
+					Err(message) => Err(message),
+				};
+				if let Err(message) = result {
+					break message;
+				}
+			}
+			_ = check.tick() => {
+				let current = supervisor.terminal(service, process).await;
+				if !current.is_ok_and(|(now, _)| Arc::ptr_eq(&now, &master)) {
+					break format!("{}/{}: no longer running", service, process);
+				}
 			}
 		}
+	};
+	let _ = write_response(writer, &Response::Ok { message: Some(message) }).await;
+}
+
+/// Takes the valid UTF-8 prefix of `buf`, leaving a character split across
+/// chunks for the next call. Invalid bytes are replaced.
+fn take_utf8(buf: &mut Vec<u8>) -> String {
+	let complete = match std::str::from_utf8(buf) {
+		Ok(_) => buf.len(),
+		Err(e) if e.error_len().is_none() => e.valid_up_to(),
+		Err(_) => buf.len(),
+	};
+	let text = String::from_utf8_lossy(&buf[..complete]).into_owned();
+	buf.drain(..complete);
+	text
+}
+
+/// Runs `action` on every named service at once, so one service draining its
+/// tasks doesn't hold up the rest, and answers with the messages in `names`
+/// order. The first failure in that order makes the answer an error.
+async fn for_each_service<F, Fut>(supervisor: &Arc<supervisor::Supervisor>, names: &[String], action: F) -> Response
+where
+	F: Fn(Arc<supervisor::Supervisor>, String) -> Fut,
+	Fut: std::future::Future<Output = Result<String, supervisor::SupervisorError>> + Send + 'static,
+{
+	let mut running = tokio::task::JoinSet::new();
+	for (index, name) in names.iter().enumerate() {
+		let action = action(Arc::clone(supervisor), name.clone());
+		running.spawn(async move { (index, action.await) });
+	}
+	let mut results: Vec<Option<Result<String, supervisor::SupervisorError>>> = names.iter().map(|_| None).collect();
+	while let Some(joined) = running.join_next().await {
+		if let Ok((index, result)) = joined {
+			results[index] = Some(result);
+		}
+	}
+	let mut messages = Vec::new();
+	for result in results.into_iter().flatten() {
+		match result {
+			Ok(message) => messages.push(message),
+			Err(e) => return Response::from(e),
+		}
+	}
+	Response::Ok { message: Some(messages.join("\n")) }
+}
+
+/// Keeps the daemon log under `max_bytes` (0 for no cap) when it is this
+/// daemon's stdout, as for a background start: the contents move to
+/// `daemon.log.1` and the file starts over.
+fn cap_daemon_log(max_bytes: u64) {
+	use std::os::unix::fs::MetadataExt;
+	let path = protocol::daemon_log_path();
+	let Ok(meta) = std::fs::metadata(&path) else { return };
+	if max_bytes == 0 || meta.len() <= max_bytes {
+		return;
+	}
+	// SAFETY: fstat only fills in the struct we pass
+	let mut stdout: libc::stat = unsafe { std::mem::zeroed() };
+	if unsafe { libc::fstat(libc::STDOUT_FILENO, &mut stdout) } != 0
+		|| stdout.st_dev as u64 != meta.dev()
+		|| stdout.st_ino as u64 != meta.ino()
+	{
+		return;
+	}
+	let previous = path.with_extension("log.1");
+	if let Err(e) = std::fs::copy(&path, &previous) {
+		tracing::warn!("daemon log: could not keep {}: {}", previous.display(), e);
+	}
+	// SAFETY: truncates our own stdout, opened for appending, so writes go on from the start
+	if unsafe { libc::ftruncate(libc::STDOUT_FILENO, 0) } == 0 {
+		tracing::info!("daemon log passed {} bytes; earlier output is in {}", max_bytes, previous.display());
+	}
+}
+
+/// Frees the descriptors of log files that have gone quiet; they reopen on the next write.
+async fn close_idle_logs(supervisor: &supervisor::Supervisor) {
+	let services = supervisor.services.read().await;
+	for service in services.values() {
+		for mp in service.processes.values() {
+			mp.output.close_if_idle(output::LOG_IDLE_CLOSE);
+		}
+	}
+}
+
+/// Gets every capture's queued output into its log file before the daemon
+/// exits, giving up after a few seconds.
+async fn flush_logs(supervisor: &supervisor::Supervisor) {
+	let mut flushes = tokio::task::JoinSet::new();
+	{
+		let services = supervisor.services.read().await;
+		for mp in services.values().flat_map(|service| service.processes.values()) {
+			let output = mp.output.clone();
+			flushes.spawn(async move { output.flush().await });
+		}
+	}
+	let all = async { while flushes.join_next().await.is_some() {} };
+	if tokio::time::timeout(std::time::Duration::from_secs(5), all).await.is_err() {
+		tracing::warn!("log files not flushed within 5s, exiting anyway");
+	}
+}
+
+fn fd_limit() -> Option<u64> {
+	let mut limit = libc::rlimit { rlim_cur: 0, rlim_max: 0 };
+	// SAFETY: getrlimit only writes into the struct we pass
+	if unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) } != 0 || limit.rlim_cur == libc::RLIM_INFINITY {
+		return None;
+	}
+	Some(limit.rlim_cur)
+}
+
+async fn handle_request(supervisor: &Arc<supervisor::Supervisor>, request: Request) -> Response {
+	match request {
+		Request::Ping => Response::Pong,
+		Request::DaemonInfo => Response::DaemonInfo {
+			pid: std::process::id(),
+			version: env!("CARGO_PKG_VERSION").to_string(),
+			started_at: supervisor.started_at,
+			config_reloads: supervisor.config_reloads.load(std::sync::atomic::Ordering::Relaxed),
+			open_log_files: output::open_log_files(),
+			fd_limit: fd_limit(),
+			protocol: protocol::PROTOCOL_VERSION,
+			subscribers: supervisor.subscriber_counts().await,
+		},
+		Request::Status => {
+			let services = supervisor.status().await;
+			Response::Status {
+				services,
+				http_port: supervisor.http_port,
+				version: Some(env!("CARGO_PKG_VERSION").to_string()),
+			}
+		}
+		Request::ProcessStatus { service, process } => Response::ProcessStatus {
+			status: supervisor.process_status(&service, &process).await,
+		},
+		Request::Events { service, limit } => Response::Events {
+			events: supervisor.recent_events(service.as_deref(), limit.unwrap_or(usize::MAX)),
+		},
+		Request::Start { names, all, processes } => match supervisor.start_services(&names, all, &processes).await {
+			Ok(msg) => Response::Ok { message: Some(msg) },
+			Err(e) => Response::from(e),
+		},
+		Request::Stop { names, drain_tasks } => {
+			let drain_tasks = drain_tasks.map(Duration::from_secs);
+			for_each_service(supervisor, &names, move |sup, name| async move { sup.stop_service(&name, drain_tasks).await }).await
+		}
+		Request::Reload { names, processes, drain_tasks, .. } if !processes.is_empty() => {
+			let drain_tasks = drain_tasks.map(Duration::from_secs);
+			let processes = Arc::new(processes);
+			for_each_service(supervisor, &names, move |sup, name| {
+				let processes = Arc::clone(&processes);
+				async move {
+					let mut messages = Vec::new();
+					for process in processes.iter() {
+						messages.push(sup.reload_in_place(&name, process, drain_tasks).await?);
+					}
+					Ok(messages.join("\n"))
+				}
+			})
+			.await
+		}
+		Request::Reload { names, all, drain_tasks, .. } => {
+			let drain_tasks = drain_tasks.map(Duration::from_secs);
+			for_each_service(supervisor, &names, move |sup, name| async move {
+				sup.reload_service_filtered(&name, all, &[], drain_tasks).await
+			})
+			.await
+		}
 		Request::Restart { service, process } => {
 			match supervisor.restart_process(&service, &process).await {
 				Ok(msg) => Response::Ok { message: Some(msg) },
-				Err(e) => Response::Error { message: e },
+				Err(e) => Response::from(e),
+			}
+		}
+		Request::StartProcess { service, process, command } => {
+			match supervisor.start_process(&service, &process, command.as_deref()).await {
+				Ok(msg) => Response::Ok { message: Some(msg) },
+				Err(e) => Response::from(e),
+			}
+		}
+		Request::StopProcess { service, process } => {
+			match supervisor.stop_process(&service, &process).await {
+				Ok(msg) => Response::Ok { message: Some(msg) },
+				Err(e) => Response::from(e),
 			}
 		}
+		Request::Drain { service, process } => match supervisor.drain_process(&service, &process).await {
+			Ok(msg) => Response::Ok { message: Some(msg) },
+			Err(e) => Response::from(e),
+		},
 		Request::Kill { service, process } => {
 			match supervisor.kill_process(&service, &process).await {
 				Ok(msg) => Response::Ok { message: Some(msg) },
-				Err(e) => Response::Error { message: e },
+				Err(e) => Response::from(e),
+			}
+		}
+		Request::Adopt { service, process, pid } => match supervisor.adopt_process(&service, process.as_deref(), pid).await {
+			Ok(msg) => Response::Ok { message: Some(msg) },
+			Err(e) => Response::from(e),
+		},
+		Request::Clear { names } => {
+			let mut messages = Vec::new();
+			for name in &names {
+				match supervisor.clear_task_records(name).await {
+					Ok(msg) => messages.push(msg),
+					Err(e) => return Response::from(e),
+				}
+			}
+			Response::Ok {
+				message: Some(messages.join("\n")),
 			}
 		}
 		Request::Logs { service, process, follow: _ } => {
 			match supervisor.get_output(&service, process.as_deref()).await {
+				Ok(capture) if !capture.is_enabled() => Response::Ok {
+					message: Some(format!(
+						"{}{}: output capture disabled (capture = false)",
+						service,
+						process.as_deref().map(|p| format!(".{}", p)).unwrap_or_default()
+					)),
+				},
 				Ok(capture) => {
 					let snapshot = capture.snapshot().await;
 					Response::Log {
 						line: String::from_utf8_lossy(&snapshot).to_string(),
+						process: None,
+						service: None,
 					}
 				}
-				Err(e) => Response::Error { message: e },
+				Err(e) => Response::from(e),
+			}
+		}
+		Request::Diff { service } => match supervisor.diff_service(&service).await {
+			Ok(diffs) => Response::Diff { diffs },
+			Err(e) => Response::from(e),
+		},
+		// Streamed by the connection loop before it gets here
+		Request::LogsAll { .. } => Response::error("logs_all is only answered as a stream"),
+		Request::Connect { .. } => Response::error("connect is only answered as a session"),
+		Request::Input { .. } | Request::Resize { .. } => {
+			Response::error(format!("{} needs a connect session", request.kind()))
+		}
+		Request::GetConfig => {
+			let services = supervisor.effective_services().await;
+			Response::Config { config: supervisor.config.clone(), services }
+		}
+		Request::ReloadConfig => {
+			let changes = supervisor.reconcile().await;
+			Response::Ok {
+				message: Some(if changes.is_empty() { "no changes".to_string() } else { changes.join("\n") }),
 			}
 		}
 		Request::Shutdown => {
-			tokio::spawn(async {
+			let supervisor = Arc::clone(supervisor);
+			tokio::spawn(async move {
 				tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+				flush_logs(&supervisor).await;
 				std::process::exit(0);
 			});
 			Response::Ok {
@@ -209,8 +848,34 @@ async fn write_response(
 	writer.write_all(&data).await
 }
 
-async fn run_http_server(supervisor: Arc<supervisor::Supervisor>, port: u16) {
+/// Serves the HTTP API on 127.0.0.1:`port`, or on `unix_socket` when set.
+/// `bound` is set once the unix socket has been created.
+async fn run_http_server(
+	supervisor: Arc<supervisor::Supervisor>,
+	port: u16,
+	unix_socket: Option<std::path::PathBuf>,
+	bound: Arc<std::sync::atomic::AtomicBool>,
+) {
 	let app = api::router(supervisor);
+	if let Some(path) = unix_socket {
+		// Clear a socket left by a previous run, but never some other file
+		if is_socket(&path) {
+			let _ = std::fs::remove_file(&path);
+		}
+		let listener = match tokio::net::UnixListener::bind(&path) {
+			Ok(l) => l,
+			Err(e) => {
+				tracing::error!("failed to bind HTTP on unix:{}: {}", path.display(), e);
+				return;
+			}
+		};
+		bound.store(true, std::sync::atomic::Ordering::Relaxed);
+		tracing::info!("HTTP listening on unix:{}", path.display());
+		if let Err(e) = axum::serve(listener, app).await {
+			tracing::error!("HTTP server error: {}", e);
+		}
+		return;
+	}
 	let addr = std::net::SocketAddr::from(([127, 0, 0, 1], port));
 	let listener = match tokio::net::TcpListener::bind(addr).await {
 		Ok(l) => l,
@@ -224,3 +889,45 @@ async fn run_http_server(supervisor: Arc<supervisor::Supervisor>, port: u16) {
 		tracing::error!("HTTP server error: {}", e);
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[tokio::test]
+	async fn test_request_hook_sees_each_request() {
+		let socket_path = std::env::temp_dir().join(format!("ubermind-hook-test-{}.sock", std::process::id()));
+		let _ = std::fs::remove_file(&socket_path);
+		let seen = Arc::new(std::sync::Mutex::new(Vec::new()));
+		let hook: RequestHook = {
+			let seen = Arc::clone(&seen);
+			Arc::new(move |request: &Request, _| seen.lock().unwrap().push(request.kind()))
+		};
+		let supervisor = supervisor::Supervisor::new(config::GlobalConfig::default(), None, vec![]);
+		let server = {
+			let socket_path = socket_path.clone();
+			tokio::spawn(async move { run_socket_server_with_error(supervisor, &socket_path, Some(hook)).await })
+		};
+
+		let mut stream = None;
+		for _ in 0..100 {
+			if let Ok(s) = tokio::net::UnixStream::connect(&socket_path).await {
+				stream = Some(s);
+				break;
+			}
+			tokio::time::sleep(Duration::from_millis(10)).await;
+		}
+		let mut stream = stream.expect("socket server never listened");
+		stream.write_all(&Request::Ping.encode()).await.unwrap();
+		let mut line = String::new();
+		BufReader::new(&mut stream).read_line(&mut line).await.unwrap();
+		server.abort();
+		let _ = std::fs::remove_file(&socket_path);
+		assert!(matches!(serde_json::from_str(&line), Ok(Response::Pong)), "{}", line);
+		assert_eq!(*seen.lock().unwrap(), vec!["ping"]);
+
+		let unbindable = std::env::temp_dir().join("ubermind-no-such-dir").join("daemon.sock");
+		let supervisor = supervisor::Supervisor::new(config::GlobalConfig::default(), None, vec![]);
+		assert!(run_socket_server_with_error(supervisor, &unbindable, None).await.is_err());
+	}
+}
@@ -0,0 +1,118 @@
+use crate::config;
+use crate::daemon::supervisor::Supervisor;
+use crate::types::*;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// How `ub run-once` ended: the targets' final status and every reason it failed.
+pub struct Outcome {
+	pub services: Vec<ServiceStatus>,
+	pub failures: Vec<String>,
+}
+
+/// Supervises `names` in this process without a daemon: starts their autostart
+/// processes, waits until each is ready, has exited, or `timeout` passes, then
+/// stops everything and waits for the process groups to go away.
+pub async fn run_once(names: &[String], timeout: Duration) -> Outcome {
+	let filter = tracing_subscriber::EnvFilter::try_from_default_env()
+		.unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("warn"));
+	tracing_subscriber::fmt().with_env_filter(filter).with_writer(std::io::stderr).init();
+
+	let supervisor = Supervisor::new(config::load_global_config(), None, vec![]);
+	let mut failures = Vec::new();
+	let mut started = Vec::new();
+	for name in names {
+		match supervisor.start_service_filtered(name, false, &[]).await {
+			Ok(_) => started.push(name.clone()),
+			Err(e) => failures.push(e.to_string()),
+		}
+	}
+
+	let deadline = Instant::now() + timeout;
+	let mut interrupted = false;
+	let services = loop {
+		let services = target_status(&supervisor, &started).await;
+		let settled = services.iter().flat_map(|s| &s.processes).all(|p| settle(p).is_some());
+		if settled || Instant::now() >= deadline {
+			break services;
+		}
+		tokio::select! {
+			_ = tokio::time::sleep(Duration::from_millis(250)) => {}
+			_ = tokio::signal::ctrl_c() => {
+				interrupted = true;
+				break target_status(&supervisor, &started).await;
+			}
+		}
+	};
+
+	for service in &services {
+		for process in &service.processes {
+			let problem = match settle(process) {
+				Some(Ok(())) => continue,
+				Some(Err(problem)) => problem,
+				None if interrupted => "interrupted before it was ready".to_string(),
+				None => format!("not ready after {}s", timeout.as_secs()),
+			};
+			failures.push(format!("{}.{}: {}", service.name, process.name, problem));
+		}
+	}
+
+	teardown(&supervisor, &started).await;
+	Outcome { services, failures }
+}
+
+async fn target_status(supervisor: &Arc<Supervisor>, names: &[String]) -> Vec<ServiceStatus> {
+	let mut services = supervisor.status().await;
+	services.retain(|s| names.contains(&s.name));
+	services
+}
+
+/// None while a process is still on its way; otherwise whether it ended up fine.
+/// Services count once running and passing their `ready_port` probe, tasks once
+/// they complete.
+fn settle(process: &ProcessStatus) -> Option<Result<(), String>> {
+	match &process.state {
+		ProcessState::Running { .. } if process.service_type == ServiceType::Task => None,
+		ProcessState::Running { .. } => match process.health {
+			None | Some(Health::Healthy) => Some(Ok(())),
+			Some(_) => None,
+		},
+		ProcessState::Stopped { reason: StopReason::NotStarted } if process.autostart => None,
+		ProcessState::Stopped { reason: StopReason::NotStarted | StopReason::CleanExit } => Some(Ok(())),
+		ProcessState::Stopped { reason } => Some(Err(reason.label().to_string())),
+		ProcessState::Completed { .. } => Some(Ok(())),
+		ProcessState::Crashed { exit_code, .. } => Some(Err(format!("crashed (exit {})", exit_code))),
+		ProcessState::Failed { exit_code, .. } => Some(Err(format!("failed (exit {})", exit_code))),
+	}
+}
+
+/// Stops the services and waits for their process groups to exit, at most as
+/// long as the longest `stop_signals` sequence plus a little slack.
+async fn teardown(supervisor: &Arc<Supervisor>, names: &[String]) {
+	let mut groups = Vec::new();
+	let mut grace = 0;
+	{
+		let services = supervisor.services.read().await;
+		for service in names.iter().filter_map(|name| services.get(name)) {
+			for mp in service.processes.values() {
+				if let ProcessState::Running { pid, .. } = mp.state {
+					groups.push(nix::unistd::Pid::from_raw(pid as i32));
+					grace = grace.max(mp.def.stop_signals.iter().map(|(_, wait)| wait).sum::<u64>());
+				}
+			}
+		}
+	}
+
+	for name in names {
+		let _ = supervisor.stop_service(name, None).await;
+	}
+
+	let deadline = Instant::now() + Duration::from_secs(grace + 2);
+	while Instant::now() < deadline {
+		groups.retain(|&pgid| nix::sys::signal::killpg(pgid, None).is_ok());
+		if groups.is_empty() {
+			return;
+		}
+		tokio::time::sleep(Duration::from_millis(100)).await;
+	}
+}
@@ -1,63 +1,368 @@
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
 use std::fs::{self, File, OpenOptions};
 use std::io::Write;
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::Arc;
-use tokio::sync::broadcast;
+use std::time::{Duration, Instant};
+use tokio::sync::{broadcast, mpsc, oneshot};
 use tokio::sync::Mutex;
+use crate::config::LogsConfig;
 use crate::logs;
+use crate::types::ProcessDef;
 
 const RING_BUFFER_SIZE: usize = 64 * 1024;
+/// A long streak is summarised every this many repeats so the file shows it is ongoing.
+const DEDUPE_FLUSH_EVERY: u32 = 1000;
+/// A log file nothing has been written to for this long is closed to free its
+/// descriptor, and reopened on the next write.
+pub const LOG_IDLE_CLOSE: Duration = Duration::from_secs(300);
+/// Chunks of output that may wait for the disk before new ones are dropped.
+const LOG_QUEUE_CHUNKS: usize = 1024;
+/// Written where output was dropped because the disk couldn't keep up.
+const DISK_SLOW_MARKER: &[u8] = b"[disk slow]\n";
+
+/// Log files currently held open across every capture.
+static OPEN_LOG_FILES: AtomicUsize = AtomicUsize::new(0);
+
+pub fn open_log_files() -> usize {
+	OPEN_LOG_FILES.load(Ordering::Relaxed)
+}
+
+/// An open log file, counted in `OPEN_LOG_FILES` for as long as it is held.
+struct LogFile(File);
+
+impl LogFile {
+	fn open(path: &std::path::Path) -> std::io::Result<Self> {
+		let file = OpenOptions::new().create(true).append(true).open(path)?;
+		OPEN_LOG_FILES.fetch_add(1, Ordering::Relaxed);
+		Ok(Self(file))
+	}
+}
+
+impl Drop for LogFile {
+	fn drop(&mut self) {
+		OPEN_LOG_FILES.fetch_sub(1, Ordering::Relaxed);
+	}
+}
 
 #[derive(Clone)]
 pub struct OutputCapture {
 	ring: Arc<Mutex<VecDeque<u8>>>,
-	log_writer: Arc<Mutex<LogWriter>>,
-	sender: broadcast::Sender<Vec<u8>>,
+	/// Feeds the task that owns the log file; `None` when nothing is written.
+	log_queue: Option<LogQueue>,
+	sender: broadcast::Sender<Chunk>,
+	enabled: bool,
+	/// Also copy output to the daemon's stdout (`daemon run --attach`).
+	tee_stdout: bool,
+	/// False while the log file can't be opened or written; output is then
+	/// only kept in memory.
+	log_ok: Arc<AtomicBool>,
+}
+
+/// Which pipe a chunk of output came from. ubermind's own messages count as stdout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stream {
+	Stdout,
+	Stderr,
+}
+
+/// Output as broadcast to live subscribers, tagged with its stream.
+#[derive(Debug, Clone)]
+pub struct Chunk {
+	pub stream: Stream,
+	pub data: Vec<u8>,
+}
+
+enum LogCommand {
+	/// `gap`: output before this was dropped.
+	Write { data: Vec<u8>, gap: bool },
+	CloseIfIdle(Duration),
+	/// Write out everything held back, then answer.
+	Flush(oneshot::Sender<()>),
+}
+
+/// The sending side of a log writer task. Pushing never waits: when the queue
+/// is full the output is dropped, and the next write that gets through is
+/// preceded by `DISK_SLOW_MARKER` in the file.
+#[derive(Clone)]
+struct LogQueue {
+	tx: mpsc::Sender<LogCommand>,
+	dropped: Arc<AtomicBool>,
+}
+
+impl LogQueue {
+	/// Starts a task writing to `writer` until every sender is gone.
+	fn spawn(writer: LogWriter) -> Self {
+		let (tx, rx) = mpsc::channel(LOG_QUEUE_CHUNKS);
+		match tokio::runtime::Handle::try_current() {
+			Ok(runtime) => {
+				runtime.spawn(run_log_writer(writer, rx));
+			}
+			Err(e) => {
+				tracing::warn!("log writer unavailable: {}, keeping in-memory only", e);
+				writer.log_ok.store(false, Ordering::Relaxed);
+			}
+		}
+		Self { tx, dropped: Arc::new(AtomicBool::new(false)) }
+	}
+
+	fn push(&self, data: &[u8]) {
+		let gap = self.dropped.swap(false, Ordering::Relaxed);
+		if let Err(mpsc::error::TrySendError::Full(_)) = self.tx.try_send(LogCommand::Write { data: data.to_vec(), gap }) {
+			self.dropped.store(true, Ordering::Relaxed);
+		}
+	}
+}
+
+/// Writes queued output, everything that piled up since the last write at once.
+/// The file I/O runs on tokio's blocking pool so a slow disk never holds up a
+/// runtime thread.
+async fn run_log_writer(mut writer: LogWriter, mut rx: mpsc::Receiver<LogCommand>) {
+	while let Some(command) = rx.recv().await {
+		let mut commands = vec![command];
+		while let Ok(command) = rx.try_recv() {
+			commands.push(command);
+		}
+		match tokio::task::spawn_blocking(move || {
+			apply_log_commands(&mut writer, commands);
+			writer
+		})
+		.await
+		{
+			Ok(returned) => writer = returned,
+			Err(e) => {
+				tracing::warn!("log writer failed: {}", e);
+				return;
+			}
+		}
+	}
+	let _ = tokio::task::spawn_blocking(move || writer.flush()).await;
+}
+
+fn apply_log_commands(writer: &mut LogWriter, commands: Vec<LogCommand>) {
+	let mut batch = Vec::new();
+	for command in commands {
+		match command {
+			LogCommand::Write { data, gap } => {
+				if gap {
+					batch.extend_from_slice(DISK_SLOW_MARKER);
+				}
+				batch.extend_from_slice(&data);
+			}
+			LogCommand::CloseIfIdle(after) => {
+				writer.write(&std::mem::take(&mut batch));
+				writer.close_if_idle(Instant::now(), after);
+			}
+			LogCommand::Flush(done) => {
+				writer.write(&std::mem::take(&mut batch));
+				writer.flush();
+				let _ = done.send(());
+			}
+		}
+	}
+	writer.write(&batch);
 }
 
 struct LogWriter {
-	file: Option<File>,
+	file: Option<LogFile>,
+	log_ok: Arc<AtomicBool>,
 	path: PathBuf,
+	last_write: Instant,
+	/// The file was closed for being idle and is reopened on the next write.
+	idle: bool,
 	bytes_written: u64,
+	/// Rotate once the file reaches this size; 0 never rotates.
 	max_size: u64,
 	service: String,
 	process: String,
+	dedupe: Option<Dedupe>,
+	syslog: Option<SyslogSink>,
 }
 
-impl OutputCapture {
-	pub fn new(service: &str, process: &str, max_log_size: u64) -> Self {
-		let log_dir = logs::service_log_dir(service);
-		let _ = fs::create_dir_all(&log_dir);
+/// Forwards whole lines to the local syslog, tagged `service.process`.
+struct SyslogSink {
+	logger: syslog::Logger<syslog::LoggerBackend, syslog::Formatter3164>,
+	partial: Vec<u8>,
+}
+
+impl SyslogSink {
+	fn connect(service: &str, process: &str) -> Option<Self> {
+		let formatter = syslog::Formatter3164 {
+			facility: syslog::Facility::LOG_USER,
+			hostname: None,
+			process: format!("{}.{}", service, process),
+			pid: std::process::id(),
+		};
+		match syslog::unix(formatter) {
+			Ok(logger) => Some(Self { logger, partial: Vec::new() }),
+			Err(e) => {
+				tracing::warn!("{}/{}: syslog unavailable: {}", service, process, e);
+				None
+			}
+		}
+	}
+
+	fn feed(&mut self, data: &[u8]) {
+		self.partial.extend_from_slice(data);
+		while let Some(pos) = self.partial.iter().position(|&b| b == b'\n') {
+			let line: Vec<u8> = self.partial.drain(..=pos).collect();
+			let line = String::from_utf8_lossy(&line);
+			let _ = self.logger.info(line.trim_end_matches(['\n', '\r']));
+		}
+	}
+
+	/// Sends a trailing line that never got its newline.
+	fn flush(&mut self) {
+		if !self.partial.is_empty() {
+			let line = String::from_utf8_lossy(&self.partial);
+			let _ = self.logger.info(line.trim_end_matches('\r'));
+			self.partial.clear();
+		}
+	}
+}
+
+/// Collapses consecutive identical lines on their way to disk. The first copy is
+/// written as usual; the rest are counted and summarised as `line (repeated Nx)`.
+#[derive(Default)]
+struct Dedupe {
+	partial: Vec<u8>,
+	last: Vec<u8>,
+	repeats: u32,
+}
+
+impl Dedupe {
+	/// Returns the bytes to write for `data`. An incomplete trailing line is held
+	/// back until its newline arrives.
+	fn feed(&mut self, data: &[u8]) -> Vec<u8> {
+		let mut out = Vec::new();
+		self.partial.extend_from_slice(data);
+		while let Some(pos) = self.partial.iter().position(|&b| b == b'\n') {
+			let line: Vec<u8> = self.partial.drain(..=pos).collect();
+			if line == self.last {
+				self.repeats += 1;
+				if self.repeats >= DEDUPE_FLUSH_EVERY {
+					self.summarise(&mut out);
+				}
+				continue;
+			}
+			self.summarise(&mut out);
+			out.extend_from_slice(&line);
+			self.last = line;
+		}
+		out
+	}
+
+	fn summarise(&mut self, out: &mut Vec<u8>) {
+		if self.repeats == 0 {
+			return;
+		}
+		out.extend_from_slice(&self.last[..self.last.len() - 1]);
+		out.extend_from_slice(format!(" (repeated {}x)\n", self.repeats).as_bytes());
+		self.repeats = 0;
+	}
 
-		let log_name = logs::current_log_name(process);
-		let log_path = log_dir.join(&log_name);
+	/// Returns what is still held back: a pending summary and any incomplete
+	/// line, for when no more output is coming.
+	fn flush(&mut self) -> Vec<u8> {
+		let mut out = Vec::new();
+		self.summarise(&mut out);
+		out.append(&mut self.partial);
+		self.last.clear();
+		out
+	}
+}
 
-		let file = OpenOptions::new()
-			.create(true)
-			.append(true)
-			.open(&log_path)
-			.ok();
+impl OutputCapture {
+	/// Captures into a dated, rotated file under the service log directory, or into
+	/// the process's `log_file` as-is when it sets one. `logs.dedupe` collapses
+	/// repeated lines in the file only; `logs.syslog` also forwards every line.
+	pub fn new(service: &str, def: &ProcessDef, logs: &LogsConfig) -> Self {
+		let process = def.name.as_str();
+		let log_file = def.log_file.as_deref();
+		let max_log_size = def.max_log_size.unwrap_or(logs.max_size_bytes);
+		let log_path = match log_file {
+			Some(path) => path.to_path_buf(),
+			None => logs::service_log_dir(service).join(logs::current_log_name(process)),
+		};
+
+		let file = log_path
+			.parent()
+			.map_or(Ok(()), fs::create_dir_all)
+			.and_then(|_| LogFile::open(&log_path));
+		let file = match file {
+			Ok(file) => Some(file),
+			Err(e) => {
+				warn_log_unavailable(service, process, &log_path, &e);
+				None
+			}
+		};
+		let log_ok = Arc::new(AtomicBool::new(file.is_some()));
 
-		let bytes_written = file.as_ref().and_then(|f| f.metadata().ok()).map(|m| m.len()).unwrap_or(0);
+		let bytes_written = file.as_ref().and_then(|f| f.0.metadata().ok()).map(|m| m.len()).unwrap_or(0);
 
 		let (sender, _) = broadcast::channel(256);
 
 		Self {
 			ring: Arc::new(Mutex::new(VecDeque::with_capacity(RING_BUFFER_SIZE))),
-			log_writer: Arc::new(Mutex::new(LogWriter {
+			log_queue: Some(LogQueue::spawn(LogWriter {
 				file,
+				log_ok: log_ok.clone(),
 				path: log_path,
+				last_write: Instant::now(),
+				idle: false,
 				bytes_written,
-				max_size: max_log_size,
+				max_size: if log_file.is_some() { 0 } else { max_log_size },
 				service: service.to_string(),
 				process: process.to_string(),
+				dedupe: logs.dedupe.then(Dedupe::default),
+				syslog: if logs.syslog { SyslogSink::connect(service, process) } else { None },
 			})),
 			sender,
+			enabled: true,
+			tee_stdout: false,
+			log_ok,
 		}
 	}
 
+	/// A capture for a process whose output is discarded. It only carries
+	/// ubermind's own messages and never writes a log file.
+	pub fn disabled() -> Self {
+		let (sender, _) = broadcast::channel(256);
+		// Nothing is meant to reach a file, so there is nothing to report
+		let log_ok = Arc::new(AtomicBool::new(true));
+
+		Self {
+			ring: Arc::new(Mutex::new(VecDeque::new())),
+			log_queue: None,
+			sender,
+			enabled: false,
+			tee_stdout: false,
+			log_ok,
+		}
+	}
+
+	pub fn is_enabled(&self) -> bool {
+		self.enabled
+	}
+
+	/// Whether output is currently reaching the log file.
+	pub fn log_file_ok(&self) -> bool {
+		self.log_ok.load(Ordering::Relaxed)
+	}
+
+	pub fn tee_stdout(mut self) -> Self {
+		self.tee_stdout = true;
+		self
+	}
+
 	pub async fn write(&self, data: &[u8]) {
+		self.write_stream(Stream::Stdout, data).await;
+	}
+
+	/// Records output from one of the process's pipes. The ring buffer and log
+	/// file stay merged; only live subscribers see which stream it came from.
+	pub async fn write_stream(&self, stream: Stream, data: &[u8]) {
 		{
 			let mut ring = self.ring.lock().await;
 			for &byte in data {
@@ -66,14 +371,20 @@ impl OutputCapture {
 				}
 				ring.push_back(byte);
 			}
+			// Sent under the ring lock so `follow` sees each chunk exactly once
+			let _ = self.sender.send(Chunk { stream, data: data.to_vec() });
 		}
 
-		{
-			let mut writer = self.log_writer.lock().await;
-			writer.write(data);
+		// The disk may be slow; the process's pipe shouldn't wait for it
+		if let Some(queue) = &self.log_queue {
+			queue.push(data);
 		}
 
-		let _ = self.sender.send(data.to_vec());
+		if self.tee_stdout {
+			let mut stdout = std::io::stdout().lock();
+			let _ = stdout.write_all(data);
+			let _ = stdout.flush();
+		}
 	}
 
 	pub async fn snapshot(&self) -> Vec<u8> {
@@ -81,24 +392,117 @@ impl OutputCapture {
 		ring.iter().copied().collect()
 	}
 
-	pub fn subscribe(&self) -> broadcast::Receiver<Vec<u8>> {
+	pub fn subscribe(&self) -> broadcast::Receiver<Chunk> {
 		self.sender.subscribe()
 	}
+
+	/// The buffered output and a subscription to what comes after it, taken
+	/// together so no chunk is in both or in neither.
+	pub async fn follow(&self) -> (Vec<u8>, broadcast::Receiver<Chunk>) {
+		let ring = self.ring.lock().await;
+		(ring.iter().copied().collect(), self.sender.subscribe())
+	}
+
+	/// Live followers of this output: echo websockets, `ub echo` and the like.
+	pub fn subscriber_count(&self) -> usize {
+		self.sender.receiver_count()
+	}
+
+	/// Waits until everything written so far is in the log file, including what
+	/// dedupe and syslog are holding back, e.g. before the daemon exits.
+	pub async fn flush(&self) {
+		let Some(queue) = &self.log_queue else { return };
+		let (done, flushed) = oneshot::channel();
+		if queue.tx.send(LogCommand::Flush(done)).await.is_ok() {
+			let _ = flushed.await;
+		}
+	}
+
+	/// Closes the log file if nothing has been written to it for `after`.
+	pub fn close_if_idle(&self, after: Duration) {
+		if let Some(queue) = &self.log_queue {
+			let _ = queue.tx.try_send(LogCommand::CloseIfIdle(after));
+		}
+	}
 }
 
 impl LogWriter {
 	fn write(&mut self, data: &[u8]) {
+		if let Some(ref mut syslog) = self.syslog {
+			syslog.feed(data);
+		}
+		let deduped;
+		let data = match self.dedupe {
+			Some(ref mut dedupe) => {
+				deduped = dedupe.feed(data);
+				&deduped[..]
+			}
+			None => data,
+		};
+		self.write_file(data);
+	}
+
+	fn write_file(&mut self, data: &[u8]) {
+		if data.is_empty() {
+			return;
+		}
+		self.last_write = Instant::now();
+		if self.idle {
+			self.reopen();
+		}
 		if let Some(ref mut file) = self.file {
-			let _ = file.write_all(data);
+			// Keep the file: a full disk may free up, and the next write then succeeds
+			if let Err(e) = file.0.write_all(data) {
+				self.fail(&e);
+				return;
+			}
+			self.log_ok.store(true, Ordering::Relaxed);
 
 			self.bytes_written += data.len() as u64;
 
-			if self.bytes_written >= self.max_size {
+			if self.max_size > 0 && self.bytes_written >= self.max_size {
 				self.rotate();
 			}
 		}
 	}
 
+	/// Writes out what the dedupe and syslog sinks are holding back; the output
+	/// has ended.
+	fn flush(&mut self) {
+		if let Some(ref mut syslog) = self.syslog {
+			syslog.flush();
+		}
+		if let Some(rest) = self.dedupe.as_mut().map(Dedupe::flush) {
+			self.write_file(&rest);
+		}
+	}
+
+	/// Warns the first time the log file becomes unusable.
+	fn fail(&self, e: &std::io::Error) {
+		if self.log_ok.swap(false, Ordering::Relaxed) {
+			warn_log_unavailable(&self.service, &self.process, &self.path, e);
+		}
+	}
+
+	fn close_if_idle(&mut self, now: Instant, after: Duration) {
+		if self.file.is_some() && now.saturating_duration_since(self.last_write) >= after {
+			self.file = None;
+			self.idle = true;
+		}
+	}
+
+	/// Reopens a file closed for being idle. A failure leaves it idle, so the
+	/// next write tries again.
+	fn reopen(&mut self) {
+		match LogFile::open(&self.path) {
+			Ok(file) => {
+				self.file = Some(file);
+				self.idle = false;
+			}
+			Err(e) => self.fail(&e),
+		}
+	}
+
 	fn rotate(&mut self) {
 		if let Some(file) = self.file.take() {
 			drop(file);
@@ -111,16 +515,29 @@ impl LogWriter {
 
 		let new_name = logs::current_log_name(&self.process);
 		self.path = log_dir.join(&new_name);
-		self.file = OpenOptions::new()
-			.create(true)
-			.append(true)
-			.open(&self.path)
-			.ok();
+		match LogFile::open(&self.path) {
+			Ok(file) => {
+				self.file = Some(file);
+				self.log_ok.store(true, Ordering::Relaxed);
+			}
+			Err(e) => self.fail(&e),
+		}
 		self.bytes_written = 0;
 	}
 }
 
-pub fn expire_logs(max_age_days: u32, max_files: u32) {
+fn warn_log_unavailable(service: &str, process: &str, path: &std::path::Path, e: &std::io::Error) {
+	tracing::warn!(
+		"{}/{}: log file unavailable: {} ({}), keeping in-memory only",
+		service,
+		process,
+		e,
+		path.display()
+	);
+}
+
+/// `service_caps` maps service names to their `max_log_total_bytes`.
+pub fn expire_logs(max_age_days: u32, max_files: u32, service_caps: &HashMap<String, u64>) {
 	let log_dir = logs::log_dir();
 	if !log_dir.exists() {
 		return;
@@ -135,12 +552,15 @@ pub fn expire_logs(max_age_days: u32, max_files: u32) {
 		if !entry.path().is_dir() {
 			continue;
 		}
-		expire_service_logs(&entry.path(), max_age_days, max_files);
+		let cap = service_caps.get(entry.file_name().to_string_lossy().as_ref()).copied();
+		expire_service_logs(&entry.path(), max_age_days, max_files, cap);
 	}
 }
 
-fn expire_service_logs(dir: &std::path::Path, max_age_days: u32, max_files: u32) {
-	let mut log_files: Vec<(PathBuf, Option<(u32, u32, u32)>)> = Vec::new();
+type LogFileDate = Option<(u32, u32, u32)>;
+
+fn expire_service_logs(dir: &std::path::Path, max_age_days: u32, max_files: u32, max_total_bytes: Option<u64>) {
+	let mut log_files: Vec<(PathBuf, LogFileDate)> = Vec::new();
 
 	let entries = match fs::read_dir(dir) {
 		Ok(e) => e,
@@ -158,10 +578,7 @@ fn expire_service_logs(dir: &std::path::Path, max_age_days: u32, max_files: u32)
 	}
 
 	if max_age_days > 0 {
-		let now_secs = std::time::SystemTime::now()
-			.duration_since(std::time::UNIX_EPOCH)
-			.unwrap()
-			.as_secs();
+		let now_secs = crate::types::unix_now();
 		let cutoff_secs = now_secs.saturating_sub(max_age_days as u64 * 86400);
 
 		for (path, date) in &log_files {
@@ -185,6 +602,40 @@ fn expire_service_logs(dir: &std::path::Path, max_age_days: u32, max_files: u32)
 			let _ = fs::remove_file(path);
 		}
 	}
+
+	if let Some(cap) = max_total_bytes {
+		// Whatever survived the age and count passes
+		let mut remaining: Vec<(PathBuf, u64, std::time::SystemTime)> = log_files
+			.into_iter()
+			.filter_map(|(path, _)| {
+				let meta = path.metadata().ok()?;
+				Some((path, meta.len(), meta.modified().ok()?))
+			})
+			.collect();
+		remaining.sort_by_key(|(_, _, modified)| *modified);
+		for path in over_budget(&remaining, cap) {
+			let _ = fs::remove_file(path);
+		}
+	}
+}
+
+/// Files to delete, oldest first (`remaining` is sorted by mtime), so the total is
+/// at most `cap`. Today's live logs count towards the total but are kept.
+fn over_budget(remaining: &[(PathBuf, u64, std::time::SystemTime)], cap: u64) -> Vec<&PathBuf> {
+	let mut total: u64 = remaining.iter().map(|(_, size, _)| size).sum();
+	let mut doomed = Vec::new();
+	for (path, size, _) in remaining {
+		if total <= cap {
+			break;
+		}
+		let name = path.file_name().unwrap_or_default().to_string_lossy();
+		if logs::is_current_log_name(&name) {
+			continue;
+		}
+		total -= size;
+		doomed.push(path);
+	}
+	doomed
 }
 
 fn date_to_epoch(year: u32, month: u32, day: u32) -> u64 {
@@ -203,3 +654,90 @@ fn date_to_epoch(year: u32, month: u32, day: u32) -> u64 {
 	let days = era * 146097 + doe - 719468;
 	(days * 86400) as u64
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_dedupe_collapses_streaks() {
+		let mut dedupe = Dedupe::default();
+		let mut out = dedupe.feed(b"ok\nerr\nerr\ne");
+		out.extend(dedupe.feed(b"rr\nerr\ndone\n"));
+		assert_eq!(String::from_utf8(out).unwrap(), "ok\nerr\nerr (repeated 3x)\ndone\n");
+
+		let mut out = dedupe.feed(b"done\ndone\nno newline");
+		out.extend(dedupe.flush());
+		assert_eq!(String::from_utf8(out).unwrap(), "done (repeated 2x)\nno newline");
+	}
+
+	#[tokio::test]
+	async fn test_follow_sees_each_chunk_once() {
+		let capture = OutputCapture::disabled();
+		capture.write(b"before\n").await;
+		let (snapshot, mut rx) = capture.follow().await;
+		capture.write(b"after\n").await;
+		assert_eq!(snapshot, b"before\n");
+		assert_eq!(rx.recv().await.unwrap().data, b"after\n");
+		assert!(rx.try_recv().is_err());
+	}
+
+	#[test]
+	fn test_idle_log_file_reopens_on_write() {
+		let dir = std::env::temp_dir().join(format!("ubermind-idle-log-test-{}", std::process::id()));
+		fs::create_dir_all(&dir).unwrap();
+		let path = dir.join("web.log");
+		let mut writer = LogWriter {
+			file: Some(LogFile::open(&path).unwrap()),
+			log_ok: Arc::new(AtomicBool::new(true)),
+			path: path.clone(),
+			last_write: Instant::now(),
+			idle: false,
+			bytes_written: 0,
+			max_size: 0,
+			service: "demo".to_string(),
+			process: "web".to_string(),
+			dedupe: None,
+			syslog: None,
+		};
+
+		writer.write(b"one\n");
+		writer.close_if_idle(Instant::now(), Duration::from_secs(60));
+		assert!(writer.file.is_some(), "closed while still active");
+		writer.close_if_idle(Instant::now() + Duration::from_secs(60), Duration::from_secs(60));
+		assert!(writer.file.is_none() && writer.idle);
+
+		writer.write(b"two\n");
+		assert!(writer.file.is_some() && !writer.idle);
+		assert_eq!(fs::read_to_string(&path).unwrap(), "one\ntwo\n");
+		let _ = fs::remove_dir_all(&dir);
+	}
+
+	#[test]
+	fn test_log_cap_deletes_oldest_first() {
+		let at = |secs| std::time::UNIX_EPOCH + Duration::from_secs(secs);
+		let remaining = vec![
+			(PathBuf::from("web 2020-01-01.log"), 400, at(1)),
+			(PathBuf::from("worker 2020-01-02.log"), 300, at(2)),
+			(PathBuf::from("web 2020-01-03.log"), 300, at(3)),
+		];
+		assert_eq!(over_budget(&remaining, 700), vec![&remaining[0].0]);
+		assert_eq!(over_budget(&remaining, 300), vec![&remaining[0].0, &remaining[1].0]);
+		assert!(over_budget(&remaining, 1000).is_empty());
+	}
+
+	#[test]
+	fn test_full_log_queue_drops_and_marks() {
+		let (tx, mut rx) = mpsc::channel(1);
+		let queue = LogQueue { tx, dropped: Arc::new(AtomicBool::new(false)) };
+		queue.push(b"one\n");
+		queue.push(b"two\n");
+		let Ok(LogCommand::Write { data, gap: false }) = rx.try_recv() else { panic!("first write missing") };
+		assert_eq!(data, b"one\n");
+
+		queue.push(b"three\n");
+		let Ok(LogCommand::Write { data, gap: true }) = rx.try_recv() else { panic!("gap not marked") };
+		assert_eq!(data, b"three\n");
+		assert!(rx.try_recv().is_err());
+	}
+}
@@ -0,0 +1,131 @@
+use std::collections::HashMap;
+use std::time::Instant;
+
+/// CPU and memory of one supervised process group.
+#[derive(Debug, Clone, Copy)]
+pub struct Usage {
+	pub cpu_percent: f32,
+	pub rss_bytes: u64,
+}
+
+/// Samples process-group usage, keeping each group's previous CPU time so the
+/// next sample can report CPU% over the interval in between.
+#[derive(Default)]
+pub struct Sampler {
+	last: HashMap<u32, (u64, Instant)>,
+}
+
+impl Sampler {
+	/// Usage for each group led by a pid in `pgids`, summed over every process in
+	/// the group. A group's first sample reports 0% CPU.
+	pub fn sample(&mut self, pgids: &[u32]) -> HashMap<u32, Usage> {
+		let now = Instant::now();
+		let totals = read_groups(pgids);
+		let ticks = clock_ticks();
+
+		let mut result = HashMap::new();
+		for (&pgid, &(jiffies, rss_bytes)) in &totals {
+			let cpu_percent = match self.last.get(&pgid) {
+				Some(&(prev, at)) if jiffies >= prev => {
+					let elapsed = now.duration_since(at).as_secs_f32();
+					if elapsed > 0.0 {
+						(jiffies - prev) as f32 / ticks / elapsed * 100.0
+					} else {
+						0.0
+					}
+				}
+				_ => 0.0,
+			};
+			result.insert(pgid, Usage { cpu_percent, rss_bytes });
+		}
+
+		self.last = totals.into_iter().map(|(pgid, (jiffies, _))| (pgid, (jiffies, now))).collect();
+		result
+	}
+}
+
+/// Total CPU jiffies and resident bytes per group, in one pass over /proc.
+#[cfg(target_os = "linux")]
+fn read_groups(pgids: &[u32]) -> HashMap<u32, (u64, u64)> {
+	let mut totals: HashMap<u32, (u64, u64)> = HashMap::new();
+	if pgids.is_empty() {
+		return totals;
+	}
+	let Ok(entries) = std::fs::read_dir("/proc") else { return totals };
+	// SAFETY: sysconf only reads a system constant
+	let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) }.max(0) as u64;
+
+	for entry in entries.flatten() {
+		let name = entry.file_name();
+		let Some(pid) = name.to_str().and_then(|n| n.parse::<u32>().ok()) else { continue };
+		let Ok(stat) = std::fs::read_to_string(format!("/proc/{}/stat", pid)) else { continue };
+		let Some((pgrp, jiffies)) = parse_stat(&stat) else { continue };
+		if !pgids.contains(&pgrp) {
+			continue;
+		}
+		let resident = std::fs::read_to_string(format!("/proc/{}/statm", pid))
+			.ok()
+			.and_then(|statm| statm.split_whitespace().nth(1)?.parse::<u64>().ok())
+			.unwrap_or(0);
+		let total = totals.entry(pgrp).or_default();
+		total.0 += jiffies;
+		total.1 += resident * page_size;
+	}
+	totals
+}
+
+#[cfg(not(target_os = "linux"))]
+fn read_groups(_pgids: &[u32]) -> HashMap<u32, (u64, u64)> {
+	HashMap::new()
+}
+
+/// Process group and utime + stime from a /proc/<pid>/stat line. The command
+/// name is parenthesised and may contain spaces, so fields count from the last `)`.
+#[cfg(target_os = "linux")]
+fn parse_stat(stat: &str) -> Option<(u32, u64)> {
+	let fields: Vec<&str> = stat[stat.rfind(')')? + 1..].split_whitespace().collect();
+	let pgrp = fields.get(2)?.parse().ok()?;
+	let utime: u64 = fields.get(11)?.parse().ok()?;
+	let stime: u64 = fields.get(12)?.parse().ok()?;
+	Some((pgrp, utime + stime))
+}
+
+fn clock_ticks() -> f32 {
+	// SAFETY: sysconf only reads a system constant
+	let ticks = unsafe { libc::sysconf(libc::_SC_CLK_TCK) };
+	if ticks > 0 {
+		ticks as f32
+	} else {
+		100.0
+	}
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_parse_stat_counts_from_the_last_paren() {
+		let stat = "4242 (my (odd) app) S 1 4200 4200 0 -1 4194560 100 0 0 0 250 50 0 0 20 0 1 0 123 4096 100";
+		assert_eq!(parse_stat(stat), Some((4200, 300)));
+		assert_eq!(parse_stat("12 (sh) S 1"), None);
+		assert_eq!(parse_stat("no parens here"), None);
+	}
+
+	#[test]
+	fn test_second_sample_reports_cpu() {
+		use std::os::unix::process::CommandExt;
+		let mut busy = std::process::Command::new("sh").args(["-c", "while :; do :; done"]).process_group(0).spawn().unwrap();
+		let pgid = busy.id();
+		let mut sampler = Sampler::default();
+		let first = sampler.sample(&[pgid]);
+		std::thread::sleep(std::time::Duration::from_millis(500));
+		let second = sampler.sample(&[pgid]);
+		let _ = busy.kill();
+		let _ = busy.wait();
+
+		assert_eq!(first[&pgid].cpu_percent, 0.0);
+		assert!(first[&pgid].rss_bytes > 0);
+		assert!(second[&pgid].cpu_percent > 20.0, "{:?}", second[&pgid]);
+	}
+}
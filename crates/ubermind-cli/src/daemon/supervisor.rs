@@ -1,123 +1,635 @@
-use crate::daemon::output::OutputCapture;
-use std::collections::HashMap;
+use crate::daemon::output::{OutputCapture, Stream};
+use crate::daemon::resources;
+use std::collections::{BTreeMap, HashMap, VecDeque};
 use std::process::Stdio;
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::{Instant, SystemTime};
 use tokio::io::AsyncReadExt;
 use tokio::process::{Child, Command};
-use tokio::sync::RwLock;
+use tokio::sync::{broadcast, RwLock};
+use serde::Serialize;
 use crate::config::{self, GlobalConfig};
+use crate::protocol::{ErrorCode, Response};
 use crate::types::*;
 
+const PORT_AUDIT_DELAY_SECS: u64 = 5;
+
 pub struct Supervisor {
 	pub services: Arc<RwLock<HashMap<String, ManagedService>>>,
 	pub config: GlobalConfig,
 	pub http_port: Option<u16>,
+	/// Unix time the daemon started.
+	pub started_at: u64,
+	/// Process state transitions, for anything that wants to react to them.
+	pub events: broadcast::Sender<StateChange>,
+	/// `service.process` targets whose output is teed to the daemon's stdout.
+	pub attach: Vec<(String, String)>,
+	sampler: std::sync::Mutex<resources::Sampler>,
+	/// The last `EVENT_LOG_SIZE` state changes, oldest first.
+	event_log: std::sync::Mutex<VecDeque<ProcessEvent>>,
+	/// Config reloads since the daemon started.
+	pub config_reloads: std::sync::atomic::AtomicU64,
+	spawner: Arc<dyn Spawner>,
+}
+
+/// Starts a process's child. Embedders can wrap the command (a sandbox, a
+/// container) by giving `Supervisor::with_spawner` their own implementation.
+/// The child must lead its own process group, since stops signal the group.
+/// `def.env` arrives with its keychain references already resolved. For a
+/// process with `pty = true`, `attach_pty` sets the command up on a terminal.
+pub trait Spawner: Send + Sync {
+	fn spawn(&self, def: &ProcessDef, dir: &std::path::Path) -> Result<Spawned, String>;
+}
+
+/// A child a `Spawner` started and the argv it runs, as reported by
+/// `ub show --resolved`. `command_argv` reads it off the spawning `Command`.
+pub struct Spawned {
+	pub child: Child,
+	pub argv: Vec<String>,
+	/// The master side of the child's terminal, when it runs on one.
+	pub terminal: Option<std::fs::File>,
+}
+
+/// The program and arguments `cmd` runs.
+pub fn command_argv(cmd: &Command) -> Vec<String> {
+	let cmd = cmd.as_std();
+	std::iter::once(cmd.get_program())
+		.chain(cmd.get_args())
+		.map(|arg| arg.to_string_lossy().into_owned())
+		.collect()
+}
+
+/// Runs the command with `sh -c` in the process's directory.
+pub struct ShellSpawner;
+
+impl Spawner for ShellSpawner {
+	fn spawn(&self, def: &ProcessDef, dir: &std::path::Path) -> Result<Spawned, String> {
+		spawn_process(def, dir)
+	}
+}
+
+const EVENT_LOG_SIZE: usize = 200;
+
+/// Why a supervisor request failed. Displays as the message shown to users;
+/// callers that answer differently per failure (HTTP status codes, the CLI's
+/// fallbacks) match on the variant.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SupervisorError {
+	/// No service by that name in projects.toml.
+	UnknownService(String),
+	/// The service has no process by that name.
+	ProcessNotFound { service: String, process: String },
+	/// The daemon isn't running the service or process (`service` or `service/process`).
+	NotRunning(String),
+	/// The process is already running (`service/process`).
+	AlreadyRunning(String),
+	PortInUse(String),
+	NotReady(String),
+	/// services.toml doesn't load or defines nothing to run.
+	InvalidConfig(String),
+	SecretUnavailable(String),
+	Other(String),
+}
+
+impl SupervisorError {
+	fn process_not_found(service: &str, process: &str) -> Self {
+		Self::ProcessNotFound { service: service.to_string(), process: process.to_string() }
+	}
+
+	/// A service the daemon has no record of: unknown if it isn't configured
+	/// either, otherwise simply not running.
+	fn not_managed(service: &str) -> Self {
+		if config::load_service_entries().contains_key(service) {
+			Self::NotRunning(service.to_string())
+		} else {
+			Self::UnknownService(service.to_string())
+		}
+	}
+}
+
+impl std::fmt::Display for SupervisorError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::UnknownService(service) => write!(f, "unknown service: {}", service),
+			Self::ProcessNotFound { service, process } => write!(f, "{}/{}: not found", service, process),
+			Self::NotRunning(target) => write!(f, "{}: not running", target),
+			Self::AlreadyRunning(target) => write!(f, "{}: already running", target),
+			Self::PortInUse(message)
+			| Self::NotReady(message)
+			| Self::InvalidConfig(message)
+			| Self::SecretUnavailable(message)
+			| Self::Other(message) => f.write_str(message),
+		}
+	}
+}
+
+impl SupervisorError {
+	/// The code clients get for this error, over the socket and HTTP alike.
+	pub fn code(&self) -> ErrorCode {
+		match self {
+			Self::UnknownService(_) => ErrorCode::UnknownService,
+			Self::ProcessNotFound { .. } => ErrorCode::ProcessNotFound,
+			Self::NotRunning(_) => ErrorCode::NotRunning,
+			Self::AlreadyRunning(_) => ErrorCode::AlreadyRunning,
+			Self::PortInUse(_) => ErrorCode::PortInUse,
+			Self::NotReady(_) => ErrorCode::NotReady,
+			Self::InvalidConfig(_) => ErrorCode::InvalidConfig,
+			Self::SecretUnavailable(_) => ErrorCode::SecretUnavailable,
+			Self::Other(_) => ErrorCode::BadRequest,
+		}
+	}
+}
+
+impl From<SupervisorError> for Response {
+	fn from(e: SupervisorError) -> Self {
+		Response::Error { message: e.to_string(), code: Some(e.code()) }
+	}
+}
+
+impl From<String> for SupervisorError {
+	fn from(message: String) -> Self {
+		Self::Other(message)
+	}
+}
+
+/// A process moving to a different kind of state. Uptime ticks are not changes.
+#[derive(Debug, Clone, Serialize)]
+pub struct StateChange {
+	pub service: String,
+	pub process: String,
+	pub state: &'static str,
+	/// Unix seconds.
+	pub at: u64,
 }
 
 pub struct ManagedService {
 	#[allow(dead_code)]
 	pub name: String,
-	#[allow(dead_code)]
 	pub dir: std::path::PathBuf,
 	pub processes: HashMap<String, ManagedProcess>,
 }
 
+/// A running process as the port checks see it.
+struct PortUser {
+	service: String,
+	/// "service.process"
+	label: String,
+	declared: Vec<u16>,
+	pid: u32,
+	output: OutputCapture,
+}
+
 pub struct ManagedProcess {
 	pub def: ProcessDef,
 	pub state: ProcessState,
 	pub output: OutputCapture,
-	#[allow(dead_code)]
-	pub started_at: Option<Instant>,
+	/// When the current instance started; uptime is computed from it on read.
+	pub started_at: Option<SystemTime>,
 	pub retry_count: u32,
+	/// The last exit was confirmed as the kernel's OOM killer.
+	pub oom_killed: bool,
+	/// Relaunches since the process was first started, crash or manual.
+	pub restart_count: u32,
+	/// Unix seconds of the latest relaunch.
+	pub last_restart: Option<u64>,
+	/// What the latest launch ran.
+	pub resolved: Option<ResolvedCommand>,
+	/// The master side of the running instance's terminal, when `pty = true`.
+	terminal: Option<Arc<std::fs::File>>,
 	cancel: Option<tokio::sync::watch::Sender<bool>>,
 }
 
+impl ManagedProcess {
+	/// A stopped process with no loop yet.
+	fn new(def: ProcessDef, output: OutputCapture) -> Self {
+		Self {
+			def,
+			state: ProcessState::Stopped { reason: StopReason::NotStarted },
+			output,
+			started_at: None,
+			retry_count: 0,
+			oom_killed: false,
+			restart_count: 0,
+			last_restart: None,
+			resolved: None,
+			terminal: None,
+			cancel: None,
+		}
+	}
+
+	/// The state with `uptime_secs` brought up to date for a running process.
+	fn current_state(&self) -> ProcessState {
+		match (&self.state, self.started_at) {
+			(ProcessState::Running { pid, .. }, Some(started_at)) => ProcessState::Running {
+				pid: *pid,
+				uptime_secs: started_at.elapsed().map(|d| d.as_secs()).unwrap_or(0),
+			},
+			(state, _) => state.clone(),
+		}
+	}
+
+	/// What can be said about the process without probing it: no ports, usage
+	/// or `resolved`, and health `Unknown` when it has a readiness port.
+	fn status(&self, name: &str) -> ProcessStatus {
+		ProcessStatus {
+			name: name.to_string(),
+			state: self.current_state(),
+			pid: match &self.state {
+				ProcessState::Running { pid, .. } => Some(*pid),
+				_ => None,
+			},
+			autostart: self.def.autostart,
+			service_type: self.def.service_type.clone(),
+			ports: vec![],
+			oom_killed: self.oom_killed,
+			restart_count: self.restart_count,
+			last_restart: self.last_restart,
+			cpu_percent: None,
+			rss_bytes: None,
+			started_at_ms: self.started_at_ms(),
+			log_file_ok: self.output.log_file_ok(),
+			health: self.def.ready_port.map(|_| Health::Unknown),
+			resolved: None,
+		}
+	}
+
+	/// Unix milliseconds when the running instance started.
+	fn started_at_ms(&self) -> Option<u64> {
+		if !self.state.is_running() {
+			return None;
+		}
+		let since_epoch = self.started_at?.duration_since(std::time::UNIX_EPOCH).ok()?;
+		Some(since_epoch.as_millis() as u64)
+	}
+
+	/// Cancels the loop driving this process. The loop then stops the process
+	/// group the same way every time: SIGTERM, then SIGKILL after a grace period.
+	fn stop_loop(&mut self) {
+		if let Some(cancel) = self.cancel.take() {
+			let _ = cancel.send(true);
+		}
+	}
+
+	fn note_restart(&mut self) {
+		self.restart_count += 1;
+		self.last_restart = Some(unix_now());
+	}
+
+	/// Whether `cancel` belongs to the loop currently driving this process.
+	/// During a graceful reload two loops briefly run side by side; only the
+	/// owner may update state or respawn.
+	fn owned_by(&self, cancel: &tokio::sync::watch::Receiver<bool>) -> bool {
+		self.cancel.as_ref().is_some_and(|tx| tx.subscribe().same_channel(cancel))
+	}
+}
+
 impl Supervisor {
-	pub fn new(config: GlobalConfig, http_port: Option<u16>) -> Arc<Self> {
+	pub fn new(config: GlobalConfig, http_port: Option<u16>, attach: Vec<(String, String)>) -> Arc<Self> {
+		Self::with_spawner(config, http_port, attach, Arc::new(ShellSpawner))
+	}
+
+	pub fn with_spawner(
+		config: GlobalConfig,
+		http_port: Option<u16>,
+		attach: Vec<(String, String)>,
+		spawner: Arc<dyn Spawner>,
+	) -> Arc<Self> {
 		Arc::new(Self {
 			services: Arc::new(RwLock::new(HashMap::new())),
 			config,
 			http_port,
+			started_at: unix_now(),
+			events: broadcast::channel(256).0,
+			attach,
+			sampler: std::sync::Mutex::new(resources::Sampler::default()),
+			event_log: std::sync::Mutex::new(VecDeque::with_capacity(EVENT_LOG_SIZE)),
+			config_reloads: std::sync::atomic::AtomicU64::new(0),
+			spawner,
 		})
 	}
 
+	/// Sets a process's state and publishes the change when its kind differs, or
+	/// when a new instance takes over from a running one (a graceful reload).
+	fn set_state(&self, service: &str, mp: &mut ManagedProcess, state: ProcessState) {
+		let replaced = match (&mp.state, &state) {
+			(ProcessState::Running { pid: old, .. }, ProcessState::Running { pid: new, .. }) if old != new => Some(*old),
+			_ => None,
+		};
+		if replaced.is_some() || std::mem::discriminant(&mp.state) != std::mem::discriminant(&state) {
+			let detail = match replaced {
+				Some(old) => format!("{} (replaced pid {})", state.detail(), old),
+				None => state.detail(),
+			};
+			if let Ok(mut log) = self.event_log.lock() {
+				if log.len() >= EVENT_LOG_SIZE {
+					log.pop_front();
+				}
+				log.push_back(ProcessEvent {
+					service: service.to_string(),
+					process: mp.def.name.clone(),
+					state: state.label().to_string(),
+					detail,
+					at: unix_now(),
+				});
+			}
+			let _ = self.events.send(StateChange {
+				service: service.to_string(),
+				process: mp.def.name.clone(),
+				state: state.label(),
+				at: unix_now(),
+			});
+		}
+		// A new instance starts its uptime clock; `uptime_secs` covers time it ran before we saw it
+		if let ProcessState::Running { pid, uptime_secs } = state {
+			let same_instance = matches!(mp.state, ProcessState::Running { pid: old, .. } if old == pid);
+			if !same_instance {
+				mp.started_at = SystemTime::now().checked_sub(std::time::Duration::from_secs(uptime_secs));
+			}
+		}
+		mp.state = state;
+	}
+
 	pub async fn status(self: &Arc<Self>) -> Vec<ServiceStatus> {
 		let entries = config::load_service_entries();
-		let services = self.services.read().await;
-		let running_pids: Vec<u32> = services
-			.values()
-			.flat_map(|s| s.processes.values())
-			.filter_map(|mp| match &mp.state {
-				ProcessState::Running { pid, .. } => Some(*pid),
-				_ => None,
-			})
-			.collect();
-		let pid_ports = listening_ports_for_pids(&running_pids);
-		let mut result = Vec::new();
+		// Only the snapshot is taken under the lock; the port scan, usage sample
+		// and health probes below would otherwise hold up every state update
+		let mut managed: HashMap<String, Vec<(ProcessStatus, Option<u16>)>> = {
+			let services = self.services.read().await;
+			services
+				.iter()
+				.map(|(name, service)| {
+					let processes = service
+						.processes
+						.iter()
+						.map(|(pname, mp)| (mp.status(pname), mp.def.ready_port))
+						.collect();
+					(name.clone(), processes)
+				})
+				.collect()
+		};
+
+		let running_pids: Vec<u32> = managed.values().flatten().filter_map(|(status, _)| status.pid).collect();
+		let pids = running_pids.clone();
+		let pid_ports = tokio::task::spawn_blocking(move || listening_ports_for_pids(&pids)).await.unwrap_or_default();
+		let usage = self.sampler.lock().map(|mut s| s.sample(&running_pids)).unwrap_or_default();
+
+		let mut probes = tokio::task::JoinSet::new();
+		for (name, processes) in &managed {
+			for (index, (status, ready_port)) in processes.iter().enumerate() {
+				if let (Some(port), Some(_)) = (ready_port, status.pid) {
+					let (name, port) = (name.clone(), *port);
+					probes.spawn(async move { (name, index, probe_port(port).await) });
+				}
+			}
+		}
+		while let Some(Ok((name, index, health))) = probes.join_next().await {
+			if let Some((status, _)) = managed.get_mut(&name).and_then(|processes| processes.get_mut(index)) {
+				status.health = Some(health);
+			}
+		}
 
+		let mut result = Vec::new();
 		for (name, entry) in &entries {
-			if let Some(managed) = services.get(name) {
-				let processes = managed
+			let processes = match managed.remove(name) {
+				Some(processes) => processes
+					.into_iter()
+					.map(|(mut status, _)| {
+						if let Some(pid) = status.pid {
+							status.ports = pid_ports.get(&pid).cloned().unwrap_or_default();
+							let usage = usage.get(&pid);
+							status.cpu_percent = usage.map(|u| u.cpu_percent);
+							status.rss_bytes = usage.map(|u| u.rss_bytes);
+						}
+						status
+					})
+					.collect(),
+				None => config::load_service(entry, &self.config.defaults)
 					.processes
 					.iter()
-					.map(|(pname, mp)| {
-						let pid = match &mp.state {
-							ProcessState::Running { pid, .. } => Some(*pid),
-							_ => None,
-						};
-						let ports = pid
-							.and_then(|p| pid_ports.get(&p))
-							.cloned()
-							.unwrap_or_default();
-					ProcessStatus {
-						name: pname.clone(),
-						state: mp.state.clone(),
-						pid,
-						autostart: mp.def.autostart,
-						service_type: mp.def.service_type.clone(),
-						ports,
-					}
+					.map(|p| ProcessStatus {
+						name: p.name.clone(),
+						state: ProcessState::Stopped { reason: StopReason::NotStarted },
+						pid: None,
+						autostart: p.autostart,
+						service_type: p.service_type.clone(),
+						ports: vec![],
+						oom_killed: false,
+						restart_count: 0,
+						last_restart: None,
+						cpu_percent: None,
+						rss_bytes: None,
+						started_at_ms: None,
+						log_file_ok: true,
+						health: p.ready_port.map(|_| Health::Unknown),
+						resolved: None,
 					})
-					.collect();
-				result.push(ServiceStatus {
-					name: name.clone(),
-					dir: entry.dir.clone(),
-					processes,
-				});
-			} else {
-			let service = config::load_service(entry, &self.config.defaults);
-			let processes = service
-				.processes
-				.iter()
-				.map(|p| ProcessStatus {
-					name: p.name.clone(),
-					state: ProcessState::Stopped,
-					pid: None,
-					autostart: p.autostart,
-					service_type: p.service_type.clone(),
-					ports: vec![],
-				})
-				.collect();
-				result.push(ServiceStatus {
-					name: name.clone(),
-					dir: entry.dir.clone(),
-					processes,
-				});
-			}
+					.collect(),
+			};
+			result.push(ServiceStatus {
+				name: name.clone(),
+				dir: entry.dir.clone(),
+				processes,
+			});
 		}
 		result
 	}
 
+	/// The most recent `limit` logged events, oldest first, optionally for one service.
+	pub fn recent_events(&self, service: Option<&str>, limit: usize) -> Vec<ProcessEvent> {
+		let Ok(log) = self.event_log.lock() else { return vec![] };
+		let matching: Vec<&ProcessEvent> = log.iter().filter(|e| service.is_none_or(|s| e.service == s)).collect();
+		matching[matching.len().saturating_sub(limit)..].iter().map(|e| (*e).clone()).collect()
+	}
+
+	/// Takes over `pid`, started outside this daemon (usually left behind by one
+	/// that crashed), as one of `service`'s processes. It must lead its process
+	/// group and run that process's command. From then on it is watched, and
+	/// stop/restart work as usual. When it exits it is relaunched if its
+	/// restart policy says so.
+	pub async fn adopt_process(self: &Arc<Self>, service: &str, process: Option<&str>, pid: u32) -> Result<String, SupervisorError> {
+		let entries = config::load_service_entries();
+		let entry = entries.get(service).ok_or_else(|| SupervisorError::UnknownService(service.to_string()))?;
+		let defs = config::load_service(entry, &self.config.defaults).processes;
+		let def = match process {
+			Some(p) => defs.iter().find(|d| d.name == p).ok_or_else(|| SupervisorError::process_not_found(service, p))?,
+			None if defs.len() == 1 => &defs[0],
+			None => return Err(format!("{}: has {} processes, name one as {}.<process>", service, defs.len(), service).into()),
+		}
+		.clone();
+
+		let info = ps_info(pid).ok_or_else(|| format!("no process with pid {}", pid))?;
+		if info.pgid != pid {
+			return Err(format!("pid {} is not a process group leader (its group is {})", pid, info.pgid).into());
+		}
+		if !command_matches(&info.args, &def.command) {
+			return Err(format!("pid {} runs `{}`, not {}/{}'s `{}`", pid, info.args, service, def.name, def.command).into());
+		}
+
+		let mut services = self.services.write().await;
+		let managed = services.entry(service.to_string()).or_insert_with(|| ManagedService {
+			name: service.to_string(),
+			dir: entry.dir.clone(),
+			processes: defs.iter().map(|d| (d.name.clone(), ManagedProcess::new(d.clone(), self.new_output(service, d)))).collect(),
+		});
+		let mp = managed
+			.processes
+			.get_mut(&def.name)
+			.ok_or_else(|| SupervisorError::process_not_found(service, &def.name))?;
+		if matches!(mp.state, ProcessState::Running { .. } | ProcessState::Crashed { .. }) {
+			return Err(SupervisorError::AlreadyRunning(format!("{}/{}", service, def.name)));
+		}
+
+		mp.stop_loop();
+		let (cancel_tx, cancel_rx) = tokio::sync::watch::channel(false);
+		mp.cancel = Some(cancel_tx);
+		mp.def = def.clone();
+		mp.retry_count = 0;
+		self.set_state(service, mp, ProcessState::Running { pid, uptime_secs: info.elapsed_secs });
+
+		let sup = Arc::clone(self);
+		let (service_name, process_name, dir, output) =
+			(service.to_string(), def.name.clone(), entry.dir.clone(), mp.output.clone());
+		tokio::spawn(async move {
+			run_adopted_loop(sup, service_name, process_name, def, dir, pid, output, cancel_rx).await;
+		});
+		Ok(format!("{}/{}: adopted pid {}", service, mp.def.name, pid))
+	}
+
+	/// Status of a single managed process, looking up only that process's ports.
+	pub async fn process_status(&self, service: &str, process: &str) -> Option<ProcessStatus> {
+		let (mut status, ready_port) = {
+			let services = self.services.read().await;
+			let mp = services.get(service)?.processes.get(process)?;
+			let status = ProcessStatus { resolved: mp.resolved.clone(), ..mp.status(process) };
+			(status, mp.def.ready_port)
+		};
+		if let Some(pid) = status.pid {
+			let ports = tokio::task::spawn_blocking(move || listening_ports_for_pids(&[pid])).await.unwrap_or_default();
+			status.ports = ports.get(&pid).cloned().unwrap_or_default();
+			if let Some(port) = ready_port {
+				status.health = Some(probe_port(port).await);
+			}
+		}
+		Some(status)
+	}
+
 	pub async fn start_service_filtered(
 		self: &Arc<Self>,
 		name: &str,
 		all: bool,
 		processes: &[String],
-	) -> Result<String, String> {
+	) -> Result<String, SupervisorError> {
+		let entries = config::load_service_entries();
+		let entry = entries.get(name).ok_or_else(|| SupervisorError::UnknownService(name.to_string()))?;
+		self.start_entry(name, entry, all, processes, None).await
+	}
+
+	/// Starts several services. When the processes starting set more than one
+	/// `start_priority`, they come up tier by tier, lowest first, and each tier
+	/// must be ready (or, for tasks, finished) before the next begins. A tier that
+	/// fails or times out leaves the later ones unstarted.
+	pub async fn start_services(self: &Arc<Self>, names: &[String], all: bool, processes: &[String]) -> Result<String, SupervisorError> {
 		let entries = config::load_service_entries();
-		let entry = entries.get(name).ok_or_else(|| format!("unknown service: {}", name))?;
+		let mut tiers: BTreeMap<i32, Vec<(String, ProcessDef)>> = BTreeMap::new();
+		if processes.is_empty() {
+			for name in names {
+				let entry = entries.get(name).ok_or_else(|| SupervisorError::UnknownService(name.to_string()))?;
+				for def in config::load_service(entry, &self.config.defaults).processes {
+					if all || def.autostart {
+						tiers.entry(def.start_priority).or_default().push((name.clone(), def));
+					}
+				}
+			}
+		}
+
+		let mut messages = Vec::new();
+		if tiers.len() <= 1 {
+			for name in names {
+				messages.push(self.start_service_filtered(name, all, processes).await?);
+			}
+			return Ok(messages.join("\n"));
+		}
+
+		for (priority, tier) in tiers {
+			let mut events = self.events.subscribe();
+			let mut waiting = HashMap::new();
+			for (service, def) in &tier {
+				let msg = self.start_process(service, &def.name, None).await?;
+				let running = msg.ends_with("already running");
+				messages.push(msg);
+				waiting.insert((service.clone(), def.name.clone()), (running, def.service_type == ServiceType::Task));
+			}
+			let timeout = tier.iter().map(|(_, def)| def.ready_timeout_secs).max().unwrap_or(0);
+			self.await_tier(&mut events, waiting, timeout)
+				.await
+				.map_err(|e| format!("{}\nstart_priority {}: {}; later tiers not started", messages.join("\n"), priority, e))?;
+		}
+		Ok(messages.join("\n"))
+	}
+
+	/// Waits until every `(service, process)` in `waiting` is ready. Values say
+	/// whether it is already running and whether it is a task, which is ready
+	/// only once it completes.
+	async fn await_tier(
+		&self,
+		events: &mut broadcast::Receiver<StateChange>,
+		mut waiting: HashMap<(String, String), (bool, bool)>,
+		timeout_secs: u64,
+	) -> Result<(), String> {
+		let deadline = Instant::now() + std::time::Duration::from_secs(timeout_secs);
+		loop {
+			loop {
+				let change = match events.try_recv() {
+					Ok(change) => change,
+					Err(broadcast::error::TryRecvError::Lagged(_)) => continue,
+					Err(_) => break,
+				};
+				let key = (change.service, change.process);
+				let Some((running, _)) = waiting.get_mut(&key) else { continue };
+				match change.state {
+					"Running" => *running = true,
+					"Completed" | "Stopped" => {
+						waiting.remove(&key);
+					}
+					state => return Err(format!("{}.{} {}", key.0, key.1, state.to_lowercase())),
+				}
+			}
+
+			let mut ready = Vec::new();
+			for (key, &(running, is_task)) in &waiting {
+				if !running || is_task {
+					continue;
+				}
+				let status = self.process_status(&key.0, &key.1).await;
+				if status.is_some_and(|s| s.state.is_running() && matches!(s.health, None | Some(Health::Healthy))) {
+					ready.push(key.clone());
+				}
+			}
+			for key in ready {
+				waiting.remove(&key);
+			}
 
+			if waiting.is_empty() {
+				return Ok(());
+			}
+			if Instant::now() >= deadline {
+				let names: Vec<String> = waiting.keys().map(|(s, p)| format!("{}.{}", s, p)).collect();
+				return Err(format!("{} not ready after {}s", names.join(", "), timeout_secs));
+			}
+			tokio::time::sleep(std::time::Duration::from_millis(250)).await;
+		}
+	}
+
+	/// `command` replaces the command of the named processes for their first
+	/// launch only; crash restarts run the configured one.
+	async fn start_entry(
+		self: &Arc<Self>,
+		name: &str,
+		entry: &config::ServiceEntry,
+		all: bool,
+		processes: &[String],
+		command: Option<&str>,
+	) -> Result<String, SupervisorError> {
 		{
 			let services = self.services.read().await;
 			if let Some(managed) = services.get(name) {
@@ -129,10 +641,14 @@ impl Supervisor {
 
 		let service = config::load_service(entry, &self.config.defaults);
 		if service.processes.is_empty() {
-			return Err(format!("{}: no processes defined (missing services.toml?)", name));
+			return Err(SupervisorError::InvalidConfig(format!("{}: no processes defined (missing services.toml?)", name)));
 		}
 
+		self.check_port_conflicts(name, &service.processes, all, processes).await?;
+		let mut secret_envs = Self::resolve_secrets(name, &service.processes, all, processes).await?;
+
 		let mut managed_processes = HashMap::new();
+		let mut to_spawn = Vec::new();
 
 		for proc_def in &service.processes {
 			let should_start = if !processes.is_empty() {
@@ -143,32 +659,22 @@ impl Supervisor {
 				proc_def.autostart
 			};
 
-			let output = OutputCapture::new(name, &proc_def.name, self.config.logs.max_size_bytes);
+			let output = self.new_output(name, proc_def);
 			let (cancel_tx, cancel_rx) = tokio::sync::watch::channel(false);
 
-			let mp = ManagedProcess {
-				def: proc_def.clone(),
-				state: ProcessState::Stopped,
-				output: output.clone(),
-				started_at: None,
-				retry_count: 0,
-				cancel: Some(cancel_tx),
-			};
+			let mp = ManagedProcess { cancel: Some(cancel_tx), ..ManagedProcess::new(proc_def.clone(), output.clone()) };
 			managed_processes.insert(proc_def.name.clone(), mp);
 
 			if should_start {
-				let sup = Arc::clone(self);
-				let service_name = name.to_string();
-				let process_name = proc_def.name.clone();
-				let proc_def_clone = proc_def.clone();
-				let dir = entry.dir.clone();
-
-				tokio::spawn(async move {
-					run_process_loop(sup, service_name, process_name, proc_def_clone, dir, output, cancel_rx).await;
-				});
+				let mut launch_def = proc_def.clone();
+				if let Some(env) = secret_envs.remove(&proc_def.name) {
+					launch_def.env = env;
+				}
+				to_spawn.push((launch_def, output, cancel_rx));
 			}
 		}
 
+		// Register the service before spawning so each loop finds itself as the owner.
 		{
 			let mut services = self.services.write().await;
 			services.insert(
@@ -181,158 +687,921 @@ impl Supervisor {
 			);
 		}
 
-		Ok(format!("{}: starting", name))
-	}
-
-	pub async fn stop_service(self: &Arc<Self>, name: &str) -> Result<String, String> {
-		let mut services = self.services.write().await;
-		let managed = services.get_mut(name).ok_or_else(|| format!("{}: not running", name))?;
-
-		let mut any_running = false;
-		for (_, mp) in managed.processes.iter_mut() {
-			if mp.state.is_running() {
-				any_running = true;
-				if let Some(cancel) = mp.cancel.take() {
-					let _ = cancel.send(true);
-				}
-				if let ProcessState::Running { pid, .. } = &mp.state {
-					kill_process_tree(*pid);
-				}
-				mp.state = ProcessState::Stopped;
-			}
+		for (proc_def, output, cancel_rx) in to_spawn {
+			self.spawn_loop(name, proc_def, command.map(str::to_string), &entry.dir, output, cancel_rx);
 		}
 
-		if !any_running {
-			return Ok(format!("{}: already stopped", name));
-		}
+		let sup = Arc::clone(self);
+		let service_name = name.to_string();
+		tokio::spawn(async move {
+			tokio::time::sleep(std::time::Duration::from_secs(PORT_AUDIT_DELAY_SECS)).await;
+			sup.audit_undeclared_ports(&service_name).await;
+		});
 
-		services.remove(name);
-		Ok(format!("{}: stopped", name))
+		Ok(format!("{}: starting", name))
 	}
 
-	pub async fn reload_service_filtered(
+	fn spawn_loop(
 		self: &Arc<Self>,
-		name: &str,
-		all: bool,
-		processes: &[String],
-	) -> Result<String, String> {
-		let _ = self.stop_service(name).await;
-		tokio::time::sleep(std::time::Duration::from_millis(200)).await;
-		self.start_service_filtered(name, all, processes).await
-	}
-
-	pub async fn restart_process(self: &Arc<Self>, service: &str, process: &str) -> Result<String, String> {
-		let entries = config::load_service_entries();
-		let entry = entries.get(service).ok_or_else(|| format!("unknown service: {}", service))?;
-
-		let mut services = self.services.write().await;
-		let managed = services.get_mut(service).ok_or_else(|| format!("{}: not running", service))?;
-		let mp = managed.processes.get_mut(process).ok_or_else(|| format!("{}/{}: not found", service, process))?;
-
-		if let Some(cancel) = mp.cancel.take() {
-			let _ = cancel.send(true);
-		}
-		if let ProcessState::Running { pid, .. } = &mp.state {
-			kill_process_tree(*pid);
-		}
-		mp.state = ProcessState::Stopped;
-		mp.retry_count = 0;
-
-		let output = OutputCapture::new(service, process, self.config.logs.max_size_bytes);
-		let (cancel_tx, cancel_rx) = tokio::sync::watch::channel(false);
-		mp.output = output.clone();
-		mp.cancel = Some(cancel_tx);
-
+		service: &str,
+		def: ProcessDef,
+		command: Option<String>,
+		dir: &std::path::Path,
+		output: OutputCapture,
+		cancel_rx: tokio::sync::watch::Receiver<bool>,
+	) {
 		let sup = Arc::clone(self);
 		let service_name = service.to_string();
-		let process_name = process.to_string();
-		let proc_def = mp.def.clone();
-		let dir = entry.dir.clone();
+		let process_name = def.name.clone();
+		let dir = dir.to_path_buf();
 
 		tokio::spawn(async move {
-			run_process_loop(sup, service_name, process_name, proc_def, dir, output, cancel_rx).await;
+			run_process_loop(sup, service_name, process_name, def, command, dir, output, cancel_rx).await;
 		});
-
-		Ok(format!("{}/{}: restarting", service, process))
 	}
 
-	pub async fn kill_process(self: &Arc<Self>, service: &str, process: &str) -> Result<String, String> {
-		let mut services = self.services.write().await;
-		let managed = services.get_mut(service).ok_or_else(|| format!("{}: not running", service))?;
-		let mp = managed.processes.get_mut(process).ok_or_else(|| format!("{}/{}: not found", service, process))?;
-
-		if let Some(cancel) = mp.cancel.take() {
-			let _ = cancel.send(true);
-		}
-		if let ProcessState::Running { pid, .. } = &mp.state {
-			kill_process_tree(*pid);
-		}
-		mp.state = ProcessState::Stopped;
-
-		Ok(format!("{}/{}: killed", service, process))
+	/// Every registered service with the process definitions in effect: the ones
+	/// running services were started with, freshly loaded ones for the rest.
+	pub async fn effective_services(&self) -> Vec<Service> {
+		let entries = config::load_service_entries();
+		let services = self.services.read().await;
+		entries
+			.values()
+			.map(|entry| {
+				let mut service = config::load_service(entry, &self.config.defaults);
+				if let Some(managed) = services.get(&entry.name) {
+					let mut processes: Vec<ProcessDef> = managed.processes.values().map(|mp| mp.def.clone()).collect();
+					processes.sort_by_key(|def| service.processes.iter().position(|p| p.name == def.name));
+					service.processes = processes;
+				}
+				service
+			})
+			.collect()
 	}
 
-	pub async fn get_output(&self, service: &str, process: Option<&str>) -> Result<OutputCapture, String> {
+	/// What a reload of `name` would change: processes its config adds or
+	/// removes, and the fields that differ from the definitions running now.
+	pub async fn diff_service(&self, name: &str) -> Result<Vec<ProcessDiff>, SupervisorError> {
+		let entries = config::load_service_entries();
+		let entry = entries.get(name).ok_or_else(|| SupervisorError::UnknownService(name.to_string()))?;
+		let fresh = config::try_load_service(entry, &self.config.defaults).map_err(|e| format!("{}: {}", name, e))?;
 		let services = self.services.read().await;
-		let managed = services.get(service).ok_or_else(|| format!("{}: not found", service))?;
+		let managed = services.get(name).ok_or_else(|| SupervisorError::NotRunning(name.to_string()))?;
 
-		if let Some(proc_name) = process {
-			let mp = managed.processes.get(proc_name).ok_or_else(|| format!("{}/{}: not found", service, proc_name))?;
-			Ok(mp.output.clone())
-		} else {
-			managed
-				.processes
-				.values()
-				.next()
-				.map(|mp| mp.output.clone())
-				.ok_or_else(|| format!("{}: no processes", service))
+		let mut diffs = Vec::new();
+		for def in &fresh.processes {
+			match managed.processes.get(&def.name) {
+				Some(mp) => {
+					let fields = diff_defs(&mp.def, def);
+					if !fields.is_empty() {
+						diffs.push(ProcessDiff {
+							process: def.name.clone(),
+							change: DiffChange::Changed,
+							running: mp.state.is_running(),
+							fields,
+						});
+					}
+				}
+				None => diffs.push(ProcessDiff {
+					process: def.name.clone(),
+					change: DiffChange::Added,
+					running: def.autostart,
+					fields: vec![],
+				}),
+			}
 		}
-	}
-
-	pub async fn get_all_outputs(&self, service: &str) -> Result<Vec<(String, OutputCapture)>, String> {
-		let services = self.services.read().await;
-		let managed = services.get(service).ok_or_else(|| format!("{}: not found", service))?;
-		Ok(managed
+		let mut removed: Vec<_> = managed
 			.processes
 			.iter()
-			.map(|(name, mp)| (name.clone(), mp.output.clone()))
-			.collect())
+			.filter(|(pname, _)| !fresh.processes.iter().any(|d| &d.name == *pname))
+			.map(|(pname, mp)| ProcessDiff {
+				process: pname.clone(),
+				change: DiffChange::Removed,
+				running: mp.state.is_running(),
+				fields: vec![],
+			})
+			.collect();
+		removed.sort_by(|a, b| a.process.cmp(&b.process));
+		diffs.extend(removed);
+		Ok(diffs)
+	}
+
+	/// Re-reads projects.toml and the services.toml of every managed service and
+	/// applies the differences. Returns one line per change.
+	pub async fn reconcile(self: &Arc<Self>) -> Vec<String> {
+		self.config_reloads.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+		self.reconcile_entries(&config::load_service_entries()).await
+	}
+
+	async fn reconcile_entries(self: &Arc<Self>, entries: &BTreeMap<String, config::ServiceEntry>) -> Vec<String> {
+		let names: Vec<String> = self.services.read().await.keys().cloned().collect();
+
+		let mut changes = Vec::new();
+		for name in names {
+			let Some(entry) = entries.get(&name) else {
+				if let Ok(msg) = self.stop_service(&name, None).await {
+					changes.push(format!("{} (no longer in projects.toml)", msg));
+				}
+				continue;
+			};
+			// A half-saved services.toml leaves the service as it is until it parses again
+			let fresh = match config::try_load_service(entry, &self.config.defaults) {
+				Ok(fresh) => fresh,
+				Err(e) => {
+					tracing::warn!("reconcile: {}: {}", name, e);
+					changes.push(format!("{}: unchanged, {}", name, e));
+					continue;
+				}
+			};
+			changes.extend(self.reconcile_service(&name, &entry.dir, fresh.processes).await);
+		}
+
+		for change in &changes {
+			tracing::info!("reconcile: {}", change);
+		}
+		changes
+	}
+
+	/// Brings one managed service in line with `defs`: removed processes are
+	/// stopped, changed running ones restart with their new definition, and new
+	/// autostart processes are started.
+	async fn reconcile_service(
+		self: &Arc<Self>,
+		name: &str,
+		dir: &std::path::Path,
+		defs: Vec<ProcessDef>,
+	) -> Vec<String> {
+		let mut changes = Vec::new();
+		let mut to_restart = Vec::new();
+		let mut to_spawn = Vec::new();
+
+		{
+			let mut services = self.services.write().await;
+			let Some(managed) = services.get_mut(name) else { return changes };
+
+			let removed: Vec<String> = managed
+				.processes
+				.keys()
+				.filter(|pname| !defs.iter().any(|d| &d.name == *pname))
+				.cloned()
+				.collect();
+			for pname in removed {
+				if let Some(mut mp) = managed.processes.remove(&pname) {
+					mp.stop_loop();
+					self.set_state(name, &mut mp, ProcessState::Stopped { reason: StopReason::Manual });
+				}
+				changes.push(format!("{}.{}: removed", name, pname));
+			}
+
+			for def in defs {
+				match managed.processes.get_mut(&def.name) {
+					Some(mp) if mp.def != def => {
+						if mp.state.is_running() {
+							to_restart.push(def.name.clone());
+							changes.push(format!("{}.{}: changed, restarting", name, def.name));
+						} else {
+							changes.push(format!("{}.{}: changed", name, def.name));
+						}
+						mp.def = def;
+					}
+					Some(_) => {}
+					None => {
+						let output = self.new_output(name, &def);
+						let (cancel_tx, cancel_rx) = tokio::sync::watch::channel(false);
+						if def.autostart {
+							changes.push(format!("{}.{}: added, starting", name, def.name));
+							to_spawn.push((def.clone(), output.clone(), cancel_rx));
+						} else {
+							changes.push(format!("{}.{}: added", name, def.name));
+						}
+						managed.processes.insert(
+							def.name.clone(),
+							ManagedProcess { cancel: Some(cancel_tx), ..ManagedProcess::new(def, output) },
+						);
+					}
+				}
+			}
+		}
+
+		for process in to_restart {
+			if let Err(e) = self.restart_process(name, &process).await {
+				changes.push(e.to_string());
+			}
+		}
+		for (def, output, cancel_rx) in to_spawn {
+			self.spawn_loop(name, def, None, dir, output, cancel_rx);
+		}
+		changes
+	}
+
+	/// Every running supervised process with its declared ports, and the ports
+	/// each one is detected listening on. The scan runs after the lock is released.
+	async fn port_users(&self) -> (Vec<PortUser>, HashMap<u32, Vec<u16>>) {
+		let users: Vec<PortUser> = {
+			let services = self.services.read().await;
+			services
+				.iter()
+				.flat_map(|(sname, managed)| {
+					managed.processes.iter().filter_map(move |(pname, mp)| match &mp.state {
+						ProcessState::Running { pid, .. } => Some(PortUser {
+							service: sname.clone(),
+							label: format!("{}.{}", sname, pname),
+							declared: mp.def.ports.clone(),
+							pid: *pid,
+							output: mp.output.clone(),
+						}),
+						_ => None,
+					})
+				})
+				.collect()
+		};
+		let pids: Vec<u32> = users.iter().map(|user| user.pid).collect();
+		let detected = tokio::task::spawn_blocking(move || listening_ports_for_pids(&pids)).await.unwrap_or_default();
+		(users, detected)
+	}
+
+	/// Maps each port held by a running supervised process to "service.process",
+	/// using both declared and detected ports. `exclude` skips one such label.
+	fn port_holders(users: &[PortUser], detected: &HashMap<u32, Vec<u16>>, exclude: Option<&str>) -> HashMap<u16, String> {
+		let mut holders = HashMap::new();
+		for user in users {
+			if exclude == Some(user.label.as_str()) {
+				continue;
+			}
+			let ports = user.declared.iter().chain(detected.get(&user.pid).into_iter().flatten());
+			for port in ports {
+				holders.entry(*port).or_insert_with(|| user.label.clone());
+			}
+		}
+		holders
+	}
+
+	/// Refuses to start when a declared port is already held by another supervised
+	/// process or by something else on the system.
+	async fn check_port_conflicts(
+		&self,
+		name: &str,
+		defs: &[ProcessDef],
+		all: bool,
+		processes: &[String],
+	) -> Result<(), SupervisorError> {
+		let starting: Vec<&ProcessDef> = defs
+			.iter()
+			.filter(|d| {
+				if !processes.is_empty() {
+					processes.contains(&d.name)
+				} else {
+					all || d.autostart
+				}
+			})
+			.collect();
+		if starting.iter().all(|d| d.ports.is_empty()) {
+			return Ok(());
+		}
+
+		let (users, detected) = self.port_users().await;
+		let holders = Self::port_holders(&users, &detected, None);
+
+		for def in starting {
+			for port in &def.ports {
+				if let Some(holder) = holders.get(port) {
+					return Err(SupervisorError::PortInUse(format!(
+						"{}.{}: port {} already held by {}",
+						name, def.name, port, holder
+					)));
+				}
+				if port_in_use(*port) {
+					return Err(SupervisorError::PortInUse(format!(
+						"{}.{}: port {} already in use by another program",
+						name, def.name, port
+					)));
+				}
+			}
+		}
+		Ok(())
+	}
+
+	/// Fetches the keychain secrets of the processes about to start, so a missing
+	/// one fails the start instead of the process quietly getting an empty value.
+	/// Returns the resolved env of each process that has secrets, for its launch.
+	async fn resolve_secrets(
+		name: &str,
+		defs: &[ProcessDef],
+		all: bool,
+		only: &[String],
+	) -> Result<HashMap<String, HashMap<String, String>>, SupervisorError> {
+		let mut resolved = HashMap::new();
+		for def in defs {
+			let starting = if !only.is_empty() { only.contains(&def.name) } else { all || def.autostart };
+			if starting && crate::secrets::has_secrets(&def.env) {
+				let env = crate::secrets::resolve_env_async(&def.env)
+					.await
+					.map_err(|e| SupervisorError::SecretUnavailable(format!("{}.{}: {}", name, def.name, e)))?;
+				resolved.insert(def.name.clone(), env);
+			}
+		}
+		Ok(resolved)
+	}
+
+	/// Warns when a process without declared ports turns out to listen on a port
+	/// another supervised process also holds.
+	async fn audit_undeclared_ports(&self, name: &str) {
+		let (users, detected) = self.port_users().await;
+		for user in users.iter().filter(|user| user.service == name && user.declared.is_empty()) {
+			let holders = Self::port_holders(&users, &detected, Some(&user.label));
+			for port in detected.get(&user.pid).into_iter().flatten() {
+				if let Some(holder) = holders.get(port) {
+					let msg = format!(
+						"[ubermind] warning: {} listens on port {} also held by {} (declare `ports` to check before start)\n",
+						user.label, port, holder
+					);
+					tracing::warn!("{}", msg.trim_end());
+					user.output.write(msg.as_bytes()).await;
+				}
+			}
+		}
+	}
+
+	/// Stops every running process of a service and forgets it. With `drain_tasks`,
+	/// long-running processes stop at once but running tasks get up to that long
+	/// to finish on their own before they are killed.
+	pub async fn stop_service(
+		self: &Arc<Self>,
+		name: &str,
+		drain_tasks: Option<std::time::Duration>,
+	) -> Result<String, SupervisorError> {
+		let mut any_running = false;
+		let mut drained = None;
+		if let Some(timeout) = drain_tasks {
+			let mut tasks = 0;
+			{
+				let mut services = self.services.write().await;
+				let managed = services.get_mut(name).ok_or_else(|| SupervisorError::NotRunning(name.to_string()))?;
+				for mp in managed.processes.values_mut().filter(|mp| mp.state.is_running()) {
+					any_running = true;
+					if mp.def.service_type == ServiceType::Task {
+						tasks += 1;
+					} else {
+						mp.stop_loop();
+						self.set_state(name, mp, ProcessState::Stopped { reason: StopReason::Manual });
+					}
+				}
+			}
+			if tasks > 0 {
+				drained = Some(self.await_tasks(name, None, timeout).await);
+			}
+		}
+
+		let mut services = self.services.write().await;
+		let managed = services.get_mut(name).ok_or_else(|| SupervisorError::NotRunning(name.to_string()))?;
+
+		for (_, mp) in managed.processes.iter_mut() {
+			if mp.state.is_running() {
+				any_running = true;
+				mp.stop_loop();
+				self.set_state(name, mp, ProcessState::Stopped { reason: StopReason::Manual });
+			}
+		}
+
+		if !any_running {
+			return Ok(format!("{}: already stopped", name));
+		}
+
+		services.remove(name);
+		Ok(match drained {
+			Some(true) => format!("{}: stopped after its running tasks finished", name),
+			Some(false) => format!("{}: stopped, killing tasks still running after {}s", name, drain_tasks.unwrap_or_default().as_secs()),
+			None => format!("{}: stopped", name),
+		})
+	}
+
+	/// Waits until no task of `service`, or just its `process`, is running.
+	/// False if some still are at `timeout`.
+	async fn await_tasks(&self, service: &str, process: Option<&str>, timeout: std::time::Duration) -> bool {
+		let deadline = Instant::now() + timeout;
+		loop {
+			let running = self.services.read().await.get(service).is_some_and(|managed| {
+				managed.processes.iter().any(|(name, mp)| {
+					process.is_none_or(|p| p == name) && mp.def.service_type == ServiceType::Task && mp.state.is_running()
+				})
+			});
+			if !running {
+				return true;
+			}
+			if Instant::now() >= deadline {
+				return false;
+			}
+			tokio::time::sleep(std::time::Duration::from_millis(250)).await;
+		}
+	}
+
+	/// `drain_tasks` is passed on to `stop_service` when the service is restarted.
+	pub async fn reload_service_filtered(
+		self: &Arc<Self>,
+		name: &str,
+		all: bool,
+		processes: &[String],
+		drain_tasks: Option<std::time::Duration>,
+	) -> Result<String, SupervisorError> {
+		if let Some(entry) = config::load_service_entries().get(name) {
+			config::try_load_service(entry, &self.config.defaults)
+				.map_err(|e| SupervisorError::InvalidConfig(format!("{}: not reloaded, {}", name, e)))?;
+		}
+		if let Some(result) = self.reload_service_graceful(name).await {
+			return result;
+		}
+		let _ = self.stop_service(name, drain_tasks).await;
+		tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+		self.start_service_filtered(name, all, processes).await
+	}
+
+	/// Reloads a running service in place when any of its running processes use
+	/// `reload_policy = "graceful"`. Graceful processes are replaced start-new-first;
+	/// the rest are restarted with their fresh definitions. Returns `None` when the
+	/// service should fall back to a plain stop-then-start.
+	async fn reload_service_graceful(self: &Arc<Self>, name: &str) -> Option<Result<String, SupervisorError>> {
+		let entries = config::load_service_entries();
+		let entry = entries.get(name)?;
+		let service = config::load_service(entry, &self.config.defaults);
+
+		let running: Vec<String> = {
+			let services = self.services.read().await;
+			let managed = services.get(name)?;
+			managed
+				.processes
+				.iter()
+				.filter(|(_, mp)| mp.state.is_running())
+				.map(|(pname, _)| pname.clone())
+				.collect()
+		};
+
+		let fresh: Vec<&ProcessDef> = service.processes.iter().filter(|p| running.contains(&p.name)).collect();
+		if fresh.len() != running.len() || !fresh.iter().any(|p| p.reloads_gracefully()) {
+			return None;
+		}
+
+		let mut messages = Vec::new();
+		for def in fresh {
+			let result = if def.reloads_gracefully() {
+				self.replace_process(name, &entry.dir, def.clone()).await
+			} else {
+				{
+					let mut services = self.services.write().await;
+					if let Some(mp) = services.get_mut(name).and_then(|m| m.processes.get_mut(&def.name)) {
+						mp.def = def.clone();
+					}
+				}
+				self.restart_process(name, &def.name).await
+			};
+			match result {
+				Ok(msg) => messages.push(msg),
+				Err(e) => return Some(Err(e)),
+			}
+		}
+		Some(Ok(messages.join("\n")))
+	}
+
+	/// Starts a replacement for a running process, waits for it to pass its readiness
+	/// probe, and only then stops the previous instance. If the replacement never
+	/// becomes ready it is discarded and the previous instance keeps running.
+	async fn replace_process(
+		self: &Arc<Self>,
+		service: &str,
+		dir: &std::path::Path,
+		def: ProcessDef,
+	) -> Result<String, SupervisorError> {
+		let process = def.name.clone();
+		let port = def.ready_port.ok_or_else(|| format!("{}/{}: no readiness probe", service, process))?;
+
+		let output = self.new_output(service, &def);
+		let (cancel_tx, cancel_rx) = tokio::sync::watch::channel(false);
+
+		let probe = cancel_rx.clone();
+		let (old_cancel, old_output, old_def, old_state, old_started_at) = {
+			let mut services = self.services.write().await;
+			let mp = services
+				.get_mut(service)
+				.and_then(|m| m.processes.get_mut(&process))
+				.ok_or_else(|| SupervisorError::NotRunning(format!("{}/{}", service, process)))?;
+			let previous = (
+				mp.cancel.replace(cancel_tx),
+				std::mem::replace(&mut mp.output, output.clone()),
+				std::mem::replace(&mut mp.def, def.clone()),
+				mp.state.clone(),
+				mp.started_at,
+			);
+			mp.retry_count = 0;
+			previous
+		};
+
+		let sup = Arc::clone(self);
+		let service_name = service.to_string();
+		let process_name = process.clone();
+		let new_def = def.clone();
+		let new_dir = dir.to_path_buf();
+		tokio::spawn(async move {
+			run_process_loop(sup, service_name, process_name, new_def, None, new_dir, output, cancel_rx).await;
+		});
+
+		let old_pid = match old_state {
+			ProcessState::Running { pid, .. } => Some(pid),
+			_ => None,
+		};
+		let ready = self.wait_ready(service, &process, &probe, old_pid, port, def.ready_timeout_secs).await;
+
+		let mut services = self.services.write().await;
+		let mp = services
+			.get_mut(service)
+			.and_then(|m| m.processes.get_mut(&process))
+			.ok_or_else(|| format!("{}/{}: removed during reload", service, process))?;
+
+		if ready {
+			// The old loop takes its process group down
+			if let Some(cancel) = old_cancel {
+				let _ = cancel.send(true);
+			}
+			Ok(format!("{}/{}: reloaded gracefully", service, process))
+		} else {
+			mp.stop_loop();
+			mp.cancel = old_cancel;
+			mp.output = old_output;
+			mp.def = old_def;
+			mp.state = old_state;
+			mp.started_at = old_started_at;
+			Err(SupervisorError::NotReady(format!(
+				"{}/{}: replacement not ready on port {} after {}s, keeping previous instance",
+				service, process, port, def.ready_timeout_secs
+			)))
+		}
+	}
+
+	/// Polls until the replacement started with `cancel` is running and its own
+	/// process group listens on `port`. The previous instance (`old_pid`) may
+	/// still hold the port, so a connection alone says nothing about the
+	/// replacement.
+	async fn wait_ready(
+		&self,
+		service: &str,
+		process: &str,
+		cancel: &tokio::sync::watch::Receiver<bool>,
+		old_pid: Option<u32>,
+		port: u16,
+		timeout_secs: u64,
+	) -> bool {
+		let deadline = Instant::now() + std::time::Duration::from_secs(timeout_secs);
+		while Instant::now() < deadline {
+			tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+			let pid = {
+				let services = self.services.read().await;
+				services.get(service).and_then(|m| m.processes.get(process)).and_then(|mp| match mp.state {
+					ProcessState::Running { pid, .. } if mp.owned_by(cancel) && Some(pid) != old_pid => Some(pid),
+					_ => None,
+				})
+			};
+			let Some(pid) = pid else { continue };
+			let ports = tokio::task::spawn_blocking(move || listening_ports_for_pids(&[pid])).await.unwrap_or_default();
+			if ports.get(&pid).is_some_and(|ports| ports.contains(&port)) {
+				return true;
+			}
+		}
+		false
+	}
+
+	pub async fn restart_process(self: &Arc<Self>, service: &str, process: &str) -> Result<String, SupervisorError> {
+		let entries = config::load_service_entries();
+		let entry = entries.get(service).ok_or_else(|| SupervisorError::UnknownService(service.to_string()))?;
+
+		let mut services = self.services.write().await;
+		let managed = services.get_mut(service).ok_or_else(|| SupervisorError::NotRunning(service.to_string()))?;
+		let mp = managed.processes.get_mut(process).ok_or_else(|| SupervisorError::process_not_found(service, process))?;
+
+		mp.stop_loop();
+		self.set_state(service, mp, ProcessState::Stopped { reason: StopReason::Manual });
+		mp.note_restart();
+		self.respawn(service, mp, &entry.dir);
+
+		Ok(format!("{}/{}: restarting", service, process))
+	}
+
+	/// Starts a fresh loop for a process that is not running.
+	fn respawn(self: &Arc<Self>, service: &str, mp: &mut ManagedProcess, dir: &std::path::Path) {
+		self.respawn_as(service, mp, dir, None);
+	}
+
+	/// Relaunches `mp`, running `command` instead of the configured one for the
+	/// first launch; restarts go back to `mp.def`.
+	fn respawn_as(self: &Arc<Self>, service: &str, mp: &mut ManagedProcess, dir: &std::path::Path, command: Option<String>) {
+		let def = mp.def.clone();
+		mp.stop_loop();
+		mp.retry_count = 0;
+		let output = self.new_output(service, &mp.def);
+		let (cancel_tx, cancel_rx) = tokio::sync::watch::channel(false);
+		mp.output = output.clone();
+		mp.cancel = Some(cancel_tx);
+		self.spawn_loop(service, def, command, dir, output, cancel_rx);
+	}
+
+	/// Starts a single process. A service that isn't managed yet is started with
+	/// just this process; otherwise the other processes are left alone. `command`
+	/// overrides the configured command for this launch only, and is an error
+	/// for a process that is already running.
+	pub async fn start_process(
+		self: &Arc<Self>,
+		service: &str,
+		process: &str,
+		command: Option<&str>,
+	) -> Result<String, SupervisorError> {
+		let entries = config::load_service_entries();
+		let entry = entries.get(service).ok_or_else(|| SupervisorError::UnknownService(service.to_string()))?;
+
+		let mut services = self.services.write().await;
+		let Some(managed) = services.get_mut(service) else {
+			drop(services);
+			self.start_entry(service, entry, false, &[process.to_string()], command).await?;
+			return Ok(format!("{}/{}: starting", service, process));
+		};
+		let mp = managed.processes.get_mut(process).ok_or_else(|| SupervisorError::process_not_found(service, process))?;
+		if matches!(mp.state, ProcessState::Running { .. } | ProcessState::Crashed { .. }) {
+			// Reporting success would hide that the command given wasn't run
+			if command.is_some() {
+				return Err(SupervisorError::AlreadyRunning(format!("{}/{}", service, process)));
+			}
+			return Ok(format!("{}/{}: already running", service, process));
+		}
+		self.respawn_as(service, mp, &entry.dir, command.map(str::to_string));
+		Ok(format!("{}/{}: starting", service, process))
+	}
+
+	/// Stops a single process. The service is dropped once nothing in it is running.
+	pub async fn stop_process(self: &Arc<Self>, service: &str, process: &str) -> Result<String, SupervisorError> {
+		let mut services = self.services.write().await;
+		let managed = services.get_mut(service).ok_or_else(|| SupervisorError::NotRunning(service.to_string()))?;
+		let mp = managed.processes.get_mut(process).ok_or_else(|| SupervisorError::process_not_found(service, process))?;
+		if !matches!(mp.state, ProcessState::Running { .. } | ProcessState::Crashed { .. }) {
+			return Ok(format!("{}/{}: already stopped", service, process));
+		}
+
+		mp.stop_loop();
+		self.set_state(service, mp, ProcessState::Stopped { reason: StopReason::Manual });
+
+		if !managed.processes.values().any(|p| p.state.is_running()) {
+			services.remove(service);
+		}
+		Ok(format!("{}/{}: stopped", service, process))
+	}
+
+	/// Takes a process out of service, then stops it: runs its `drain.run` command
+	/// while the process keeps serving, waits `drain.wait_secs`, and stops it. A
+	/// failing drain command leaves the process running.
+	pub async fn drain_process(self: &Arc<Self>, service: &str, process: &str) -> Result<String, SupervisorError> {
+		let (def, output, dir) = {
+			let services = self.services.read().await;
+			let managed = services.get(service).ok_or_else(|| SupervisorError::not_managed(service))?;
+			let mp = managed.processes.get(process).ok_or_else(|| SupervisorError::process_not_found(service, process))?;
+			if !mp.state.is_running() {
+				return Err(SupervisorError::NotRunning(format!("{}/{}", service, process)));
+			}
+			(mp.def.clone(), mp.output.clone(), managed.dir.clone())
+		};
+		let drain = def.drain.clone().ok_or_else(|| format!("{}/{}: no drain configured", service, process))?;
+
+		if let Some(run) = &drain.run {
+			output.write(format!("[ubermind] {}/{} draining: {}\n", service, process, run).as_bytes()).await;
+			let mut cmd = Command::new("sh");
+			cmd.args(["-c", run]).current_dir(&dir).stdin(Stdio::null()).kill_on_drop(true);
+			cmd.envs(crate::secrets::resolve_env_async(&def.env).await?);
+			let result = match tokio::time::timeout(DRAIN_RUN_TIMEOUT, cmd.output()).await {
+				Ok(Ok(result)) => result,
+				Ok(Err(e)) => return Err(format!("{}/{}: drain command failed to start: {}, still running", service, process, e).into()),
+				Err(_) => {
+					return Err(format!(
+						"{}/{}: drain command timed out after {}s, still running",
+						service,
+						process,
+						DRAIN_RUN_TIMEOUT.as_secs()
+					)
+					.into())
+				}
+			};
+			output.write(&result.stdout).await;
+			output.write(&result.stderr).await;
+			if !result.status.success() {
+				let code = result.status.code().unwrap_or(-1);
+				output.write(format!("[ubermind] {}/{} drain command failed (exit {})\n", service, process, code).as_bytes()).await;
+				return Err(format!("{}/{}: drain command failed (exit {}), still running", service, process, code).into());
+			}
+		}
+
+		if drain.wait_secs > 0 {
+			output.write(format!("[ubermind] {}/{} drained, stopping in {}s\n", service, process, drain.wait_secs).as_bytes()).await;
+			tokio::time::sleep(std::time::Duration::from_secs(drain.wait_secs)).await;
+		}
+		self.stop_process(service, process).await?;
+		Ok(format!("{}/{}: drained and stopped", service, process))
+	}
+
+	/// Reloads a single process by signalling it when a `reload_signal` is configured,
+	/// falling back to a full restart otherwise. With `drain_tasks`, a running task
+	/// first gets up to that long to finish on its own.
+	pub async fn reload_in_place(
+		self: &Arc<Self>,
+		service: &str,
+		process: &str,
+		drain_tasks: Option<std::time::Duration>,
+	) -> Result<String, SupervisorError> {
+		if let Some(timeout) = drain_tasks {
+			self.await_tasks(service, Some(process), timeout).await;
+		}
+		let target = {
+			let services = self.services.read().await;
+			let managed = services.get(service).ok_or_else(|| SupervisorError::NotRunning(service.to_string()))?;
+			let mp = managed.processes.get(process).ok_or_else(|| SupervisorError::process_not_found(service, process))?;
+			match (&mp.state, &mp.def.reload_signal) {
+				(ProcessState::Running { pid, .. }, Some(signal)) => Some((*pid, signal.clone())),
+				_ => None,
+			}
+		};
+
+		let Some((pid, signal_name)) = target else {
+			return self.restart_process(service, process).await;
+		};
+
+		use nix::sys::signal::killpg;
+		use nix::unistd::Pid;
+		let signal = config::parse_signal(&signal_name)?;
+		killpg(Pid::from_raw(pid as i32), signal)
+			.map_err(|e| format!("{}/{}: failed to send {}: {}", service, process, signal_name, e))?;
+		Ok(format!("{}/{}: sent {}", service, process, signal_name))
+	}
+
+	pub async fn kill_process(self: &Arc<Self>, service: &str, process: &str) -> Result<String, SupervisorError> {
+		let mut services = self.services.write().await;
+		let managed = services.get_mut(service).ok_or_else(|| SupervisorError::NotRunning(service.to_string()))?;
+		let mp = managed.processes.get_mut(process).ok_or_else(|| SupervisorError::process_not_found(service, process))?;
+
+		mp.stop_loop();
+		self.set_state(service, mp, ProcessState::Stopped { reason: StopReason::Manual });
+
+		Ok(format!("{}/{}: killed", service, process))
+	}
+
+	/// Forgets completed/failed task outcomes. A service with nothing left running is
+	/// dropped entirely so it reverts to its configured state.
+	pub async fn clear_task_records(&self, name: &str) -> Result<String, SupervisorError> {
+		let mut services = self.services.write().await;
+		let managed = services.get_mut(name).ok_or_else(|| format!("{}: nothing to clear", name))?;
+
+		let mut cleared = 0;
+		for mp in managed.processes.values_mut() {
+			if mp.def.service_type == ServiceType::Task && mp.state.is_task_record() {
+				mp.state = ProcessState::Stopped { reason: StopReason::NotStarted };
+				cleared += 1;
+			}
+		}
+
+		if !managed.processes.values().any(|mp| mp.state.is_running()) {
+			services.remove(name);
+		}
+		Ok(format!("{}: cleared {} task record(s)", name, cleared))
+	}
+
+	fn new_output(&self, service: &str, def: &ProcessDef) -> OutputCapture {
+		if def.capture {
+			let output = OutputCapture::new(service, def, &self.config.logs);
+			if self.attach.iter().any(|(s, p)| s == service && p == &def.name) {
+				output.tee_stdout()
+			} else {
+				output
+			}
+		} else {
+			OutputCapture::disabled()
+		}
+	}
+
+	pub async fn get_output(&self, service: &str, process: Option<&str>) -> Result<OutputCapture, SupervisorError> {
+		let services = self.services.read().await;
+		let managed = services.get(service).ok_or_else(|| SupervisorError::not_managed(service))?;
+
+		if let Some(proc_name) = process {
+			let mp = managed.processes.get(proc_name).ok_or_else(|| SupervisorError::process_not_found(service, proc_name))?;
+			Ok(mp.output.clone())
+		} else {
+			managed
+				.processes
+				.values()
+				.next()
+				.map(|mp| mp.output.clone())
+				.ok_or_else(|| format!("{}: no processes", service).into())
+		}
+	}
+
+	/// The terminal of a running `pty = true` process and its output, for `ub connect`.
+	pub async fn terminal(&self, service: &str, process: &str) -> Result<(Arc<std::fs::File>, OutputCapture), SupervisorError> {
+		let services = self.services.read().await;
+		let managed = services.get(service).ok_or_else(|| SupervisorError::not_managed(service))?;
+		let mp = managed.processes.get(process).ok_or_else(|| SupervisorError::process_not_found(service, process))?;
+		if !mp.def.pty {
+			return Err(format!("{}/{}: no terminal to connect to (set pty = true)", service, process).into());
+		}
+		match (&mp.state, &mp.terminal) {
+			(ProcessState::Running { .. }, Some(master)) => Ok((Arc::clone(master), mp.output.clone())),
+			_ => Err(SupervisorError::NotRunning(format!("{}/{}", service, process))),
+		}
+	}
+
+	/// Live output subscribers of every managed process, keyed `service.process`.
+	pub async fn subscriber_counts(&self) -> BTreeMap<String, usize> {
+		let services = self.services.read().await;
+		services
+			.iter()
+			.flat_map(|(name, managed)| {
+				managed.processes.iter().map(move |(pname, mp)| (format!("{}.{}", name, pname), mp.output.subscriber_count()))
+			})
+			.collect()
+	}
+
+	pub async fn get_all_outputs(&self, service: &str) -> Result<Vec<(String, OutputCapture)>, SupervisorError> {
+		let services = self.services.read().await;
+		let managed = services.get(service).ok_or_else(|| SupervisorError::not_managed(service))?;
+		Ok(managed
+			.processes
+			.iter()
+			.map(|(name, mp)| (name.clone(), mp.output.clone()))
+			.collect())
 	}
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn run_process_loop(
 	supervisor: Arc<Supervisor>,
 	service: String,
 	process: String,
 	def: ProcessDef,
+	mut command: Option<String>,
 	dir: std::path::PathBuf,
 	output: OutputCapture,
 	mut cancel: tokio::sync::watch::Receiver<bool>,
 ) {
 	let mut retry_count: u32 = 0;
+	let mut crashes: VecDeque<std::time::Instant> = VecDeque::new();
+	let mut jitter = Jitter::seeded(&service, &process);
+
+	// Looked up once for the loop; restarts reuse the resolved env
+	let def = match crate::secrets::resolve_env_async(&def.env).await {
+		Ok(env) => ProcessDef { env, ..def },
+		Err(e) => {
+			let msg = format!("[ubermind] failed to spawn {}/{}: {}\n", service, process, e);
+			output.write(msg.as_bytes()).await;
+			update_state(&supervisor, &service, &process, &cancel, ProcessState::Failed { exit_code: -1, finished_at: unix_now() }).await;
+			return;
+		}
+	};
+
+	if def.nice.is_some_and(|n| n < 0) && !nix::unistd::geteuid().is_root() {
+		let msg = format!(
+			"[ubermind] warning: {}/{}: raising priority (nice {}) needs root; it will run at the default priority\n",
+			service,
+			process,
+			def.nice.unwrap_or_default()
+		);
+		output.write(msg.as_bytes()).await;
+	}
 
 	loop {
-		if *cancel.borrow() {
+		if *cancel.borrow() || !owns_process(&supervisor, &service, &process, &cancel).await {
 			return;
 		}
 
-		let child = spawn_process(&def, &dir).await;
-		let mut child = match child {
-			Ok(c) => c,
+		// A one-off command only replaces the first launch; restarts run the configured one
+		let spawned = match command.take() {
+			Some(command) => supervisor.spawner.spawn(&ProcessDef { command, ..def.clone() }, &dir),
+			None => supervisor.spawner.spawn(&def, &dir),
+		};
+		let Spawned { mut child, argv, terminal: pty } = match spawned {
+			Ok(spawned) => spawned,
 			Err(e) => {
 				let msg = format!("[ubermind] failed to spawn {}/{}: {}\n", service, process, e);
 				output.write(msg.as_bytes()).await;
-				update_state(&supervisor, &service, &process, ProcessState::Failed { exit_code: -1 }).await;
+				update_state(&supervisor, &service, &process, &cancel, ProcessState::Failed { exit_code: -1, finished_at: unix_now() }).await;
 				return;
 			}
 		};
+		let resolved = ResolvedCommand { argv, cwd: dir.clone() };
+		let terminal = pty.as_ref().and_then(|master| master.try_clone().ok()).map(Arc::new);
+		with_owned(&supervisor, &service, &process, &cancel, |mp| {
+			mp.resolved = Some(resolved);
+			mp.terminal = terminal;
+		})
+		.await;
 
-		let pid = child.id().unwrap_or(0) as u32;
-		let started_at = Instant::now();
+		let pid = child.id().unwrap_or(0);
+		let spawned_at = std::time::Instant::now();
+		let oom_kills_before = oom_kill_count();
 		update_state(
 			&supervisor,
 			&service,
 			&process,
+			&cancel,
 			ProcessState::Running {
 				pid,
 				uptime_secs: 0,
@@ -343,53 +1612,66 @@ async fn run_process_loop(
 		if let Some(stdout) = child.stdout.take() {
 			let out = output.clone();
 			tokio::spawn(async move {
-				pipe_output(stdout, out).await;
+				pipe_output(stdout, out, Stream::Stdout).await;
 			});
 		}
 		if let Some(stderr) = child.stderr.take() {
 			let out = output.clone();
 			tokio::spawn(async move {
-				pipe_output(stderr, out).await;
+				pipe_output(stderr, out, Stream::Stderr).await;
+			});
+		}
+		// A terminal merges both streams; reads fail once the process is gone
+		if let Some(master) = pty {
+			let out = output.clone();
+			tokio::spawn(async move {
+				pipe_output(tokio::fs::File::from_std(master), out, Stream::Stdout).await;
 			});
 		}
-
-		let sup_clone = Arc::clone(&supervisor);
-		let svc = service.clone();
-		let proc_name = process.clone();
-		let cancel_clone = cancel.clone();
-		let uptime_handle = tokio::spawn(async move {
-			loop {
-				tokio::time::sleep(std::time::Duration::from_secs(1)).await;
-				if *cancel_clone.borrow() {
-					return;
-				}
-				let uptime = started_at.elapsed().as_secs();
-				update_state(
-					&sup_clone,
-					&svc,
-					&proc_name,
-					ProcessState::Running { pid, uptime_secs: uptime },
-				)
-				.await;
-			}
-		});
 
 		let exit_result = tokio::select! {
 			status = child.wait() => status,
 			_ = cancel.changed() => {
-				let _ = child.kill().await;
-				uptime_handle.abort();
+				kill_process_tree(pid, &def.stop_signals);
+				let _ = child.wait().await;
 				return;
 			}
 		};
-
-		uptime_handle.abort();
+
+		let oom = match &exit_result {
+			Ok(exit) => likely_oom(exit, oom_kills_before, oom_kill_count()),
+			Err(_) => Some(false),
+		};
+		let msg = match oom {
+			Some(true) => Some(format!("[ubermind] {}/{} likely OOM-killed\n", service, process)),
+			None => Some(format!("[ubermind] {}/{} killed by SIGKILL (OOM unknown)\n", service, process)),
+			Some(false) => None,
+		};
+		if let Some(msg) = msg {
+			output.write(msg.as_bytes()).await;
+		}
+		let oom = oom == Some(true);
+		with_owned(&supervisor, &service, &process, &cancel, |mp| {
+			mp.oom_killed = oom;
+			mp.terminal = None;
+		})
+		.await;
 
 		match exit_result {
-			Ok(exit) if exit.success() => {
-				let msg = format!("[ubermind] {}/{} exited cleanly\n", service, process);
+			Ok(exit) if exit.code().is_some_and(|code| def.is_success(code)) => {
+				let code = exit.code().unwrap_or(0);
+				let msg = if code == 0 {
+					format!("[ubermind] {}/{} exited cleanly\n", service, process)
+				} else {
+					format!("[ubermind] {}/{} exited cleanly (exit {})\n", service, process, code)
+				};
 				output.write(msg.as_bytes()).await;
-				update_state(&supervisor, &service, &process, ProcessState::Stopped).await;
+				let state = if def.service_type == ServiceType::Task {
+					ProcessState::Completed { exit_code: code, finished_at: unix_now() }
+				} else {
+					ProcessState::Stopped { reason: StopReason::CleanExit }
+				};
+				update_state(&supervisor, &service, &process, &cancel, state).await;
 				return;
 			}
 			Ok(exit) => {
@@ -399,38 +1681,56 @@ async fn run_process_loop(
 				if def.service_type == ServiceType::Task {
 					let msg = format!("[ubermind] {}/{} failed (exit {})\n", service, process, code);
 					output.write(msg.as_bytes()).await;
-					update_state(&supervisor, &service, &process, ProcessState::Failed { exit_code: code }).await;
+					update_state(&supervisor, &service, &process, &cancel, ProcessState::Failed { exit_code: code, finished_at: unix_now() }).await;
 					return;
 				}
 
-				retry_count += 1;
+				// A process that had been up long enough is no longer crash-looping
+				if def.healthy_after_secs.is_some_and(|secs| spawned_at.elapsed().as_secs() >= secs) {
+					retry_count = 0;
+					crashes.clear();
+				}
+				retry_count = match def.max_retries_window_secs {
+					Some(window) => {
+						let window = std::time::Duration::from_secs(window);
+						recent_crashes(&mut crashes, std::time::Instant::now(), window)
+					}
+					None => retry_count + 1,
+				};
 
 				if def.restart && retry_count <= def.max_retries {
+					let within = def.max_retries_window_secs.map(|w| format!(" in {}s", w)).unwrap_or_default();
 					let msg = format!(
-						"[ubermind] {}/{} crashed (exit {}), restarting ({}/{})\n",
-						service, process, code, retry_count, def.max_retries
+						"[ubermind] {}/{} crashed (exit {}), restarting ({}/{}{})\n",
+						service, process, code, retry_count, def.max_retries, within
 					);
 					output.write(msg.as_bytes()).await;
 					update_state(
 						&supervisor,
 						&service,
 						&process,
+						&cancel,
 						ProcessState::Crashed { exit_code: code, retries: retry_count },
 					)
 					.await;
-					tokio::time::sleep(std::time::Duration::from_secs(def.restart_delay_secs)).await;
+					tokio::time::sleep(jitter.stretch(restart_delay(&def, retry_count), def.restart_jitter)).await;
+					with_owned(&supervisor, &service, &process, &cancel, ManagedProcess::note_restart).await;
 					continue;
 				} else {
-					let msg = format!(
-						"[ubermind] {}/{} failed (exit {}), max retries exceeded\n",
-						service, process, code
-					);
+					let msg = match def.max_retries_window_secs {
+						Some(window) => format!(
+							"[ubermind] {}/{} failed (exit {}), crashed {} times in {}s\n",
+							service, process, code, retry_count, window
+						),
+						None => format!("[ubermind] {}/{} failed (exit {}), max retries exceeded\n", service, process, code),
+					};
 					output.write(msg.as_bytes()).await;
 					update_state(
 						&supervisor,
 						&service,
 						&process,
-						ProcessState::Failed { exit_code: code },
+						&cancel,
+						ProcessState::Failed { exit_code: code, finished_at: unix_now() },
 					)
 					.await;
 					return;
@@ -439,52 +1739,395 @@ async fn run_process_loop(
 			Err(e) => {
 				let msg = format!("[ubermind] {}/{} error: {}\n", service, process, e);
 				output.write(msg.as_bytes()).await;
-				update_state(&supervisor, &service, &process, ProcessState::Failed { exit_code: -1 }).await;
+				update_state(&supervisor, &service, &process, &cancel, ProcessState::Failed { exit_code: -1, finished_at: unix_now() }).await;
+				return;
+			}
+		}
+	}
+}
+
+/// Watches a process this daemon didn't spawn. It can't be waited on, so it is
+/// polled; the exit status is unknown.
+#[allow(clippy::too_many_arguments)]
+async fn run_adopted_loop(
+	supervisor: Arc<Supervisor>,
+	service: String,
+	process: String,
+	def: ProcessDef,
+	dir: std::path::PathBuf,
+	pid: u32,
+	output: OutputCapture,
+	mut cancel: tokio::sync::watch::Receiver<bool>,
+) {
+	loop {
+		tokio::select! {
+			_ = cancel.changed() => {
+				kill_process_tree(pid, &def.stop_signals);
 				return;
 			}
+			_ = tokio::time::sleep(std::time::Duration::from_secs(1)) => {}
+		}
+		if nix::sys::signal::kill(nix::unistd::Pid::from_raw(pid as i32), None).is_err() {
+			break;
+		}
+	}
+
+	let msg = format!("[ubermind] {}/{} (adopted pid {}) exited\n", service, process, pid);
+	output.write(msg.as_bytes()).await;
+	if def.restart {
+		run_process_loop(supervisor, service, process, def, None, dir, output, cancel).await;
+	} else {
+		update_state(&supervisor, &service, &process, &cancel, ProcessState::Failed { exit_code: -1, finished_at: unix_now() }).await;
+	}
+}
+
+/// Longest a `drain.run` command may take before the drain is abandoned.
+const DRAIN_RUN_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// Longest an `on_failure` command may run before it is killed.
+const ON_FAILURE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(60);
+
+fn spawn_process(def: &ProcessDef, dir: &std::path::Path) -> Result<Spawned, String> {
+	let mut cmd = shell_command(def, dir);
+	let terminal = if def.pty {
+		Some(attach_pty(&mut cmd)?)
+	} else {
+		cmd.process_group(0);
+		if def.capture {
+			cmd.stdout(Stdio::piped()).stderr(Stdio::piped());
+		} else {
+			cmd.stdout(Stdio::null()).stderr(Stdio::null());
 		}
+		None
+	};
+	let argv = command_argv(&cmd);
+	let child = cmd.spawn().map_err(|e| format!("spawn failed: {}", e))?;
+	Ok(Spawned { child, argv, terminal })
+}
+
+/// Makes `cmd` start as the leader of a new session whose controlling
+/// terminal is a fresh pseudo-terminal, so it sees a tty on stdin, stdout and
+/// stderr, and returns the master side. The session also makes it a process
+/// group leader, as for a plain spawn, so `cmd` must not also set one.
+pub fn attach_pty(cmd: &mut Command) -> Result<std::fs::File, String> {
+	let size = nix::pty::Winsize { ws_row: 24, ws_col: 80, ws_xpixel: 0, ws_ypixel: 0 };
+	let pty = nix::pty::openpty(Some(&size), None).map_err(|e| format!("spawn failed: no pty: {}", e))?;
+	let slave = std::fs::File::from(pty.slave);
+	let stdio = || slave.try_clone().map(Stdio::from).map_err(|e| format!("spawn failed: {}", e));
+
+	if !cmd.as_std().get_envs().any(|(key, _)| key == "TERM") {
+		cmd.env("TERM", "xterm-256color");
 	}
+	cmd.stdin(stdio()?).stdout(stdio()?).stderr(stdio()?);
+	// SAFETY: setsid and ioctl are async-signal-safe. stdin is the slave, which
+	// becomes the new session's controlling terminal.
+	unsafe {
+		cmd.pre_exec(|| {
+			if libc::setsid() < 0 || libc::ioctl(0, libc::TIOCSCTTY as _, 0) < 0 {
+				return Err(std::io::Error::last_os_error());
+			}
+			Ok(())
+		});
+	}
+	Ok(std::fs::File::from(pty.master))
+}
+
+/// Sets the size of a terminal; its foreground process group gets SIGWINCH.
+pub fn resize_terminal(master: &std::fs::File, rows: u16, cols: u16) -> Result<(), String> {
+	use std::os::fd::AsRawFd;
+	let size = libc::winsize { ws_row: rows, ws_col: cols, ws_xpixel: 0, ws_ypixel: 0 };
+	// SAFETY: TIOCSWINSZ only reads the winsize we pass
+	if unsafe { libc::ioctl(master.as_raw_fd(), libc::TIOCSWINSZ, &size) } < 0 {
+		return Err(format!("resize failed: {}", std::io::Error::last_os_error()));
+	}
+	Ok(())
 }
 
-async fn spawn_process(def: &ProcessDef, dir: &std::path::Path) -> Result<Child, String> {
+/// `sh -c <command>` in `dir` with the process's env, niceness and umask applied.
+/// Secrets in `def.env` must already be resolved.
+fn shell_command(def: &ProcessDef, dir: &std::path::Path) -> Command {
 	let mut cmd = Command::new("sh");
-	cmd.args(["-c", &def.command])
-		.current_dir(dir)
-		.stdout(Stdio::piped())
-		.stderr(Stdio::piped())
-		.process_group(0);
+	cmd.args(["-c", &def.command]).current_dir(dir);
+
+	if def.env_clear {
+		cmd.env_clear();
+		// Without PATH `sh -c` can't find anything, so it stays unless env sets it
+		if let Some(path) = std::env::var_os("PATH").filter(|_| !def.env.contains_key("PATH")) {
+			cmd.env("PATH", path);
+		}
+	}
+	cmd.envs(&def.env);
+
+	if let Some(nice) = def.nice {
+		// SAFETY: setpriority is async-signal-safe; a failure (e.g. EACCES for a
+		// negative value without privileges) leaves the inherited priority.
+		unsafe {
+			cmd.pre_exec(move || {
+				libc::setpriority(libc::PRIO_PROCESS as _, 0, nice);
+				Ok(())
+			});
+		}
+	}
+
+	if let Some(mask) = def.umask {
+		// SAFETY: umask is async-signal-safe and cannot fail.
+		unsafe {
+			cmd.pre_exec(move || {
+				libc::umask(mask as libc::mode_t);
+				Ok(())
+			});
+		}
+	}
+
+	cmd
+}
+
+/// The fields that differ between two definitions of a process, compared as
+/// JSON so every field is covered. Env is compared per variable.
+fn diff_defs(running: &ProcessDef, configured: &ProcessDef) -> Vec<(String, String, String)> {
+	let (Ok(serde_json::Value::Object(old)), Ok(serde_json::Value::Object(new))) =
+		(serde_json::to_value(running), serde_json::to_value(configured))
+	else {
+		return vec![];
+	};
+	let show = |value: Option<&serde_json::Value>| value.map_or("(unset)".to_string(), |v| v.to_string());
+	let mut fields = Vec::new();
+	for (key, value) in &new {
+		if key == "env" {
+			let keys: std::collections::BTreeSet<&String> = running.env.keys().chain(configured.env.keys()).collect();
+			for var in keys {
+				let (before, after) = (running.env.get(var), configured.env.get(var));
+				if before != after {
+					let quote = |v: Option<&String>| v.map_or("(unset)".to_string(), |v| format!("{:?}", v));
+					fields.push((format!("env.{}", var), quote(before), quote(after)));
+				}
+			}
+		} else if old.get(key) != Some(value) {
+			fields.push((key.clone(), show(old.get(key)), show(Some(value))));
+		}
+	}
+	fields
+}
+
+/// Spreads restart delays so processes that crash together don't all restart
+/// in lockstep. A splitmix64 generator, seeded per process loop.
+struct Jitter(u64);
+
+impl Jitter {
+	fn seeded(service: &str, process: &str) -> Self {
+		use std::hash::{Hash, Hasher};
+		let mut hasher = std::collections::hash_map::DefaultHasher::new();
+		(service, process, std::process::id()).hash(&mut hasher);
+		let nanos = std::time::SystemTime::now()
+			.duration_since(std::time::UNIX_EPOCH)
+			.map(|d| d.subsec_nanos())
+			.unwrap_or(0);
+		Self(hasher.finish() ^ nanos as u64)
+	}
+
+	/// Uniform in 0..1.
+	fn next_fraction(&mut self) -> f64 {
+		self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+		let mut z = self.0;
+		z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+		z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+		z ^= z >> 31;
+		(z >> 11) as f64 / (1u64 << 53) as f64
+	}
+
+	/// `delay` plus a random 0..`jitter` fraction of it.
+	fn stretch(&mut self, delay: std::time::Duration, jitter: f64) -> std::time::Duration {
+		if jitter <= 0.0 {
+			return delay;
+		}
+		delay.mul_f64(1.0 + self.next_fraction() * jitter)
+	}
+}
 
-	for (key, val) in &def.env {
-		cmd.env(key, val);
+/// Health of a running process with a readiness port: whether the port
+/// accepts a connection right now.
+async fn probe_port(port: u16) -> Health {
+	let connect = tokio::net::TcpStream::connect(("127.0.0.1", port));
+	match tokio::time::timeout(std::time::Duration::from_millis(250), connect).await {
+		Ok(Ok(_)) => Health::Healthy,
+		_ => Health::Unhealthy,
 	}
+}
 
-	cmd.spawn().map_err(|e| format!("spawn failed: {}", e))
+/// Longest backoff grows a restart delay to, unless `restart_delay` itself is longer.
+const MAX_RESTART_DELAY_SECS: f64 = 300.0;
+
+/// The wait before restart number `retry` (from 1): `restart_delay_secs`,
+/// multiplied by `restart_backoff` for each restart before it.
+fn restart_delay(def: &ProcessDef, retry: u32) -> std::time::Duration {
+	let base = def.restart_delay_secs as f64;
+	let grown = base * def.restart_backoff.powi(retry.saturating_sub(1).min(64) as i32);
+	std::time::Duration::from_secs_f64(grown.min(MAX_RESTART_DELAY_SECS.max(base)))
+}
+
+/// Records a crash at `now` and returns how many crashes fall within `window`.
+fn recent_crashes(crashes: &mut VecDeque<std::time::Instant>, now: std::time::Instant, window: std::time::Duration) -> u32 {
+	crashes.push_back(now);
+	while crashes.front().is_some_and(|&at| now.duration_since(at) > window) {
+		crashes.pop_front();
+	}
+	crashes.len() as u32
 }
 
-async fn pipe_output<R: tokio::io::AsyncRead + Unpin>(mut reader: R, output: OutputCapture) {
+async fn pipe_output<R: tokio::io::AsyncRead + Unpin>(mut reader: R, output: OutputCapture, stream: Stream) {
 	let mut buf = [0u8; 4096];
 	loop {
 		match reader.read(&mut buf).await {
 			Ok(0) => break,
-			Ok(n) => output.write(&buf[..n]).await,
+			Ok(n) => output.write_stream(stream, &buf[..n]).await,
 			Err(_) => break,
 		}
 	}
 }
 
-async fn update_state(supervisor: &Arc<Supervisor>, service: &str, process: &str, state: ProcessState) {
+async fn update_state(
+	supervisor: &Arc<Supervisor>,
+	service: &str,
+	process: &str,
+	cancel: &tokio::sync::watch::Receiver<bool>,
+	state: ProcessState,
+) {
 	let mut services = supervisor.services.write().await;
 	if let Some(managed) = services.get_mut(service) {
+		let dir = managed.dir.clone();
 		if let Some(mp) = managed.processes.get_mut(process) {
-			mp.state = state;
+			if mp.owned_by(cancel) {
+				if let (ProcessState::Failed { exit_code, .. }, Some(run)) = (&state, &mp.def.on_failure) {
+					tokio::spawn(run_on_failure(
+						service.to_string(),
+						process.to_string(),
+						*exit_code,
+						run.clone(),
+						dir,
+						mp.def.env.clone(),
+						mp.output.clone(),
+					));
+				}
+				supervisor.set_state(service, mp, state);
+			}
+		}
+	}
+}
+
+/// Runs a process's `on_failure` command, logging its output line by line
+/// under an `[on_failure]` prefix.
+async fn run_on_failure(
+	service: String,
+	process: String,
+	exit_code: i32,
+	run: String,
+	dir: std::path::PathBuf,
+	env: HashMap<String, String>,
+	output: OutputCapture,
+) {
+	let mut cmd = Command::new("sh");
+	cmd.args(["-c", &run]).current_dir(&dir).stdin(Stdio::null()).kill_on_drop(true);
+	match crate::secrets::resolve_env_async(&env).await {
+		Ok(env) => {
+			cmd.envs(env);
+		}
+		Err(e) => {
+			output.write(format!("[on_failure] not run: {}\n", e).as_bytes()).await;
+			return;
+		}
+	}
+	cmd.env("UBERMIND_SERVICE", &service)
+		.env("UBERMIND_PROCESS", &process)
+		.env("UBERMIND_EXIT_CODE", exit_code.to_string());
+
+	let result = match tokio::time::timeout(ON_FAILURE_TIMEOUT, cmd.output()).await {
+		Ok(Ok(result)) => result,
+		Ok(Err(e)) => {
+			output.write(format!("[on_failure] failed to start: {}\n", e).as_bytes()).await;
+			return;
+		}
+		Err(_) => {
+			let msg = format!("[on_failure] timed out after {}s\n", ON_FAILURE_TIMEOUT.as_secs());
+			output.write(msg.as_bytes()).await;
+			return;
+		}
+	};
+	for data in [&result.stdout, &result.stderr] {
+		for line in String::from_utf8_lossy(data).lines() {
+			output.write(format!("[on_failure] {}\n", line).as_bytes()).await;
+		}
+	}
+	if !result.status.success() {
+		let msg = format!("[on_failure] exited {}\n", result.status.code().unwrap_or(-1));
+		output.write(msg.as_bytes()).await;
+	}
+}
+
+/// Applies `f` to the process if `cancel` still belongs to its current loop.
+async fn with_owned(
+	supervisor: &Arc<Supervisor>,
+	service: &str,
+	process: &str,
+	cancel: &tokio::sync::watch::Receiver<bool>,
+	f: impl FnOnce(&mut ManagedProcess),
+) {
+	let mut services = supervisor.services.write().await;
+	if let Some(mp) = services.get_mut(service).and_then(|m| m.processes.get_mut(process)) {
+		if mp.owned_by(cancel) {
+			f(mp);
 		}
 	}
 }
 
+/// Whether an exit was the OOM killer: an exit by SIGKILL that ubermind didn't
+/// send (a stop cancels the loop before killing), confirmed by the cgroup's
+/// `oom_kill` counter going up across the process's lifetime. `sh -c` reports a
+/// killed child as 137. None for a SIGKILL when the counter can't be read (off
+/// Linux, or without cgroup v2), since a plain `kill -9` looks the same.
+fn likely_oom(exit: &std::process::ExitStatus, kills_before: Option<u64>, kills_after: Option<u64>) -> Option<bool> {
+	use std::os::unix::process::ExitStatusExt;
+	let sigkill = exit.signal() == Some(libc::SIGKILL) || exit.code() == Some(128 + libc::SIGKILL);
+	if !sigkill {
+		return Some(false);
+	}
+	Some(kills_after? > kills_before?)
+}
+
+/// OOM kills so far in the daemon's cgroup (cgroup v2 `memory.events`); children
+/// inherit the cgroup, so a rise across a process's lifetime points at it.
+#[cfg(target_os = "linux")]
+fn oom_kill_count() -> Option<u64> {
+	let cgroup = std::fs::read_to_string("/proc/self/cgroup").ok()?;
+	let path = cgroup.lines().find_map(|l| l.strip_prefix("0::"))?;
+	let events = std::fs::read_to_string(format!("/sys/fs/cgroup{}/memory.events", path.trim_end_matches('/'))).ok()?;
+	events
+		.lines()
+		.find_map(|l| l.strip_prefix("oom_kill "))
+		.and_then(|n| n.trim().parse().ok())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn oom_kill_count() -> Option<u64> {
+	None
+}
+
+async fn owns_process(
+	supervisor: &Arc<Supervisor>,
+	service: &str,
+	process: &str,
+	cancel: &tokio::sync::watch::Receiver<bool>,
+) -> bool {
+	let services = supervisor.services.read().await;
+	services
+		.get(service)
+		.and_then(|m| m.processes.get(process))
+		.is_some_and(|mp| mp.owned_by(cancel))
+}
+
 #[cfg(target_os = "macos")]
 fn listening_ports_for_pids(target_pids: &[u32]) -> HashMap<u32, Vec<u16>> {
-	use libproc::processes::{pids_by_type, ProcFilter};
 	use netstat2::*;
+	use nix::unistd::{getpgid, Pid};
 
 	let af = AddressFamilyFlags::IPV4 | AddressFamilyFlags::IPV6;
 	let proto = ProtocolFlags::TCP;
@@ -493,58 +2136,622 @@ fn listening_ports_for_pids(target_pids: &[u32]) -> HashMap<u32, Vec<u16>> {
 		Err(_) => return HashMap::new(),
 	};
 
-	let mut all_ports: HashMap<u32, Vec<u16>> = HashMap::new();
+	// Each supervised process leads its own process group, so grouping the
+	// listeners by pgid covers children (e.g. a server started by `npm run`)
+	// with one getpgid per listening pid instead of a process scan per target.
+	let mut pgids: HashMap<u32, Option<u32>> = HashMap::new();
+	let mut group_ports: HashMap<u32, Vec<u16>> = HashMap::new();
 	for si in &sockets {
 		if let ProtocolSocketInfo::Tcp(ref tcp) = si.protocol_socket_info {
 			if tcp.state == TcpState::Listen {
 				for pid in &si.associated_pids {
-					let ports = all_ports.entry(*pid).or_default();
-					if !ports.contains(&tcp.local_port) {
-						ports.push(tcp.local_port);
+					let pgid = *pgids.entry(*pid).or_insert_with(|| {
+						getpgid(Some(Pid::from_raw(*pid as i32))).ok().map(|p| p.as_raw() as u32)
+					});
+					for owner in [Some(*pid), pgid].into_iter().flatten() {
+						let ports = group_ports.entry(owner).or_default();
+						if !ports.contains(&tcp.local_port) {
+							ports.push(tcp.local_port);
+						}
 					}
 				}
 			}
 		}
 	}
 
-	let mut result: HashMap<u32, Vec<u16>> = HashMap::new();
-	for &pid in target_pids {
-		if let Some(ports) = all_ports.get(&pid) {
-			result.insert(pid, ports.clone());
+	target_pids
+		.iter()
+		.filter_map(|pid| {
+			let mut ports = group_ports.get(pid)?.clone();
+			ports.sort();
+			Some((*pid, ports))
+		})
+		.collect()
+}
+
+#[cfg(target_os = "linux")]
+fn listening_ports_for_pids(target_pids: &[u32]) -> HashMap<u32, Vec<u16>> {
+	// Listening TCP sockets by inode: hex local address:port, state 0A, inode tenth
+	let mut listeners: HashMap<String, u16> = HashMap::new();
+	for table in ["/proc/net/tcp", "/proc/net/tcp6"] {
+		let Ok(content) = std::fs::read_to_string(table) else { continue };
+		for line in content.lines().skip(1) {
+			let fields: Vec<&str> = line.split_whitespace().collect();
+			if fields.len() < 10 || fields[3] != "0A" {
+				continue;
+			}
+			if let Some(port) = fields[1].rsplit(':').next().and_then(|p| u16::from_str_radix(p, 16).ok()) {
+				listeners.insert(fields[9].to_string(), port);
+			}
+		}
+	}
+	if listeners.is_empty() {
+		return HashMap::new();
+	}
+
+	// As on macOS, a target owns its own listeners and those of its process group
+	let mut group_ports: HashMap<u32, Vec<u16>> = HashMap::new();
+	let Ok(procs) = std::fs::read_dir("/proc") else {
+		return group_ports;
+	};
+	for entry in procs.flatten() {
+		let Some(pid) = entry.file_name().to_str().and_then(|name| name.parse::<u32>().ok()) else {
+			continue;
+		};
+		let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).unwrap_or_default();
+		// After the parenthesised command: state, ppid, pgrp
+		let pgid = stat.rsplit(')').next().and_then(|rest| rest.split_whitespace().nth(2)).and_then(|p| p.parse::<u32>().ok());
+		let owners: Vec<u32> = [Some(pid), pgid].into_iter().flatten().filter(|p| target_pids.contains(p)).collect();
+		if owners.is_empty() {
 			continue;
 		}
-		let group_pids = pids_by_type(ProcFilter::ByProgramGroup { pgrpid: pid })
-			.unwrap_or_default();
-		let mut ports: Vec<u16> = Vec::new();
-		for gpid in &group_pids {
-			if let Some(p) = all_ports.get(gpid) {
-				for port in p {
-					if !ports.contains(port) {
-						ports.push(*port);
-					}
+		let Ok(fds) = std::fs::read_dir(format!("/proc/{}/fd", pid)) else { continue };
+		for fd in fds.flatten() {
+			let Ok(link) = std::fs::read_link(fd.path()) else { continue };
+			let link = link.to_string_lossy();
+			let Some(port) = link.strip_prefix("socket:[").and_then(|s| s.strip_suffix(']')).and_then(|inode| listeners.get(inode))
+			else {
+				continue;
+			};
+			for owner in &owners {
+				let ports = group_ports.entry(*owner).or_default();
+				if !ports.contains(port) {
+					ports.push(*port);
 				}
 			}
 		}
-		if !ports.is_empty() {
-			ports.sort();
-			result.insert(pid, ports);
-		}
 	}
-	result
+	for ports in group_ports.values_mut() {
+		ports.sort();
+	}
+	group_ports
 }
 
-#[cfg(not(target_os = "macos"))]
+#[cfg(not(any(target_os = "macos", target_os = "linux")))]
 fn listening_ports_for_pids(_target_pids: &[u32]) -> HashMap<u32, Vec<u16>> {
 	HashMap::new()
 }
 
-fn kill_process_tree(pid: u32) {
-	use nix::sys::signal::{killpg, Signal};
+pub(crate) struct PsInfo {
+	pub pgid: u32,
+	pub elapsed_secs: u64,
+	pub args: String,
+}
+
+/// Process group, age and command line of `pid`, via ps.
+pub(crate) fn ps_info(pid: u32) -> Option<PsInfo> {
+	let output = std::process::Command::new("ps")
+		.args(["-o", "pgid=,etime=,args=", "-p", &pid.to_string()])
+		.output()
+		.ok()?;
+	let line = String::from_utf8_lossy(&output.stdout);
+	let mut fields = line.split_whitespace();
+	let pgid = fields.next()?.parse().ok()?;
+	let elapsed_secs = parse_etime(fields.next()?)?;
+	let args = fields.collect::<Vec<_>>().join(" ");
+	Some(PsInfo { pgid, elapsed_secs, args })
+}
+
+/// ps's `[[dd-]hh:]mm:ss` elapsed time.
+fn parse_etime(etime: &str) -> Option<u64> {
+	let (days, rest) = match etime.split_once('-') {
+		Some((d, rest)) => (d.parse::<u64>().ok()?, rest),
+		None => (0, etime),
+	};
+	let secs = rest.split(':').try_fold(0u64, |acc, part| Some(acc * 60 + part.parse::<u64>().ok()?))?;
+	Some(days * 86400 + secs)
+}
+
+/// Whether a ps command line is `command`, run directly or through the
+/// `sh -c` wrapper the supervisor spawns with. Whitespace is normalised
+/// because ps joins arguments with single spaces.
+pub(crate) fn command_matches(args: &str, command: &str) -> bool {
+	let command = command.split_whitespace().collect::<Vec<_>>().join(" ");
+	args == command || args.strip_prefix("sh -c ") == Some(command.as_str())
+}
+
+/// Whether something on the system is already bound to `port`.
+fn port_in_use(port: u16) -> bool {
+	use std::net::{Ipv4Addr, TcpListener};
+	[Ipv4Addr::UNSPECIFIED, Ipv4Addr::LOCALHOST].iter().any(|addr| {
+		matches!(TcpListener::bind((*addr, port)), Err(e) if e.kind() == std::io::ErrorKind::AddrInUse)
+	})
+}
+
+/// Works through `signals` against the process group: send each one, then wait
+/// its seconds before escalating. Stops as soon as the group is gone.
+fn kill_process_tree(pid: u32, signals: &[(String, u64)]) {
+	use nix::sys::signal::killpg;
 	use nix::unistd::Pid;
+	if pid == 0 {
+		return;
+	}
 	let pgid = Pid::from_raw(pid as i32);
-	let _ = killpg(pgid, Signal::SIGTERM);
+	let steps: Vec<_> = signals
+		.iter()
+		.filter_map(|(name, wait)| Some((config::parse_signal(name).ok()?, *wait)))
+		.collect();
 	std::thread::spawn(move || {
-		std::thread::sleep(std::time::Duration::from_secs(3));
-		let _ = killpg(pgid, Signal::SIGKILL);
+		for (signal, wait) in steps {
+			if killpg(pgid, signal).is_err() {
+				return;
+			}
+			let deadline = Instant::now() + std::time::Duration::from_secs(wait);
+			while Instant::now() < deadline {
+				std::thread::sleep(std::time::Duration::from_millis(100));
+				if killpg(pgid, None).is_err() {
+					return;
+				}
+			}
+		}
 	});
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	async fn pid_of(supervisor: &Arc<Supervisor>, service: &str, process: &str) -> Option<u32> {
+		let services = supervisor.services.read().await;
+		match services.get(service)?.processes.get(process)?.state {
+			ProcessState::Running { pid, .. } => Some(pid),
+			_ => None,
+		}
+	}
+
+	#[test]
+	fn test_adopt_helpers() {
+		assert_eq!(parse_etime("05:07"), Some(307));
+		assert_eq!(parse_etime("01:00:00"), Some(3600));
+		assert_eq!(parse_etime("2-00:00:01"), Some(2 * 86400 + 1));
+		assert_eq!(parse_etime("x"), None);
+		assert!(command_matches("sh -c npm run dev", "npm  run dev"));
+		assert!(command_matches("npm run dev", "npm run dev"));
+		assert!(!command_matches("sh -c npm run build", "npm run dev"));
+	}
+
+	/// A `demo` service in a temp dir of its own. Dropping it kills every process
+	/// group it spawned and removes the dir, so neither a failed assert nor a
+	/// test ending before a stop lands leaves a `sleep 30` behind.
+	struct Demo {
+		supervisor: Arc<Supervisor>,
+		dir: std::path::PathBuf,
+		spawned: Arc<std::sync::Mutex<Vec<u32>>>,
+	}
+
+	/// Passes spawns through to `inner`, noting each child's process group.
+	struct Tracking {
+		inner: Arc<dyn Spawner>,
+		spawned: Arc<std::sync::Mutex<Vec<u32>>>,
+	}
+
+	impl Spawner for Tracking {
+		fn spawn(&self, def: &ProcessDef, dir: &std::path::Path) -> Result<Spawned, String> {
+			let spawned = self.inner.spawn(def, dir)?;
+			self.spawned.lock().unwrap().extend(spawned.child.id());
+			Ok(spawned)
+		}
+	}
+
+	impl Demo {
+		fn new(services_toml: &str, spawner: Arc<dyn Spawner>) -> Self {
+			static COUNTER: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+			let n = COUNTER.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+			let dir = std::env::temp_dir().join(format!("ubermind-supervisor-test-{}-{}", std::process::id(), n));
+			std::fs::create_dir_all(&dir).unwrap();
+			std::fs::write(dir.join("services.toml"), services_toml).unwrap();
+			let spawned = Arc::new(std::sync::Mutex::new(Vec::new()));
+			let tracking = Arc::new(Tracking { inner: spawner, spawned: Arc::clone(&spawned) });
+			let supervisor = Supervisor::with_spawner(GlobalConfig::default(), None, vec![], tracking);
+			Self { supervisor, dir, spawned }
+		}
+
+		fn entry(&self) -> config::ServiceEntry {
+			config::ServiceEntry { name: "demo".to_string(), dir: self.dir.clone(), inline_command: None }
+		}
+	}
+
+	impl Drop for Demo {
+		fn drop(&mut self) {
+			for pgid in self.spawned.lock().unwrap().iter() {
+				let _ = nix::sys::signal::killpg(nix::unistd::Pid::from_raw(*pgid as i32), nix::sys::signal::Signal::SIGKILL);
+			}
+			let _ = std::fs::remove_dir_all(&self.dir);
+		}
+	}
+
+	/// Starts the autostart processes of `services_toml` as service `demo`.
+	async fn start_demo(services_toml: &str) -> Demo {
+		let demo = Demo::new(services_toml, Arc::new(ShellSpawner));
+		demo.supervisor.start_entry("demo", &demo.entry(), false, &[], None).await.unwrap();
+		demo
+	}
+
+	/// Polls `check` for up to two seconds; whether it came true.
+	async fn wait_for<F, Fut>(mut check: F) -> bool
+	where
+		F: FnMut() -> Fut,
+		Fut: std::future::Future<Output = bool>,
+	{
+		for _ in 0..100 {
+			if check().await {
+				return true;
+			}
+			tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+		}
+		false
+	}
+
+	async fn logged(supervisor: &Arc<Supervisor>, process: &str) -> String {
+		let capture = supervisor.get_output("demo", Some(process)).await.unwrap();
+		String::from_utf8_lossy(&capture.snapshot().await).into_owned()
+	}
+
+	#[tokio::test]
+	async fn test_invalid_services_toml_keeps_processes_running() {
+		let demo = start_demo("[web]\nrun = \"sleep 30\"\ncapture = false\n").await;
+		let supervisor = &demo.supervisor;
+		assert!(wait_for(|| async { pid_of(supervisor, "demo", "web").await.is_some() }).await, "web never started");
+		let pid = pid_of(supervisor, "demo", "web").await;
+
+		// Saved halfway through an edit
+		std::fs::write(demo.dir.join("services.toml"), "[web]\nrun = \"sleep 30\ncapture = false\n").unwrap();
+		let entries = BTreeMap::from([("demo".to_string(), demo.entry())]);
+		let changes = supervisor.reconcile_entries(&entries).await;
+
+		assert_eq!(changes.len(), 1);
+		assert!(changes[0].starts_with("demo: unchanged"), "{}", changes[0]);
+		assert!(changes[0].contains("line 2"), "{}", changes[0]);
+		assert_eq!(pid_of(supervisor, "demo", "web").await, pid);
+	}
+
+	/// Whether `pid` still exists and isn't a zombie waiting to be reaped.
+	fn alive(pid: i32) -> bool {
+		if nix::sys::signal::kill(nix::unistd::Pid::from_raw(pid), None).is_err() {
+			return false;
+		}
+		let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).unwrap_or_default();
+		!stat.rsplit(')').next().is_some_and(|rest| rest.trim_start().starts_with('Z'))
+	}
+
+	#[tokio::test]
+	async fn test_stop_kills_grandchildren() {
+		let demo = start_demo("[web]\nrun = \"sleep 300 & echo $! > grandchild.pid; wait\"\ncapture = false\n").await;
+		let pid_file = demo.dir.join("grandchild.pid");
+		let read_pid = || std::fs::read_to_string(&pid_file).ok().and_then(|s| s.trim().parse::<i32>().ok());
+		assert!(wait_for(|| async { read_pid().is_some() }).await, "grandchild never started");
+		let grandchild = read_pid().unwrap();
+		assert!(alive(grandchild));
+
+		demo.supervisor.stop_service("demo", None).await.unwrap();
+		assert!(wait_for(|| async { !alive(grandchild) }).await, "grandchild {} survived the stop", grandchild);
+	}
+
+	#[tokio::test]
+	async fn test_stop_escalates_to_sigkill_after_the_wait() {
+		use std::os::unix::process::ExitStatusExt;
+		let def: ProcessDef = serde_json::from_value(serde_json::json!({
+			"name": "stubborn",
+			"command": "trap '' TERM; sleep 30",
+			"capture": false,
+			"stop_signals": [["SIGTERM", 1], ["SIGKILL", 0]],
+		}))
+		.unwrap();
+		let mut child = spawn_process(&def, &std::env::temp_dir()).unwrap().child;
+		tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+
+		let stopped = Instant::now();
+		kill_process_tree(child.id().unwrap(), &def.stop_signals);
+		let status = child.wait().await.unwrap();
+		let waited = stopped.elapsed();
+		assert_eq!(status.signal(), Some(libc::SIGKILL));
+		assert!(waited >= std::time::Duration::from_millis(900) && waited < std::time::Duration::from_secs(3), "{:?}", waited);
+	}
+
+	#[tokio::test]
+	async fn test_graceful_reload_ignores_the_old_instance_on_the_port() {
+		// Stands in for the old instance still serving the ready port
+		let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+		let port = listener.local_addr().unwrap().port();
+		let own = std::process::id();
+		assert!(listening_ports_for_pids(&[own]).get(&own).is_some_and(|ports| ports.contains(&port)));
+		let demo = start_demo(&format!(
+			"[web]\nrun = \"sleep 30\"\ncapture = false\nreload_policy = \"graceful\"\nready_port = {}\nready_timeout = 1\n",
+			port
+		))
+		.await;
+		let supervisor = &demo.supervisor;
+		assert!(wait_for(|| async { pid_of(supervisor, "demo", "web").await.is_some() }).await, "web never started");
+		let old_pid = pid_of(supervisor, "demo", "web").await;
+		let def = supervisor.services.read().await["demo"].processes["web"].def.clone();
+
+		let result = supervisor.replace_process("demo", &demo.dir, def).await;
+		assert!(result.is_err(), "{:?}", result);
+		assert_eq!(pid_of(supervisor, "demo", "web").await, old_pid);
+	}
+
+	#[tokio::test]
+	async fn test_custom_spawner_is_used() {
+		struct Recording(std::sync::Mutex<Vec<String>>);
+		impl Spawner for Recording {
+			fn spawn(&self, def: &ProcessDef, dir: &std::path::Path) -> Result<Spawned, String> {
+				self.0.lock().unwrap().push(def.command.clone());
+				let mut cmd = Command::new("nice");
+				cmd.args(["-n", "1", "sh", "-c", &def.command]).current_dir(dir);
+				let terminal = if def.pty {
+					Some(attach_pty(&mut cmd)?)
+				} else {
+					cmd.process_group(0);
+					None
+				};
+				let argv = command_argv(&cmd);
+				Ok(Spawned { child: cmd.spawn().map_err(|e| e.to_string())?, argv, terminal })
+			}
+		}
+
+		let spawner = Arc::new(Recording(std::sync::Mutex::new(Vec::new())));
+		let demo = Demo::new("[web]\nrun = \"sleep 30\"\ncapture = false\n\n[shell]\nrun = \"sleep 31\"\npty = true\n", spawner.clone());
+		let supervisor = &demo.supervisor;
+		supervisor.start_entry("demo", &demo.entry(), false, &[], None).await.unwrap();
+		wait_for(|| async { pid_of(supervisor, "demo", "web").await.is_some() && pid_of(supervisor, "demo", "shell").await.is_some() }).await;
+
+		let mut spawned = spawner.0.lock().unwrap().clone();
+		spawned.sort();
+		assert_eq!(spawned, ["sleep 30", "sleep 31"]);
+		let resolved = supervisor.process_status("demo", "web").await.and_then(|s| s.resolved).unwrap();
+		assert_eq!(resolved.argv, ["nice", "-n", "1", "sh", "-c", "sleep 30"]);
+		// The pty process goes through the spawner too, and still gets its terminal
+		let resolved = supervisor.process_status("demo", "shell").await.and_then(|s| s.resolved).unwrap();
+		assert_eq!(resolved.argv, ["nice", "-n", "1", "sh", "-c", "sleep 31"]);
+		assert!(supervisor.terminal("demo", "shell").await.is_ok());
+	}
+
+	fn drainable(drain_run: &str, wait_secs: u64) -> String {
+		format!("[web]\nrun = \"sleep 30\"\ndrain = {{ run = \"{}\", wait_secs = {} }}\n", drain_run, wait_secs)
+	}
+
+	#[tokio::test]
+	async fn test_drain_runs_waits_then_stops() {
+		let demo = start_demo(&drainable("touch drained", 1)).await;
+		let supervisor = &demo.supervisor;
+		assert!(wait_for(|| async { pid_of(supervisor, "demo", "web").await.is_some() }).await, "web never started");
+
+		let started = Instant::now();
+		let result = supervisor.drain_process("demo", "web").await;
+		let waited = started.elapsed();
+		assert_eq!(result.unwrap(), "demo/web: drained and stopped");
+		assert!(demo.dir.join("drained").exists(), "drain.run did not run in the service directory");
+		assert!(waited >= std::time::Duration::from_secs(1), "{:?}", waited);
+		assert_eq!(pid_of(supervisor, "demo", "web").await, None);
+	}
+
+	#[tokio::test]
+	async fn test_failed_drain_leaves_the_process_running() {
+		let demo = start_demo(&drainable("exit 3", 0)).await;
+		let supervisor = &demo.supervisor;
+		assert!(wait_for(|| async { pid_of(supervisor, "demo", "web").await.is_some() }).await, "web never started");
+		let before = pid_of(supervisor, "demo", "web").await;
+
+		let result = supervisor.drain_process("demo", "web").await;
+		assert_eq!(result.unwrap_err().to_string(), "demo/web: drain command failed (exit 3), still running");
+		assert_eq!(pid_of(supervisor, "demo", "web").await, before);
+	}
+
+	#[tokio::test]
+	async fn test_command_override_is_for_the_first_launch_only() {
+		let demo = Demo::new(
+			"[web]\nrun = \"echo configured; exit 1\"\nrestart = true\nmax_retries = 1\nrestart_delay = 0\n",
+			Arc::new(ShellSpawner),
+		);
+		let supervisor = &demo.supervisor;
+		supervisor.start_entry("demo", &demo.entry(), false, &["web".to_string()], Some("echo override; exit 1")).await.unwrap();
+
+		wait_for(|| async { logged(supervisor, "web").await.contains("configured") }).await;
+		let logged = logged(supervisor, "web").await;
+		assert_eq!(logged.matches("override").count(), 1, "{}", logged);
+		assert!(logged.contains("configured"), "{}", logged);
+	}
+
+	#[tokio::test]
+	async fn test_on_failure_runs_once_after_the_last_retry() {
+		let demo = start_demo(
+			"[web]\nrun = \"exit 3\"\nrestart = true\nmax_retries = 2\nrestart_delay = 0\n\
+			 on_failure = \"echo $UBERMIND_SERVICE $UBERMIND_PROCESS $UBERMIND_EXIT_CODE >> ran; echo notified\"\n",
+		)
+		.await;
+		let supervisor = &demo.supervisor;
+
+		wait_for(|| async { logged(supervisor, "web").await.contains("[on_failure] notified") }).await;
+		// Long enough for a second run to show up if there were one
+		tokio::time::sleep(std::time::Duration::from_millis(300)).await;
+		let logged = logged(supervisor, "web").await;
+		assert!(logged.contains("[on_failure] notified\n"), "{}", logged);
+		assert_eq!(std::fs::read_to_string(demo.dir.join("ran")).unwrap_or_default(), "demo web 3\n");
+		let state = supervisor.process_status("demo", "web").await.map(|s| s.state);
+		assert!(matches!(state, Some(ProcessState::Failed { exit_code: 3, .. })), "{:?}", state);
+	}
+
+	/// `cargo test -- --ignored --nocapture port_scan` times one port scan for
+	/// 50 tracked process groups, the size `ub status` is expected to handle.
+	#[test]
+	#[ignore]
+	fn bench_port_scan_with_fifty_groups() {
+		use std::os::unix::process::CommandExt;
+		let listeners: Vec<_> = (0..5).map(|_| std::net::TcpListener::bind("127.0.0.1:0").unwrap()).collect();
+		let mut children: Vec<_> = (0..50)
+			.map(|_| std::process::Command::new("sleep").arg("30").process_group(0).spawn().unwrap())
+			.collect();
+		let mut pids: Vec<u32> = children.iter().map(|c| c.id()).collect();
+		pids.push(std::process::id());
+
+		let runs = 20;
+		let started = Instant::now();
+		for _ in 0..runs {
+			let ports = listening_ports_for_pids(&pids);
+			assert_eq!(ports[&std::process::id()].len(), listeners.len());
+		}
+		let each = started.elapsed() / runs;
+		for child in &mut children {
+			let _ = child.kill();
+			let _ = child.wait();
+		}
+		eprintln!("listening_ports_for_pids, {} pids: {:?} per scan", pids.len(), each);
+	}
+
+	#[test]
+	fn test_replacing_a_running_instance_is_an_event() {
+		let supervisor = Supervisor::new(GlobalConfig::default(), None, vec![]);
+		let mut changes = supervisor.events.subscribe();
+		let def: ProcessDef = serde_json::from_value(serde_json::json!({ "name": "web", "command": "x" })).unwrap();
+		let mut mp = ManagedProcess::new(def, OutputCapture::disabled());
+
+		supervisor.set_state("demo", &mut mp, ProcessState::Running { pid: 10, uptime_secs: 0 });
+		supervisor.set_state("demo", &mut mp, ProcessState::Running { pid: 10, uptime_secs: 5 });
+		supervisor.set_state("demo", &mut mp, ProcessState::Running { pid: 11, uptime_secs: 0 });
+
+		let details: Vec<String> = supervisor.recent_events(None, 10).into_iter().map(|e| e.detail).collect();
+		assert_eq!(details, vec!["pid 10".to_string(), "pid 11 (replaced pid 10)".to_string()]);
+		assert_eq!(changes.try_recv().map(|c| c.state).ok(), Some("Running"));
+		assert_eq!(changes.try_recv().map(|c| c.state).ok(), Some("Running"));
+		assert!(changes.try_recv().is_err());
+	}
+
+	#[test]
+	fn test_oom_needs_the_counter_to_rise() {
+		use std::os::unix::process::ExitStatusExt;
+		let killed = std::process::ExitStatus::from_raw(libc::SIGKILL);
+		let shell_killed = std::process::ExitStatus::from_raw(137 << 8);
+		let crashed = std::process::ExitStatus::from_raw(1 << 8);
+
+		assert_eq!(likely_oom(&killed, Some(2), Some(3)), Some(true));
+		assert_eq!(likely_oom(&shell_killed, Some(2), Some(3)), Some(true));
+		assert_eq!(likely_oom(&killed, Some(3), Some(3)), Some(false));
+		assert_eq!(likely_oom(&killed, None, None), None);
+		assert_eq!(likely_oom(&killed, Some(3), None), None);
+		assert_eq!(likely_oom(&crashed, None, None), Some(false));
+	}
+
+	#[test]
+	fn test_recent_crashes_window() {
+		let window = std::time::Duration::from_secs(60);
+		let start = std::time::Instant::now();
+		let mut crashes = VecDeque::new();
+		assert_eq!(recent_crashes(&mut crashes, start, window), 1);
+		assert_eq!(recent_crashes(&mut crashes, start + std::time::Duration::from_secs(30), window), 2);
+		assert_eq!(recent_crashes(&mut crashes, start + std::time::Duration::from_secs(60), window), 3);
+		// The first crash has aged out
+		assert_eq!(recent_crashes(&mut crashes, start + std::time::Duration::from_secs(61), window), 3);
+		assert_eq!(recent_crashes(&mut crashes, start + std::time::Duration::from_secs(200), window), 1);
+	}
+
+	#[test]
+	fn test_jitter_stays_within_fraction() {
+		let delay = std::time::Duration::from_secs(2);
+		let mut jitter = Jitter::seeded("demo", "web");
+		assert_eq!(jitter.stretch(delay, 0.0), delay);
+		for _ in 0..1000 {
+			let stretched = jitter.stretch(delay, 0.5);
+			assert!(stretched >= delay && stretched < std::time::Duration::from_secs(3), "{:?}", stretched);
+		}
+	}
+
+	#[test]
+	fn test_restart_delay_backs_off_up_to_cap() {
+		let mut def: ProcessDef = serde_json::from_value(serde_json::json!({ "name": "web", "command": "x" })).unwrap();
+		def.restart_delay_secs = 2;
+		assert_eq!(restart_delay(&def, 3), std::time::Duration::from_secs(2));
+		def.restart_backoff = 2.0;
+		assert_eq!(restart_delay(&def, 1), std::time::Duration::from_secs(2));
+		assert_eq!(restart_delay(&def, 3), std::time::Duration::from_secs(8));
+		assert_eq!(restart_delay(&def, 1000), std::time::Duration::from_secs(300));
+		def.restart_delay_secs = 600;
+		assert_eq!(restart_delay(&def, 5), std::time::Duration::from_secs(600));
+	}
+
+	#[test]
+	fn test_diff_defs_lists_changed_fields_and_env() {
+		let def = |json: serde_json::Value| -> ProcessDef { serde_json::from_value(json).unwrap() };
+		let running = def(serde_json::json!({ "name": "web", "command": "npm start", "env": { "PORT": "3000", "OLD": "1" } }));
+		let configured = def(serde_json::json!({
+			"name": "web",
+			"command": "npm run dev",
+			"autostart": false,
+			"env": { "PORT": "3000", "NEW": "x" },
+		}));
+		let fields = diff_defs(&running, &configured);
+		let field = |name: &str| fields.iter().find(|(f, _, _)| f == name).map(|(_, a, b)| (a.as_str(), b.as_str()));
+		assert_eq!(field("command"), Some(("\"npm start\"", "\"npm run dev\"")));
+		assert_eq!(field("autostart"), Some(("true", "false")));
+		assert_eq!(field("env.OLD"), Some(("\"1\"", "(unset)")));
+		assert_eq!(field("env.NEW"), Some(("(unset)", "\"x\"")));
+		assert_eq!(fields.len(), 4);
+		assert!(diff_defs(&running, &running).is_empty());
+	}
+
+	#[tokio::test]
+	async fn test_env_clear_keeps_only_declared_env_and_path() {
+		let def: ProcessDef = serde_json::from_value(serde_json::json!({
+			"name": "env",
+			"command": "echo \"${HOME:-none}|$FOO|${PATH:+path}\"",
+			"env": { "FOO": "bar" },
+			"env_clear": true,
+		}))
+		.unwrap();
+		let child = spawn_process(&def, &std::env::temp_dir()).unwrap().child;
+		let output = child.wait_with_output().await.unwrap();
+		assert_eq!(String::from_utf8_lossy(&output.stdout), "none|bar|path\n");
+	}
+
+	#[tokio::test]
+	async fn test_pty_process_runs_on_a_terminal() {
+		let def: ProcessDef = serde_json::from_value(serde_json::json!({
+			"name": "pty",
+			"command": "[ -t 0 ] && [ -t 1 ] && echo \"tty $TERM\"",
+			"pty": true,
+		}))
+		.unwrap();
+		let mut spawned = spawn_process(&def, &std::env::temp_dir()).unwrap();
+		let master = spawned.terminal.take().unwrap();
+		assert!(spawned.child.wait().await.unwrap().success());
+		let mut out = String::new();
+		let _ = std::io::Read::read_to_string(&mut &master, &mut out);
+		assert_eq!(out, "tty xterm-256color\r\n");
+	}
+
+	#[tokio::test]
+	async fn test_resize_terminal_reaches_the_process() {
+		let def: ProcessDef = serde_json::from_value(serde_json::json!({
+			"name": "pty",
+			"command": "sleep 0.3; stty size",
+			"pty": true,
+		}))
+		.unwrap();
+		let mut spawned = spawn_process(&def, &std::env::temp_dir()).unwrap();
+		let master = spawned.terminal.take().unwrap();
+		resize_terminal(&master, 30, 100).unwrap();
+		assert!(spawned.child.wait().await.unwrap().success());
+		let mut out = String::new();
+		let _ = std::io::Read::read_to_string(&mut &master, &mut out);
+		assert_eq!(out, "30 100\r\n");
+	}
+}
@@ -1,9 +1,10 @@
 use owo_colors::OwoColorize;
+use serde::Serialize;
 use std::collections::BTreeMap;
 use std::path::{Path, PathBuf};
 use std::process::Command;
 
-const UBERMIND_PREFIX: &str = "com.ubermind.";
+pub(crate) const UBERMIND_PREFIX: &str = "com.ubermind.";
 
 // --- Public entry point ---
 
@@ -13,6 +14,7 @@ pub fn cmd_launchd(args: &[String]) {
 	match subcmd {
 		"help" | "--help" | "-h" => print_launchd_usage(),
 		"list" | "ls" => cmd_list(&args[1..]),
+		"watch" => cmd_watch(&args[1..]),
 		"status" | "st" => cmd_status(&args[1..]),
 		"start" => cmd_start(&args[1..]),
 		"stop" => cmd_stop(&args[1..]),
@@ -20,6 +22,7 @@ pub fn cmd_launchd(args: &[String]) {
 		"logs" => cmd_logs(&args[1..]),
 		"show" => cmd_show(&args[1..]),
 		"create" => cmd_create(&args[1..]),
+		"templates" => cmd_templates(),
 		"edit" => cmd_edit(&args[1..]),
 		"remove" | "rm" => cmd_remove(&args[1..]),
 		label => {
@@ -36,6 +39,7 @@ fn print_launchd_usage() {
 	eprintln!();
 	eprintln!("commands:");
 	eprintln!("  list [--all] [--global]       List agents (default: user plist agents)");
+	eprintln!("  watch [--interval N]         Refresh the list every N seconds (q to quit)");
 	eprintln!("  status [label]               Show agent status");
 	eprintln!("  start <label>                Start / load agent");
 	eprintln!("  stop <label>                 Stop / unload agent");
@@ -43,19 +47,21 @@ fn print_launchd_usage() {
 	eprintln!("  logs <label>                 Tail agent log files");
 	eprintln!("  show <label>                 Show plist contents");
 	eprintln!("  create <label> -- <cmd>      Create a new agent plist");
+	eprintln!("  templates                    List templates for create --template");
 	eprintln!("  edit <label>                 Open plist in $EDITOR");
 	eprintln!("  remove <label> [--yes]       Unload and delete agent plist");
 	eprintln!();
 	eprintln!("options:");
 	eprintln!("  --all                        Include all loaded agents (not just plist files)");
 	eprintln!("  --global                     Include /Library agents (read-only)");
+	eprintln!("  --json                       JSON output (list, status, show)");
 	eprintln!();
 	eprintln!("labels can be partial: 'ub launchd status tunnel' matches 'com.ubermind.tunnel'");
 }
 
 // --- Data types ---
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 struct AgentInfo {
 	label: String,
 	plist_path: Option<PathBuf>,
@@ -71,10 +77,13 @@ struct AgentInfo {
 	working_dir: Option<String>,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
 enum AgentDomain {
+	#[serde(rename = "user")]
 	UserAgent,
+	#[serde(rename = "global")]
 	GlobalAgent,
+	#[serde(rename = "system")]
 	GlobalDaemon,
 }
 
@@ -90,7 +99,7 @@ impl AgentDomain {
 
 // --- Discovery ---
 
-fn get_uid() -> u32 {
+pub(crate) fn get_uid() -> u32 {
 	Command::new("id")
 		.arg("-u")
 		.output()
@@ -99,12 +108,12 @@ fn get_uid() -> u32 {
 		.unwrap_or(501)
 }
 
-fn user_agents_dir() -> PathBuf {
+pub(crate) fn user_agents_dir() -> PathBuf {
 	let home = std::env::var("HOME").unwrap_or_else(|_| "/tmp".to_string());
 	PathBuf::from(home).join("Library").join("LaunchAgents")
 }
 
-fn launchd_log_dir() -> PathBuf {
+pub(crate) fn launchd_log_dir() -> PathBuf {
 	let home = std::env::var("HOME").unwrap_or_else(|_| "/tmp".to_string());
 	PathBuf::from(home)
 		.join(".local")
@@ -148,6 +157,44 @@ fn parse_launchctl_list() -> BTreeMap<String, (Option<u32>, Option<i32>)> {
 	map
 }
 
+/// The `runs` counter from `launchctl print`, i.e. how many times launchd has
+/// started the agent since it was loaded.
+fn agent_run_count(label: &str) -> Option<u64> {
+	let target = format!("gui/{}/{}", get_uid(), label);
+	let output = Command::new("launchctl").args(["print", &target]).output().ok()?;
+	let stdout = String::from_utf8_lossy(&output.stdout);
+	stdout
+		.lines()
+		.find_map(|line| line.trim().strip_prefix("runs = "))
+		.and_then(|n| n.trim().parse().ok())
+}
+
+/// Samples the agent a second apart. More runs (or a different pid when the run
+/// count is unavailable) means launchd is relaunching it in a loop. Launchd
+/// throttles relaunches (10s by default), so a stopped agent that has failed
+/// after several runs also counts. Returns a description of what was seen.
+fn detect_crash_loop(agent: &AgentInfo) -> Option<String> {
+	let runs_before = agent_run_count(&agent.label);
+	std::thread::sleep(std::time::Duration::from_secs(1));
+	let runs_after = agent_run_count(&agent.label);
+	let relaunches = match (runs_before, runs_after) {
+		(Some(before), Some(after)) => after.saturating_sub(before),
+		_ => {
+			let pid_after = parse_launchctl_list().get(&agent.label).and_then(|(p, _)| *p);
+			u64::from(pid_after.is_some() && pid_after != agent.pid)
+		}
+	};
+	if relaunches > 0 {
+		return Some(format!("relaunched {} time(s) in 1s", relaunches));
+	}
+	match (agent.pid, agent.exit_code, runs_after) {
+		(None, Some(code), Some(runs)) if code != 0 && runs > 1 => {
+			Some(format!("exit {} after {} runs, waiting to be relaunched", code, runs))
+		}
+		_ => None,
+	}
+}
+
 fn scan_plists(include_global: bool, include_all_loaded: bool) -> BTreeMap<String, AgentInfo> {
 	let mut agents: BTreeMap<String, AgentInfo> = BTreeMap::new();
 	let loaded = parse_launchctl_list();
@@ -324,14 +371,37 @@ fn find_plist_path(label: &str) -> Option<PathBuf> {
 
 // --- Commands ---
 
+fn print_json<T: Serialize + ?Sized>(value: &T) {
+	match serde_json::to_string_pretty(value) {
+		Ok(json) => println!("{}", json),
+		Err(e) => {
+			eprintln!("error: {}", e);
+			std::process::exit(1);
+		}
+	}
+}
+
 fn cmd_list(args: &[String]) {
+	render_list(args);
+}
+
+/// Prints the agent list, returning how many terminal lines it took (stdout and
+/// stderr alike) so `watch` can redraw over it. Finding no agents is not an
+/// error, with or without `--json`.
+fn render_list(args: &[String]) -> usize {
 	let include_global = args.iter().any(|a| a == "--global" || a == "-g");
 	let include_all = args.iter().any(|a| a == "--all" || a == "-a");
 	let agents = scan_plists(include_global, include_all);
 
+	if args.iter().any(|a| a == "--json") {
+		let list: Vec<&AgentInfo> = agents.values().collect();
+		print_json(&list);
+		return serde_json::to_string_pretty(&list).map_or(0, |json| json.lines().count());
+	}
+
 	if agents.is_empty() {
 		eprintln!("no agents found");
-		return;
+		return 1;
 	}
 
 	let max_label_width = agents.keys().map(|k| k.len()).max().unwrap_or(0);
@@ -381,13 +451,98 @@ fn cmd_list(args: &[String]) {
 			width = max_label_width,
 		);
 	}
+	agents.len()
+}
+
+fn cmd_watch(args: &[String]) {
+	let mut interval = 2u64;
+	let mut list_args = Vec::new();
+	let mut i = 0;
+	while i < args.len() {
+		if args[i] == "--interval" || args[i] == "-n" {
+			i += 1;
+			interval = args.get(i).and_then(|n| n.parse().ok()).unwrap_or(interval).max(1);
+		} else {
+			list_args.push(args[i].clone());
+		}
+		i += 1;
+	}
+
+	let _keys = KeyInput::enable();
+	let mut prev_lines = 0usize;
+	loop {
+		if prev_lines > 0 {
+			print!("\x1b[{}A\x1b[J", prev_lines);
+		}
+		prev_lines = render_list(&list_args);
+		let _ = std::io::Write::flush(&mut std::io::stdout());
+
+		if KeyInput::quit_pressed(std::time::Duration::from_secs(interval)) {
+			return;
+		}
+	}
+}
+
+/// Puts the terminal in unbuffered, no-echo mode so single key presses (q, Ctrl-C)
+/// can be read while watching. The previous settings are restored on drop.
+struct KeyInput {
+	saved: Option<nix::sys::termios::Termios>,
+}
+
+impl KeyInput {
+	fn enable() -> Self {
+		use nix::sys::termios::{tcgetattr, tcsetattr, LocalFlags, SetArg};
+		let stdin = std::io::stdin();
+		let Ok(saved) = tcgetattr(&stdin) else {
+			return Self { saved: None };
+		};
+		let mut raw = saved.clone();
+		raw.local_flags.remove(LocalFlags::ICANON | LocalFlags::ECHO | LocalFlags::ISIG);
+		let _ = tcsetattr(&stdin, SetArg::TCSANOW, &raw);
+		Self { saved: Some(saved) }
+	}
+
+	/// Waits up to `timeout` for input; true if it was q or Ctrl-C.
+	fn quit_pressed(timeout: std::time::Duration) -> bool {
+		use nix::poll::{poll, PollFd, PollFlags, PollTimeout};
+		use std::io::Read;
+		use std::os::fd::AsFd;
+
+		let stdin = std::io::stdin();
+		let ms = PollTimeout::try_from(timeout.as_millis().min(i32::MAX as u128) as i32).unwrap_or(PollTimeout::MAX);
+		let mut fds = [PollFd::new(stdin.as_fd(), PollFlags::POLLIN)];
+		if !matches!(poll(&mut fds, ms), Ok(n) if n > 0) {
+			return false;
+		}
+		let mut byte = [0u8; 1];
+		match stdin.lock().read(&mut byte) {
+			Ok(0) => {
+				// stdin closed (not a terminal); keep watching on the timer alone
+				std::thread::sleep(timeout);
+				false
+			}
+			Ok(_) => matches!(byte[0], b'q' | b'Q' | 0x03),
+			Err(_) => false,
+		}
+	}
+}
+
+impl Drop for KeyInput {
+	fn drop(&mut self) {
+		if let Some(ref saved) = self.saved {
+			let _ = nix::sys::termios::tcsetattr(std::io::stdin(), nix::sys::termios::SetArg::TCSANOW, saved);
+		}
+	}
 }
 
 fn cmd_status(args: &[String]) {
-	if args.is_empty() {
-		cmd_list(&[]);
+	let json = args.iter().any(|a| a == "--json");
+	let rest: Vec<String> = args.iter().filter(|a| *a != "--json").cloned().collect();
+	if rest.is_empty() {
+		cmd_list(args);
 		return;
 	}
+	let args = rest;
 
 	let agents = scan_plists(true, true);
 	let label = match resolve_label(&args[0], &agents) {
@@ -413,7 +568,20 @@ fn cmd_status(args: &[String]) {
 	};
 
 	let agent = &agents[&label];
-	let circle = if agent.pid.is_some() {
+	if json {
+		print_json(agent);
+		return;
+	}
+
+	let crash_loop = if agent.loaded && agent.keep_alive {
+		detect_crash_loop(agent)
+	} else {
+		None
+	};
+
+	let circle = if crash_loop.is_some() {
+		"●".yellow().to_string()
+	} else if agent.pid.is_some() {
 		"●".green().to_string()
 	} else if agent.loaded {
 		"●".yellow().to_string()
@@ -424,6 +592,10 @@ fn cmd_status(args: &[String]) {
 	println!(" {} {}", circle, agent.label.bold());
 	println!();
 
+	if let Some(ref reason) = crash_loop {
+		println!("   {} {}", "state:".dimmed(), format!("crash-looping ({})", reason).yellow());
+	}
+
 	if let Some(ref path) = agent.plist_path {
 		println!("   {} {}", "plist:".dimmed(), path.display());
 	}
@@ -722,6 +894,22 @@ fn cmd_logs(args: &[String]) {
 		return;
 	}
 
+	// launchd holds these files open and never rotates them.
+	let max_size = crate::config::load_global_config().logs.max_size_bytes;
+	for log_file in &log_files {
+		let size = std::fs::metadata(log_file).map(|m| m.len()).unwrap_or(0);
+		if size > max_size {
+			eprintln!(
+				"{} {} is {} MB (over logs.max_size_bytes); launchd does not rotate it",
+				"warning:".yellow(),
+				log_file.display(),
+				size / (1024 * 1024)
+			);
+			eprintln!("  to reset it: truncate -s 0 '{}'", log_file.display());
+			eprintln!();
+		}
+	}
+
 	for log_file in &log_files {
 		if log_files.len() > 1 {
 			println!("{}", log_file.display().dimmed());
@@ -767,6 +955,17 @@ fn cmd_show(args: &[String]) {
 		}
 	};
 
+	if args.iter().any(|a| a == "--json") {
+		match plist::Value::from_file(&plist_path) {
+			Ok(value) => print_json(&value),
+			Err(e) => {
+				eprintln!("error reading plist: {}", e);
+				std::process::exit(1);
+			}
+		}
+		return;
+	}
+
 	println!("{}", plist_path.display().dimmed());
 	println!();
 
@@ -837,6 +1036,10 @@ fn cmd_create(args: &[String]) {
 		eprintln!("  --no-keep-alive        Don't restart on crash");
 		eprintln!("  --no-run-at-load       Don't start on load/login");
 		eprintln!("  --env KEY=VAL          Set environment variable (repeatable)");
+		eprintln!("  --env-file <path>      Read KEY=VALUE lines from a file (--env wins)");
+		eprintln!("  --template <name>      Start from a template (see 'ub launchd templates')");
+		eprintln!("  --dry-run              Print the plist and launchctl command only");
+		eprintln!("  --wait <secs>          After loading, check the agent is running");
 		std::process::exit(1);
 	}
 
@@ -868,9 +1071,13 @@ fn cmd_create(args: &[String]) {
 		.unwrap_or_else(|_| PathBuf::from("/tmp"))
 		.to_string_lossy()
 		.to_string();
-	let mut keep_alive = true;
-	let mut run_at_load = true;
+	let mut keep_alive: Option<bool> = None;
+	let mut run_at_load: Option<bool> = None;
 	let mut env_vars: Vec<(String, String)> = Vec::new();
+	let mut file_env_vars: Vec<(String, String)> = Vec::new();
+	let mut template: Option<String> = None;
+	let mut dry_run = false;
+	let mut wait_secs: Option<u64> = None;
 
 	let mut i = 0;
 	while i < option_args.len() {
@@ -881,8 +1088,38 @@ fn cmd_create(args: &[String]) {
 					working_dir = option_args[i].clone();
 				}
 			}
-			"--no-keep-alive" => keep_alive = false,
-			"--no-run-at-load" => run_at_load = false,
+			"--no-keep-alive" => keep_alive = Some(false),
+			"--no-run-at-load" => run_at_load = Some(false),
+			"--template" => {
+				i += 1;
+				template = option_args.get(i).cloned();
+			}
+			"--dry-run" => dry_run = true,
+			"--wait" => {
+				i += 1;
+				match option_args.get(i).and_then(|n| n.parse().ok()) {
+					Some(n) => wait_secs = Some(n),
+					None => {
+						eprintln!("error: --wait needs a number of seconds");
+						std::process::exit(1);
+					}
+				}
+			}
+			"--env-file" => {
+				i += 1;
+				let Some(raw) = option_args.get(i) else {
+					eprintln!("error: --env-file needs a path");
+					std::process::exit(1);
+				};
+				let path = crate::config::expand_tilde(raw);
+				match std::fs::read_to_string(&path) {
+					Ok(content) => file_env_vars.extend(crate::config::parse_env_file(&content)),
+					Err(e) => {
+						eprintln!("error: cannot read env file {}: {}", path.display(), e);
+						std::process::exit(1);
+					}
+				}
+			}
 			"--env" => {
 				i += 1;
 				if i < option_args.len() {
@@ -899,9 +1136,12 @@ fn cmd_create(args: &[String]) {
 		i += 1;
 	}
 
+	// Inline --env values are applied after the file so they take precedence
+	file_env_vars.append(&mut env_vars);
+	let env_vars = file_env_vars;
+
 	// Check if plist already exists
 	let agents_dir = user_agents_dir();
-	let _ = std::fs::create_dir_all(&agents_dir);
 	let plist_path = agents_dir.join(format!("{}.plist", label));
 
 	if plist_path.exists() {
@@ -910,14 +1150,22 @@ fn cmd_create(args: &[String]) {
 		std::process::exit(1);
 	}
 
-	// Create log directory
 	let log_dir = launchd_log_dir();
-	let _ = std::fs::create_dir_all(&log_dir);
 	let stdout_log = log_dir.join(format!("{}.out.log", label_short));
 	let stderr_log = log_dir.join(format!("{}.err.log", label_short));
 
-	// Build plist dictionary
-	let mut dict = plist::Dictionary::new();
+	// Build plist dictionary, starting from the template's keys
+	let mut dict = match &template {
+		Some(name) => match load_template(name) {
+			Some(d) => d,
+			None => {
+				eprintln!("error: unknown template: {}", name);
+				eprintln!("see 'ub launchd templates'");
+				std::process::exit(1);
+			}
+		},
+		None => plist::Dictionary::new(),
+	};
 	dict.insert("Label".to_string(), plist::Value::String(label.clone()));
 
 	let program_args: Vec<plist::Value> = command_args
@@ -931,27 +1179,34 @@ fn cmd_create(args: &[String]) {
 
 	dict.insert(
 		"WorkingDirectory".to_string(),
-		plist::Value::String(working_dir),
-	);
-	dict.insert(
-		"KeepAlive".to_string(),
-		plist::Value::Boolean(keep_alive),
-	);
-	dict.insert(
-		"RunAtLoad".to_string(),
-		plist::Value::Boolean(run_at_load),
-	);
-	dict.insert(
-		"StandardOutPath".to_string(),
-		plist::Value::String(stdout_log.to_string_lossy().to_string()),
-	);
-	dict.insert(
-		"StandardErrorPath".to_string(),
-		plist::Value::String(stderr_log.to_string_lossy().to_string()),
+		plist::Value::String(working_dir.clone()),
 	);
+	// Flags win over the template; without either, keep alive and run at load.
+	for (key, flag) in [("KeepAlive", keep_alive), ("RunAtLoad", run_at_load)] {
+		if let Some(value) = flag {
+			dict.insert(key.to_string(), plist::Value::Boolean(value));
+		} else if !dict.contains_key(key) {
+			dict.insert(key.to_string(), plist::Value::Boolean(true));
+		}
+	}
+	if !dict.contains_key("StandardOutPath") {
+		dict.insert(
+			"StandardOutPath".to_string(),
+			plist::Value::String(stdout_log.to_string_lossy().to_string()),
+		);
+	}
+	if !dict.contains_key("StandardErrorPath") {
+		dict.insert(
+			"StandardErrorPath".to_string(),
+			plist::Value::String(stderr_log.to_string_lossy().to_string()),
+		);
+	}
 
 	if !env_vars.is_empty() {
-		let mut env_dict = plist::Dictionary::new();
+		let mut env_dict = match dict.remove("EnvironmentVariables") {
+			Some(plist::Value::Dictionary(d)) => d,
+			_ => plist::Dictionary::new(),
+		};
 		for (k, v) in &env_vars {
 			env_dict.insert(k.clone(), plist::Value::String(v.clone()));
 		}
@@ -961,8 +1216,34 @@ fn cmd_create(args: &[String]) {
 		);
 	}
 
-	// Write plist
 	let value = plist::Value::Dictionary(dict);
+	let uid = get_uid();
+	let target = format!("gui/{}", uid);
+
+	if dry_run {
+		eprintln!("{}", format!("would write {}", plist_path.display()).dimmed());
+		let mut xml = Vec::new();
+		if let Err(e) = value.to_writer_xml(&mut xml) {
+			eprintln!("error rendering plist: {}", e);
+			std::process::exit(1);
+		}
+		println!("{}", String::from_utf8_lossy(&xml));
+		eprintln!("{}", format!("would run: launchctl bootstrap {} {}", target, plist_path.display()).dimmed());
+		return;
+	}
+
+	if find_executable(&command_args[0], Path::new(&working_dir)).is_none() {
+		eprintln!(
+			"{} '{}' is not an executable file or on PATH; the agent will fail to start",
+			"warning:".yellow(),
+			command_args[0]
+		);
+	}
+
+	let _ = std::fs::create_dir_all(&agents_dir);
+	let _ = std::fs::create_dir_all(&log_dir);
+
+	// Write plist
 	if let Err(e) = value.to_file_xml(&plist_path) {
 		eprintln!("error writing plist: {}", e);
 		std::process::exit(1);
@@ -970,14 +1251,13 @@ fn cmd_create(args: &[String]) {
 	eprintln!("created {}", plist_path.display());
 
 	// Bootstrap it
-	let uid = get_uid();
-	let target = format!("gui/{}", uid);
 	let result = Command::new("launchctl")
 		.args(["bootstrap", &target, &plist_path.to_string_lossy()])
 		.output();
-	match result {
+	let loaded = match result {
 		Ok(output) if output.status.success() => {
 			eprintln!("{}: loaded and started", label);
+			true
 		}
 		Ok(output) => {
 			let err = String::from_utf8_lossy(&output.stderr);
@@ -988,17 +1268,163 @@ fn cmd_create(args: &[String]) {
 			match legacy {
 				Ok(o) if o.status.success() => {
 					eprintln!("{}: loaded (legacy)", label);
+					true
 				}
 				_ => {
 					eprintln!("created plist but failed to load: {}", err.trim());
 					eprintln!("try: launchctl load {}", plist_path.display());
+					false
 				}
 			}
 		}
 		Err(e) => {
 			eprintln!("created plist but failed to load: {}", e);
 			eprintln!("try: launchctl load {}", plist_path.display());
+			false
+		}
+	};
+
+	if let Some(secs) = wait_secs {
+		if !loaded {
+			std::process::exit(1);
+		}
+		match wait_for_agent(&label, std::time::Duration::from_secs(secs)) {
+			Ok(pid) => eprintln!("{}: {} (pid {})", label, "running".green(), pid),
+			Err(e) => {
+				eprintln!("{}: {}", label, e.red());
+				eprintln!("see: ub launchd logs {}", label_short);
+				std::process::exit(1);
+			}
+		}
+	}
+}
+
+/// Polls `launchctl list` until the agent has a pid and its last exit (if any)
+/// was clean. A pid alongside a non-zero last exit means launchd already had to
+/// relaunch it.
+fn wait_for_agent(label: &str, timeout: std::time::Duration) -> Result<u32, String> {
+	let deadline = std::time::Instant::now() + timeout;
+	loop {
+		let entry = parse_launchctl_list().get(label).copied();
+		match entry {
+			Some((Some(pid), None | Some(0))) => return Ok(pid),
+			Some((_, Some(code))) if code != 0 => {
+				return Err(format!("exited with code {}", code));
+			}
+			_ => {}
+		}
+		if std::time::Instant::now() >= deadline {
+			return Err(match entry {
+				Some(_) => format!("not running after {}s", timeout.as_secs()),
+				None => "not found in launchctl list".to_string(),
+			});
 		}
+		std::thread::sleep(std::time::Duration::from_millis(250));
+	}
+}
+
+/// Resolves a program the way a shell would: paths containing '/' relative to
+/// `dir`, bare names via PATH.
+fn find_executable(program: &str, dir: &Path) -> Option<PathBuf> {
+	use std::os::unix::fs::PermissionsExt;
+	let is_executable = |p: &Path| p.metadata().is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0);
+
+	if program.contains('/') {
+		let path = dir.join(program);
+		return is_executable(&path).then_some(path);
+	}
+	std::env::var_os("PATH")
+		.map(|paths| std::env::split_paths(&paths).map(|p| p.join(program)).collect::<Vec<_>>())
+		.unwrap_or_default()
+		.into_iter()
+		.find(|p| is_executable(p))
+}
+
+// --- Templates ---
+
+const BUILTIN_TEMPLATES: &[(&str, &str)] = &[
+	("server", "long-running server: kept alive, restarts throttled to every 10s"),
+	("periodic", "job run every hour (edit StartInterval), not kept alive"),
+	("login", "runs once at login, not kept alive"),
+];
+
+fn user_templates_dir() -> PathBuf {
+	crate::protocol::config_dir().join("launchd-templates")
+}
+
+fn builtin_template(name: &str) -> Option<plist::Dictionary> {
+	let mut dict = plist::Dictionary::new();
+	match name {
+		"server" => {
+			dict.insert("KeepAlive".to_string(), plist::Value::Boolean(true));
+			dict.insert("RunAtLoad".to_string(), plist::Value::Boolean(true));
+			dict.insert("ThrottleInterval".to_string(), plist::Value::Integer(10.into()));
+			dict.insert("ProcessType".to_string(), plist::Value::String("Interactive".to_string()));
+		}
+		"periodic" => {
+			dict.insert("KeepAlive".to_string(), plist::Value::Boolean(false));
+			dict.insert("RunAtLoad".to_string(), plist::Value::Boolean(false));
+			dict.insert("StartInterval".to_string(), plist::Value::Integer(3600.into()));
+			dict.insert("ProcessType".to_string(), plist::Value::String("Background".to_string()));
+		}
+		"login" => {
+			dict.insert("KeepAlive".to_string(), plist::Value::Boolean(false));
+			dict.insert("RunAtLoad".to_string(), plist::Value::Boolean(true));
+			dict.insert("ProcessType".to_string(), plist::Value::String("Standard".to_string()));
+		}
+		_ => return None,
+	}
+	Some(dict)
+}
+
+/// A user template (`<config>/launchd-templates/<name>.plist`) takes precedence
+/// over a built-in of the same name.
+fn load_template(name: &str) -> Option<plist::Dictionary> {
+	let path = user_templates_dir().join(format!("{}.plist", name));
+	if path.exists() {
+		return match plist::Value::from_file(&path) {
+			Ok(plist::Value::Dictionary(dict)) => Some(dict),
+			Ok(_) => {
+				eprintln!("warning: {} is not a plist dictionary", path.display());
+				None
+			}
+			Err(e) => {
+				eprintln!("warning: failed to read {}: {}", path.display(), e);
+				None
+			}
+		};
+	}
+	builtin_template(name)
+}
+
+fn cmd_templates() {
+	eprintln!("{}", "built-in".bold());
+	for (name, description) in BUILTIN_TEMPLATES {
+		eprintln!("  {:<10} {}", name, description.dimmed());
+	}
+
+	let dir = user_templates_dir();
+	let mut user: Vec<String> = std::fs::read_dir(&dir)
+		.map(|entries| {
+			entries
+				.flatten()
+				.filter_map(|e| {
+					let path = e.path();
+					(path.extension().and_then(|x| x.to_str()) == Some("plist"))
+						.then(|| path.file_stem().unwrap_or_default().to_string_lossy().to_string())
+				})
+				.collect()
+		})
+		.unwrap_or_default();
+	user.sort();
+
+	eprintln!();
+	eprintln!("{} {}", "user".bold(), dir.display().dimmed());
+	if user.is_empty() {
+		eprintln!("  {}", "(none — add <name>.plist files with the keys to pre-fill)".dimmed());
+	}
+	for name in user {
+		eprintln!("  {}", name);
 	}
 }
 
@@ -1,5 +1,13 @@
 use crate::protocol::state_dir;
-use std::path::PathBuf;
+use owo_colors::OwoColorize;
+use regex::Regex;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+/// Matches a level token at the start of a line, optionally after one leading
+/// field such as a timestamp.
+pub const DEFAULT_LEVEL_PATTERN: &str = r"^(?:\S+\s+)?\[?(ERROR|WARN(?:ING)?|INFO|DEBUG|TRACE)\b";
 
 pub fn log_dir() -> PathBuf {
 	state_dir().join("logs")
@@ -14,6 +22,12 @@ pub fn current_log_name(process: &str) -> String {
 	format!("{} {}.log", process, now)
 }
 
+/// Whether `name` is a process's log for today that hasn't been rotated, i.e. one
+/// that may still be open for writing.
+pub fn is_current_log_name(name: &str) -> bool {
+	name.split_once(' ').is_some_and(|(_, rest)| rest == format!("{}.log", now_ymd()))
+}
+
 pub fn rotated_log_name(process: &str) -> String {
 	let now = now_ymdhm();
 	let (date, hour, minute) = now;
@@ -52,22 +66,122 @@ pub fn parse_log_date(filename: &str) -> Option<(u32, u32, u32)> {
 	Some((year, month, day))
 }
 
+/// A log file in a service's log directory.
+pub struct LogFileInfo {
+	pub path: PathBuf,
+	pub name: String,
+	pub size: u64,
+	pub date: Option<(u32, u32, u32)>,
+	pub modified: Option<std::time::SystemTime>,
+}
+
+/// The `.log` files in a service's log directory sorted by name, so oldest first
+/// per process, optionally only those of `process`.
+pub fn list_log_files(service: &str, process: Option<&str>) -> Vec<LogFileInfo> {
+	let Ok(entries) = std::fs::read_dir(service_log_dir(service)) else { return Vec::new() };
+	let mut files: Vec<LogFileInfo> = entries
+		.flatten()
+		.filter_map(|entry| {
+			let name = entry.file_name().to_string_lossy().to_string();
+			if !name.ends_with(".log") {
+				return None;
+			}
+			if process.is_some_and(|p| name.split_once(' ').map(|(owner, _)| owner) != Some(p)) {
+				return None;
+			}
+			let metadata = entry.metadata().ok();
+			Some(LogFileInfo {
+				path: entry.path(),
+				size: metadata.as_ref().map(|m| m.len()).unwrap_or(0),
+				date: parse_log_date(&name),
+				modified: metadata.and_then(|m| m.modified().ok()),
+				name,
+			})
+		})
+		.collect();
+	files.sort_by(|a, b| a.name.cmp(&b.name));
+	files
+}
+
+/// The most recently written of `files`. Name order only says which is newest
+/// within one process, so across processes the modification time decides.
+pub fn newest(files: Vec<LogFileInfo>) -> Option<LogFileInfo> {
+	files.into_iter().max_by_key(|f| f.modified)
+}
+
+/// Returns the last `n` lines of a file, reading backward from the end in blocks
+/// so large logs are never loaded whole.
+pub fn read_last_lines(path: &Path, n: usize) -> std::io::Result<Vec<String>> {
+	const BLOCK: u64 = 64 * 1024;
+
+	let mut file = File::open(path)?;
+	let len = file.metadata()?.len();
+	let mut pos = len;
+	let mut buf: Vec<u8> = Vec::new();
+
+	// A trailing newline ends the last line rather than starting an empty one.
+	let needed = n + 1;
+	while pos > 0 && buf.iter().filter(|&&b| b == b'\n').count() < needed {
+		let size = BLOCK.min(pos);
+		pos -= size;
+		file.seek(SeekFrom::Start(pos))?;
+		let mut block = vec![0u8; size as usize];
+		file.read_exact(&mut block)?;
+		block.extend_from_slice(&buf);
+		buf = block;
+	}
+
+	let text = String::from_utf8_lossy(&buf);
+	let lines: Vec<&str> = text.lines().collect();
+	// Unless we reached the start of the file, the first line may be partial.
+	let complete = if pos > 0 { &lines[1.min(lines.len())..] } else { &lines[..] };
+	let start = complete.len().saturating_sub(n);
+	Ok(complete[start..].iter().map(|l| l.to_string()).collect())
+}
+
+/// Colors log lines by level for `--color-levels`: errors red, warnings yellow,
+/// everything else with a recognised level dimmed.
+pub struct LevelColorizer {
+	pattern: Regex,
+}
+
+impl LevelColorizer {
+	/// Uses the first capture group of `pattern` (or the whole match) as the level.
+	pub fn new(pattern: Option<&str>) -> Self {
+		let pattern = match pattern.map(Regex::new) {
+			Some(Ok(re)) => re,
+			Some(Err(e)) => {
+				eprintln!("warning: invalid logs.level_pattern: {}", e);
+				Regex::new(DEFAULT_LEVEL_PATTERN).unwrap()
+			}
+			None => Regex::new(DEFAULT_LEVEL_PATTERN).unwrap(),
+		};
+		Self { pattern }
+	}
+
+	pub fn colorize(&self, line: &str) -> String {
+		let Some(caps) = self.pattern.captures(line) else {
+			return line.to_string();
+		};
+		let level = caps.get(1).or_else(|| caps.get(0)).map(|m| m.as_str().to_uppercase()).unwrap_or_default();
+		if level.starts_with("ERR") {
+			line.red().to_string()
+		} else if level.starts_with("WARN") {
+			line.yellow().to_string()
+		} else {
+			line.dimmed().to_string()
+		}
+	}
+}
+
 fn now_ymd() -> String {
-	use std::time::SystemTime;
-	let now = SystemTime::now()
-		.duration_since(SystemTime::UNIX_EPOCH)
-		.unwrap()
-		.as_secs();
+	let now = crate::types::unix_now();
 	let (year, month, day, _, _) = secs_to_datetime(now);
 	format!("{:02}-{:02}{:02}", year % 100, month, day)
 }
 
 fn now_ymdhm() -> (String, String, String) {
-	use std::time::SystemTime;
-	let now = SystemTime::now()
-		.duration_since(SystemTime::UNIX_EPOCH)
-		.unwrap()
-		.as_secs();
+	let now = crate::types::unix_now();
 	let (year, month, day, hour, minute) = secs_to_datetime(now);
 	(
 		format!("{:02}-{:02}{:02}", year % 100, month, day),
@@ -100,6 +214,33 @@ fn secs_to_datetime(secs: u64) -> (u32, u32, u32, u32, u32) {
 mod tests {
 	use super::*;
 
+	#[test]
+	fn test_read_last_lines() {
+		let path = std::env::temp_dir().join(format!("ubermind-tail-test-{}.log", std::process::id()));
+		let content: String = (0..20000).map(|i| format!("line {}\n", i)).collect();
+		std::fs::write(&path, &content).unwrap();
+
+		let last = read_last_lines(&path, 3).unwrap();
+		assert_eq!(last, vec!["line 19997", "line 19998", "line 19999"]);
+		assert_eq!(read_last_lines(&path, 50000).unwrap().len(), 20000);
+
+		std::fs::write(&path, "a\nb").unwrap();
+		assert_eq!(read_last_lines(&path, 1).unwrap(), vec!["b"]);
+		let _ = std::fs::remove_file(&path);
+	}
+
+	#[test]
+	fn test_level_colorizer() {
+		let c = LevelColorizer::new(None);
+		assert_eq!(c.colorize("ERROR boom"), "ERROR boom".red().to_string());
+		assert_eq!(c.colorize("12:00:01 [WARN] slow"), "12:00:01 [WARN] slow".yellow().to_string());
+		assert_eq!(c.colorize("INFO ready"), "INFO ready".dimmed().to_string());
+		assert_eq!(c.colorize("no level here ERROR"), "no level here ERROR");
+
+		let custom = LevelColorizer::new(Some(r"^level=(\w+)"));
+		assert_eq!(custom.colorize("level=error x"), "level=error x".red().to_string());
+	}
+
 	#[test]
 	fn test_parse_log_date() {
 		assert_eq!(parse_log_date("web 26-0214.log"), Some((26, 2, 14)));
@@ -108,6 +249,19 @@ mod tests {
 		assert_eq!(parse_log_date("invalid"), None);
 	}
 
+	#[test]
+	fn test_newest_log_is_the_last_written() {
+		let file = |name: &str, secs: u64| LogFileInfo {
+			path: PathBuf::from(name),
+			name: name.to_string(),
+			size: 0,
+			date: parse_log_date(name),
+			modified: Some(std::time::UNIX_EPOCH + std::time::Duration::from_secs(secs)),
+		};
+		let files = vec![file("api 26-0214.log", 200), file("worker 26-0213.log", 100)];
+		assert_eq!(newest(files).unwrap().name, "api 26-0214.log");
+	}
+
 	#[test]
 	fn test_secs_to_datetime() {
 		let (y, m, d, h, min) = secs_to_datetime(1771027200);
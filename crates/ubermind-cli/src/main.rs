@@ -2,21 +2,23 @@ mod config;
 mod daemon;
 mod launchd;
 mod logs;
+mod migrate;
 mod protocol;
+mod secrets;
 mod self_update;
+mod service;
 mod types;
 
-use std::collections::BTreeMap;
-use std::io::{self, BufRead, BufReader, Write};
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, BufRead, BufReader, Read, Write};
 use std::os::unix::net::UnixStream;
 use std::path::PathBuf;
 use std::process::Command;
 use std::time::Instant;
 use config::ServiceEntry;
-use protocol::{Request, Response};
+use protocol::{ErrorCode, Request, Response};
 use types::*;
 use owo_colors::OwoColorize;
-use toml;
 
 fn main() {
 	let args: Vec<String> = std::env::args().skip(1).collect();
@@ -36,24 +38,39 @@ fn main() {
 		"version" | "--version" | "-V" => println!("ubermind {}", env!("CARGO_PKG_VERSION")),
 		"init" => cmd_init(),
 		"add" => cmd_add(&args[1..]),
+		"remove" | "rm" => cmd_remove(&args[1..]),
+		"rename" => cmd_rename(&args[1..]),
+		"config" => cmd_config(&args[1..]),
+		"migrate" => migrate::cmd_migrate(&args[1..]),
 		"status" | "st" => cmd_status(&args[1..]),
 		"all" => cmd_status(&["all".to_string()]),
 		"start" => cmd_start(&args[1..]),
 		"stop" => cmd_stop(&args[1..]),
 		"reload" => cmd_reload(&args[1..]),
 		"restart" => cmd_restart(&args[1..]),
+		"clear" => cmd_clear(&args[1..]),
+		"adopt" => cmd_adopt(&args[1..]),
+		"drain" => cmd_drain(&args[1..]),
+		"connect" => cmd_connect(&args[1..]),
 		"logs" => cmd_logs(&args[1..]),
 		"tail" => cmd_tail(&args[1..]),
 		"echo" => cmd_echo(&args[1..]),
+		"events" => cmd_events(&args[1..]),
+		"diff" => cmd_diff(&args[1..]),
+		"bar" => cmd_bar(&args[1..]),
+		"run-once" => cmd_run_once(&args[1..]),
 		"show" => cmd_show(&args[1..]),
 		"daemon" => cmd_daemon(&args[1..]),
 		"serve" => cmd_serve(&args[1..]),
 		"launchd" | "launch" => launchd::cmd_launchd(&args[1..]),
+		"install-service" => service::cmd_install_service(),
+		"uninstall-service" => service::cmd_uninstall_service(),
 		"self" => {
 			match args.get(1).map(|s| s.as_str()) {
 				Some("update") => self_update::cmd_self_update(),
+				Some("uninstall") => self_update::cmd_self_uninstall(&args[2..]),
 				_ => {
-					eprintln!("usage: ub self update");
+					eprintln!("usage: ub self [update|uninstall [--purge] [--yes]]");
 					std::process::exit(1);
 				}
 			}
@@ -67,10 +84,12 @@ fn main() {
 					"stop" => cmd_stop(&[args[0].clone()]),
 					"reload" => cmd_reload(&[args[0].clone()]),
 					"status" | "st" => cmd_status(&[args[0].clone()]),
+					"clear" => cmd_clear(&[args[0].clone()]),
 					"logs" => cmd_logs(&args),
 					"tail" => cmd_tail(&args),
 					"echo" => cmd_echo(&args),
 					"show" => cmd_show(&args),
+					"diff" => cmd_diff(&[args[0].clone()]),
 					"restart" => {
 						if args.len() > 2 {
 							cmd_restart(&[args[0].clone(), args[2].clone()]);
@@ -107,29 +126,57 @@ fn print_usage() {
 
 	eprintln!("{}", "services".cyan().bold());
 	eprintln!("  {} [name|--all]          Show status (default command)", "status".bold());
+	eprintln!("    --only unhealthy            Only processes failing their ready_port probe");
 	eprintln!("  {} [name|--all]           Start service(s)", "start".bold());
+	eprintln!("    --cmd \"<command>\"          Run name.process with this command, this launch only");
 	eprintln!("  {} [name|--all]            Stop service(s)", "stop".bold());
 	eprintln!("  {} [name|--all]          Reload (stop + start)", "reload".bold());
+	eprintln!("    --drain-timeout <secs>      Let running tasks finish first (stop, reload; default 300, 0 kills)");
+	eprintln!("    --dry-run                   Show what stop or reload would touch, without doing it");
 	eprintln!("  {} [name] [process]     Restart a single process", "restart".bold());
+	eprintln!("  {} <name.process>         Run its drain command, wait, then stop it", "drain".bold());
+	eprintln!("  {} <name.process>       Attach to a pty = true process; Ctrl-] detaches", "connect".bold());
+	eprintln!("  {} [name|--all]           Forget finished task results", "clear".bold());
+	eprintln!("  {} <name[.process]> <pid> Take over a process the daemon didn't start", "adopt".bold());
+	eprintln!("  {} [name|--all]        Start, wait until ready or exited, stop (for CI)", "run-once".bold());
+	eprintln!("    --timeout <secs>            Give up waiting after this long (default 60)");
 	eprintln!();
 
 	eprintln!("{}", "logs".cyan().bold());
 	eprintln!("  {} <name> [process]        Last 100 lines of log file", "logs".bold());
+	eprintln!("    --list | --date YY-MMDD | --file <name>  List log files, or read an older one");
 	eprintln!("  {} <name> [process]        Follow log file (tail -f)", "tail".bold());
+	eprintln!("    --color-levels              Color ERROR/WARN/INFO lines (logs, tail)");
 	eprintln!("  {} <name> [process]        Live output stream from daemon", "echo".bold());
+	eprintln!("    --all                       Every running service, prefixed service.process");
+	eprintln!("  {} [--service name] [-n N] Recent starts, crashes and stops", "events".bold());
+	eprintln!("  {} [--format T] [--color]   One-line process counts for status bars", "bar".bold());
 	eprintln!();
 
 	eprintln!("{}", "config".cyan().bold());
 	eprintln!("  {} [name] [process]        Show services.toml or process command", "show".bold());
+	eprintln!("    --resolved                  argv and cwd the daemon actually launched");
+	eprintln!("  {} [name]                   What a reload would change after editing services.toml", "diff".bold());
 	eprintln!("  {} [name] [dir]             Register a project", "add".bold());
+	eprintln!("  {} <name>                Unregister a project", "remove".bold());
+	eprintln!("  {} <old> <new>           Rename a project", "rename".bold());
 	eprintln!("  {}                         Create config files", "init".bold());
+	eprintln!("  {} [--dry-run]          Convert projects/commands/Procfiles to TOML", "migrate".bold());
+	eprintln!("  {}                  Show where config, state and logs live", "config path".bold());
+	eprintln!("  {} [name]           Effective config as loaded by the daemon", "config show".bold());
+	eprintln!("  {} <file>         JSON Schema for projects/services/config", "config schema".bold());
 	eprintln!();
 
 	eprintln!("{}", "system".cyan().bold());
-	eprintln!("  {} [start|stop|status]   Manage the daemon", "daemon".bold());
+	eprintln!("  {} [start|stop|reload]   Manage the daemon (reload re-reads config)", "daemon".bold());
+	eprintln!("  {} [--count N]     Time request round trips to the daemon", "daemon ping".bold());
 	eprintln!("  {} [-d|--stop|--status]   HTTP server for web UI", "serve".bold());
+	eprintln!("    --attach <name.process>     Also print that process's output (foreground)");
 	eprintln!("  {} [command]            macOS launchd agents", "launchd".bold());
+	eprintln!("  {}              Start the daemon at login (launchd/systemd)", "install-service".bold());
+	eprintln!("  {}            Remove the login service", "uninstall-service".bold());
 	eprintln!("  {}                  Update to latest version", "self update".bold());
+	eprintln!("  {} [--purge]     Remove ubermind (and config)", "self uninstall".bold());
 	eprintln!();
 
 	eprintln!("{}", "targeting".cyan().bold());
@@ -198,32 +245,106 @@ fn cmd_add(args: &[String]) {
 		std::process::exit(1);
 	}
 
-	// Check for duplicate in existing projects.toml
-	if let Ok(content) = std::fs::read_to_string(&projects_file) {
-		if let Ok(table) = toml::from_str::<toml::Value>(&content) {
-			if let Some(map) = table.as_table() {
-				if map.contains_key(&name) {
-					eprintln!("{}: already registered", name);
-					return;
-				}
-			}
-		}
+	let dir_str = dir.display().to_string();
+	if let Err(e) = edit_projects_file(&projects_file, |content| config::add_project(content, &name, &dir_str)) {
+		eprintln!("{}", e);
+		return;
 	}
+	eprintln!("{}: added ({})", name, dir.display());
 
-	let services_toml = dir.join("services.toml");
-	if !services_toml.exists() {
-		eprintln!("note: no services.toml found in {}", dir.display());
+	if !config::services_file(&dir).exists() {
+		eprintln!("note: no services.toml or Procfile found in {}", dir.display());
 		eprintln!("create one with service definitions, e.g.:");
 		eprintln!("  web = \"npm run dev\"");
 	}
+}
 
-	let mut file = std::fs::OpenOptions::new()
-		.create(true)
-		.append(true)
-		.open(&projects_file)
-		.unwrap();
-	writeln!(file, "{} = {:?}", name, dir.display().to_string()).unwrap();
-	eprintln!("{}: added ({})", name, dir.display());
+fn cmd_remove(args: &[String]) {
+	let Some(name) = args.first() else {
+		eprintln!("usage: ub remove <name>");
+		std::process::exit(1);
+	};
+	let projects_file = protocol::config_dir().join("projects.toml");
+	if let Err(e) = edit_projects_file(&projects_file, |content| config::remove_project(content, name)) {
+		eprintln!("error: {}", e);
+		std::process::exit(1);
+	}
+	eprintln!("{}: removed", name);
+}
+
+fn cmd_rename(args: &[String]) {
+	let (Some(old), Some(new)) = (args.first(), args.get(1)) else {
+		eprintln!("usage: ub rename <old> <new>");
+		std::process::exit(1);
+	};
+	let projects_file = protocol::config_dir().join("projects.toml");
+	if let Err(e) = edit_projects_file(&projects_file, |content| config::rename_project(content, old, new)) {
+		eprintln!("error: {}", e);
+		std::process::exit(1);
+	}
+	eprintln!("{}: renamed to {}", old, new);
+}
+
+fn cmd_config(args: &[String]) {
+	match (args.first().map(|s| s.as_str()), args.get(1)) {
+		(Some("schema"), Some(file)) => {
+			let file = file.trim_end_matches(".toml");
+			let Some(schema) = config::schema(file) else {
+				eprintln!("error: unknown config file '{}' (expected projects, services or config)", file);
+				std::process::exit(1);
+			};
+			println!("{}", serde_json::to_string_pretty(&schema).unwrap_or_default());
+		}
+		(Some("show"), name) => match send_request(&Request::GetConfig) {
+			Response::Config { config, mut services } => {
+				if let Some(name) = name {
+					services.retain(|s| &s.name == name);
+					if services.is_empty() {
+						eprintln!("unknown service: {}", name);
+						std::process::exit(1);
+					}
+				}
+				let out = serde_json::json!({ "config": config, "services": services });
+				println!("{}", serde_json::to_string_pretty(&out).unwrap_or_default());
+			}
+			Response::Error { message, .. } => {
+				eprintln!("error: {}", message);
+				std::process::exit(1);
+			}
+			_ => {
+				eprintln!("error: unexpected response (daemon may predate 'config show'; try 'ub daemon stop')");
+				std::process::exit(1);
+			}
+		},
+		(Some("path"), _) => {
+			let config_dir = protocol::config_dir();
+			let paths = [
+				("config dir", config_dir.clone()),
+				("projects", config_dir.join("projects.toml")),
+				("config", config_dir.join("config.toml")),
+				("state dir", protocol::state_dir()),
+				("socket", protocol::socket_path()),
+				("logs", logs::log_dir()),
+			];
+			for (label, path) in paths {
+				let note = if path.exists() { "".to_string() } else { format!(" {}", "(missing)".dimmed()) };
+				println!("{:<12}{}{}", label, path.display(), note);
+			}
+		}
+		_ => {
+			eprintln!("usage: ub config path");
+			eprintln!("       ub config show [name]");
+			eprintln!("       ub config schema <projects|services|config>");
+			std::process::exit(1);
+		}
+	}
+}
+
+/// Applies an edit to projects.toml, writing the file only if the edit succeeds.
+fn edit_projects_file(path: &std::path::Path, edit: impl FnOnce(&str) -> Result<String, String>) -> Result<(), String> {
+	let content = std::fs::read_to_string(path).unwrap_or_default();
+	let updated = edit(&content)?;
+	std::fs::write(path, updated).map_err(|e| format!("failed to write {}: {}", path.display(), e))
 }
 
 // --- Daemon communication ---
@@ -240,49 +361,128 @@ fn ensure_daemon() -> UnixStream {
 
 	eprintln!("starting daemon...");
 	let daemon_bin = find_daemon_binary();
-
-	let mut cmd = Command::new(&daemon_bin);
-	cmd.args(["daemon", "run"])
-		.stdout(std::process::Stdio::null())
-		.stderr(std::process::Stdio::null());
-
-	match cmd.spawn() {
-		Ok(_) => {}
+	let mut child = match spawn_daemon(&daemon_bin, &["daemon".to_string(), "run".to_string()]) {
+		Ok(child) => child,
 		Err(e) => {
 			eprintln!("error: failed to start daemon: {}", e);
 			eprintln!("binary: {}", daemon_bin.display());
 			std::process::exit(1);
 		}
-	}
+	};
 
-	for _ in 0..50 {
-		std::thread::sleep(std::time::Duration::from_millis(100));
-		if let Some(stream) = connect_daemon() {
+	let (timeout, mut interval) = daemon_start_timing();
+	let deadline = Instant::now() + timeout;
+	let failure = loop {
+		std::thread::sleep(interval);
+		if let Some(stream) = connect_daemon().filter(ping_daemon) {
 			return stream;
 		}
-	}
+		if let Ok(Some(status)) = child.try_wait() {
+			break format!("daemon exited during startup ({})", status);
+		}
+		if Instant::now() >= deadline {
+			break format!(
+				"daemon did not answer within {:.1}s (set UBERMIND_DAEMON_START_TIMEOUT to wait longer)",
+				timeout.as_secs_f64()
+			);
+		}
+		interval = (interval * 2).min(std::time::Duration::from_secs(1));
+	};
 
-	eprintln!("error: daemon did not start in time");
+	let log_path = protocol::daemon_log_path();
+	eprintln!("error: {}", failure);
+	for line in logs::read_last_lines(&log_path, 10).unwrap_or_default() {
+		eprintln!("  {}", line.dimmed());
+	}
+	eprintln!("daemon log: {}", log_path.display());
 	std::process::exit(1);
 }
 
+/// Starts `ubermind <args>` in the background with its output going to the
+/// daemon log, so a failed start can be explained.
+fn spawn_daemon(daemon_bin: &std::path::Path, args: &[String]) -> io::Result<std::process::Child> {
+	let log_path = protocol::daemon_log_path();
+	if let Some(dir) = log_path.parent() {
+		std::fs::create_dir_all(dir)?;
+	}
+	// Each start begins a fresh log. Appending lets the daemon cut it back in
+	// place once it passes logs.max_size_bytes, with later writes at the top
+	std::fs::File::create(&log_path)?;
+	let log = std::fs::OpenOptions::new().append(true).open(&log_path)?;
+	Command::new(daemon_bin)
+		.args(args)
+		.stdin(std::process::Stdio::null())
+		.stdout(log.try_clone()?)
+		.stderr(log)
+		.spawn()
+}
+
+/// How long to wait for an auto-started daemon, and the first delay between
+/// attempts (doubling up to 1s): UBERMIND_DAEMON_START_TIMEOUT in seconds
+/// (default 5) and UBERMIND_DAEMON_START_INTERVAL in milliseconds (default 100).
+fn daemon_start_timing() -> (std::time::Duration, std::time::Duration) {
+	let timeout = std::env::var("UBERMIND_DAEMON_START_TIMEOUT")
+		.ok()
+		.and_then(|v| v.parse::<f64>().ok())
+		.filter(|secs| secs.is_finite() && *secs > 0.0)
+		.map(std::time::Duration::from_secs_f64)
+		.unwrap_or(std::time::Duration::from_secs(5));
+	let interval = std::env::var("UBERMIND_DAEMON_START_INTERVAL")
+		.ok()
+		.and_then(|v| v.parse::<u64>().ok())
+		.filter(|ms| *ms > 0)
+		.map(std::time::Duration::from_millis)
+		.unwrap_or(std::time::Duration::from_millis(100));
+	(timeout, interval)
+}
+
+/// Whether a freshly connected daemon answers a ping; the socket can accept
+/// connections before the daemon is ready, or be left over from a dead one.
+fn ping_daemon(stream: &UnixStream) -> bool {
+	let _ = stream.set_read_timeout(Some(std::time::Duration::from_secs(2)));
+	let mut writer = stream;
+	if writer.write_all(&Request::Ping.encode()).is_err() {
+		return false;
+	}
+	let mut line = String::new();
+	let answered = BufReader::new(stream).read_line(&mut line).is_ok()
+		&& matches!(serde_json::from_str(&line), Ok(Response::Pong));
+	let _ = stream.set_read_timeout(None);
+	answered
+}
+
 fn find_daemon_binary() -> PathBuf {
 	std::env::current_exe().unwrap_or_else(|_| PathBuf::from("ubermind"))
 }
 
 fn send_request(request: &Request) -> Response {
+	let line = send_raw(&request.encode()).unwrap_or_else(|e| {
+		eprintln!("error: {}", e);
+		std::process::exit(1);
+	});
+
+	// Daemons that predate the protocol check can't say they differ; a reply we
+	// can't read is the usual sign
+	serde_json::from_str(&line).unwrap_or(Response::error(
+		"failed to parse daemon response; the daemon may be a different version, restart it with `ub daemon stop`",
+	))
+}
+
+/// Sends an already-serialized request as one line (a trailing newline is added
+/// if missing) and returns the daemon's raw response line. The bytes are not
+/// validated, so this also works for probing how the daemon handles bad input.
+fn send_raw(bytes: &[u8]) -> Result<String, String> {
 	let mut stream = ensure_daemon();
-	let mut data = serde_json::to_vec(request).unwrap();
-	data.push(b'\n');
-	stream.write_all(&data).unwrap();
+	let mut data = bytes.to_vec();
+	if data.last() != Some(&b'\n') {
+		data.push(b'\n');
+	}
+	stream.write_all(&data).map_err(|e| format!("failed to send request: {}", e))?;
 
 	let mut reader = BufReader::new(&stream);
 	let mut line = String::new();
-	reader.read_line(&mut line).unwrap();
-
-	serde_json::from_str(&line).unwrap_or(Response::Error {
-		message: "failed to parse daemon response".to_string(),
-	})
+	reader.read_line(&mut line).map_err(|e| format!("failed to read response: {}", e))?;
+	Ok(line.trim_end().to_string())
 }
 
 // --- Commands that talk to daemon ---
@@ -296,22 +496,76 @@ fn cmd_status(args: &[String]) {
 	}
 }
 
+/// Runs the targets without a daemon until they are ready or have exited, prints
+/// their status, stops them and exits non-zero if any failed or never got ready.
+fn cmd_run_once(args: &[String]) {
+	let (timeout, args) = take_flag_value(args, "--timeout");
+	let timeout = match timeout.as_deref().map(str::parse::<u64>) {
+		None => 60,
+		Some(Ok(secs)) => secs,
+		Some(Err(_)) => {
+			eprintln!("error: --timeout expects a number of seconds");
+			std::process::exit(1);
+		}
+	};
+	let entries = config::load_service_entries();
+	let names = resolve_target_names(&args, &entries);
+	if let Some(unknown) = names.iter().find(|n| !entries.contains_key(*n)) {
+		eprintln!("unknown service: {}", unknown);
+		std::process::exit(1);
+	}
+	if connect_daemon().is_some() {
+		eprintln!("{} the daemon is running; its processes may hold the same ports", "warning:".yellow());
+	}
+
+	let outcome = tokio::runtime::Runtime::new()
+		.unwrap()
+		.block_on(daemon::once::run_once(&names, std::time::Duration::from_secs(timeout)));
+
+	let width = outcome.services.iter().flat_map(|s| s.processes.iter().map(|p| p.name.len())).max().unwrap_or(0);
+	for service in &outcome.services {
+		println!(" {}", service.name.bold());
+		for proc in &service.processes {
+			print!("   └ ");
+			print_process_line(proc, width);
+		}
+	}
+	if outcome.failures.is_empty() {
+		eprintln!("{}", "all ready".green());
+	} else {
+		for failure in &outcome.failures {
+			eprintln!("{} {}", "failed:".red(), failure);
+		}
+		std::process::exit(1);
+	}
+}
+
 fn print_process_line(proc: &ProcessStatus, name_width: usize) {
 	let (circle, uptime, pid, label) = match &proc.state {
 		ProcessState::Running { pid, uptime_secs } => {
 			("●".green().to_string(), format_uptime(*uptime_secs), format!("{}", pid), "on".green().to_string())
 		}
-		ProcessState::Stopped if !proc.autostart => {
+		ProcessState::Stopped { reason: StopReason::NotStarted } if !proc.autostart => {
 			("○".dimmed().to_string(), "-".to_string(), "-".to_string(), "optional".dimmed().to_string())
 		}
-		ProcessState::Stopped => {
-			("●".red().to_string(), "-".to_string(), "-".to_string(), "off".red().to_string())
+		ProcessState::Stopped { reason } => {
+			("●".red().to_string(), "-".to_string(), "-".to_string(), reason.label().red().to_string())
 		}
 		ProcessState::Crashed { exit_code, retries } => {
-			("●".yellow().to_string(), format!("exit {}", exit_code), format!("retry {}", retries), "crashed".yellow().to_string())
+			let label = if proc.oom_killed { "crashed (oom)" } else { "crashed" };
+			("●".yellow().to_string(), format!("exit {}", exit_code), format!("retry {}", retries), label.yellow().to_string())
 		}
-		ProcessState::Failed { exit_code } => {
-			("●".red().to_string(), format!("exit {}", exit_code), "-".to_string(), "failed".red().to_string())
+		ProcessState::Failed { exit_code, finished_at } => {
+			let label = if proc.oom_killed { "failed (oom)" } else { "failed" };
+			let when = match finished_at {
+				0 => "-".to_string(),
+				at => format!("{} ago", format_uptime(unix_now().saturating_sub(*at))),
+			};
+			("●".red().to_string(), when, format!("exit {}", exit_code), label.red().to_string())
+		}
+		ProcessState::Completed { exit_code, finished_at } => {
+			let ago = unix_now().saturating_sub(*finished_at);
+			("●".green().to_string(), format!("{} ago", format_uptime(ago)), format!("exit {}", exit_code), "done".green().to_string())
 		}
 	};
 	let ports = if proc.ports.is_empty() {
@@ -319,76 +573,113 @@ fn print_process_line(proc: &ProcessStatus, name_width: usize) {
 	} else {
 		format!(" {}", proc.ports.iter().map(|p| format!(":{}", p)).collect::<Vec<_>>().join(","))
 	};
-	println!("{} {:<width$} {:<8} {:<8} {}{}", circle, proc.name, uptime, pid, label, ports, width = name_width);
+	let usage = match (proc.cpu_percent, proc.rss_bytes) {
+		(Some(cpu), Some(rss)) if proc.state.is_running() => {
+			format!(" {}", format!("{:.0}% {}", cpu, format_bytes(rss)).dimmed())
+		}
+		_ => String::new(),
+	};
+	let health = match proc.health {
+		Some(Health::Healthy) if proc.state.is_running() => format!(" {}", "✓".green()),
+		Some(Health::Unhealthy) if proc.state.is_running() => format!(" {}", "✗ unhealthy".red()),
+		Some(Health::Unknown) if proc.state.is_running() => format!(" {}", "?".dimmed()),
+		_ => String::new(),
+	};
+	let no_log = if proc.log_file_ok { String::new() } else { format!(" {}", "no log file".yellow()) };
+	let restarts = if proc.restart_count > 0 {
+		format!(" {}", format!("↻{}", proc.restart_count).dimmed())
+	} else {
+		String::new()
+	};
+	println!(
+		"{} {:<width$} {:<8} {:<8} {}{}{}{}{}{}",
+		circle, proc.name, uptime, pid, label, health, ports, usage, restarts, no_log,
+		width = name_width
+	);
 }
 
 fn cmd_start(args: &[String]) {
-	let (mut watch, rest) = parse_watch_opts(args, Some(4));
+	let (command, args) = take_flag_value(args, "--cmd");
+	let (mut watch, rest) = parse_watch_opts(&args, Some(4));
 	let entries = config::load_service_entries();
 
 	let start_all = rest.iter().any(|a| is_all_flag(a));
 	let rest: Vec<String> = rest.into_iter().filter(|a| !is_all_flag(a)).collect();
 
-	let mut target_processes: Vec<String> = Vec::new();
-	let resolved: Vec<String> = if rest.is_empty() {
-		resolve_target_names(&[], &entries)
+	// service.process targets start just that process; siblings are left as they are
+	let mut single: Vec<(String, String)> = Vec::new();
+	let mut whole: Vec<String> = Vec::new();
+	if rest.is_empty() {
+		whole = resolve_target_names(&[], &entries);
 	} else {
-		let mut service_names = Vec::new();
 		for arg in &rest {
 			let (svc, proc) = resolve_dot_target(arg, &entries);
-			if let Some(p) = proc {
-				if !service_names.contains(&svc) {
-					service_names.push(svc);
-				}
-				if !target_processes.contains(&p) {
-					target_processes.push(p);
-				}
+			let target = if let Some(p) = proc {
+				Some((svc, p))
 			} else if entries.contains_key(&svc) {
-				if !service_names.contains(&svc) {
-					service_names.push(svc);
+				if !whole.contains(&svc) {
+					whole.push(svc);
 				}
+				None
 			} else if let Some(current) = get_current_project(&entries) {
-				if !service_names.contains(&current) {
-					service_names.push(current);
-				}
-				if !target_processes.contains(&svc) {
-					target_processes.push(svc);
-				}
+				Some((current, svc))
 			} else {
 				eprintln!("unknown service: {}", svc);
 				eprintln!("registered services: {}", entries.keys().cloned().collect::<Vec<_>>().join(", "));
 				std::process::exit(1);
+			};
+			if let Some(target) = target {
+				if !single.contains(&target) {
+					single.push(target);
+				}
 			}
 		}
-		service_names
-	};
+	}
 
+	let mut resolved = whole.clone();
+	for (svc, _) in &single {
+		if !resolved.contains(svc) {
+			resolved.push(svc.clone());
+		}
+	}
 	if resolved.is_empty() {
 		eprintln!("no services to start");
 		std::process::exit(1);
 	}
 
-	let response = send_request(&Request::Start {
-		names: resolved.clone(),
-		all: start_all || !target_processes.is_empty(),
-		processes: target_processes,
-	});
-	match response {
-		Response::Ok { message } => {
-			if let Some(msg) = message {
-				for line in msg.lines() {
-					eprintln!("{}", line);
-				}
-			}
-			std::thread::sleep(std::time::Duration::from_millis(500));
+	if command.is_some() && (single.len() != 1 || !whole.is_empty() || start_all) {
+		eprintln!("error: --cmd needs exactly one service.process target");
+		std::process::exit(1);
+	}
+
+	let mut requests = Vec::new();
+	if !whole.is_empty() {
+		requests.push(Request::Start { names: whole, all: start_all, processes: vec![] });
+	}
+	for (service, process) in single {
+		requests.push(Request::StartProcess { service, process, command: command.clone() });
+	}
+	for request in &requests {
+		print_ok_or_exit(send_request(request));
+	}
+
+	std::thread::sleep(std::time::Duration::from_millis(500));
+	if !watch.enabled {
+		watch.enabled = true;
+		watch.duration = Some(4);
+	}
+	watch_status(&resolved, &watch);
+}
 
-			if !watch.enabled {
-				watch.enabled = true;
-				watch.duration = Some(4);
+/// Prints the lines of an `Ok` message, or the error and exits.
+fn print_ok_or_exit(response: Response) {
+	match response {
+		Response::Ok { message: Some(msg) } => {
+			for line in msg.lines() {
+				eprintln!("{}", line);
 			}
-			watch_status(&resolved, &watch);
 		}
-		Response::Error { message } => {
+		Response::Error { message, .. } => {
 			eprintln!("error: {}", message);
 			std::process::exit(1);
 		}
@@ -397,78 +688,224 @@ fn cmd_start(args: &[String]) {
 }
 
 fn cmd_stop(args: &[String]) {
-	let (mut watch, rest) = parse_watch_opts(args, Some(4));
+	let (drain_tasks, args) = take_drain_timeout(args);
+	let (dry_run, args) = take_dry_run(&args);
+	let (mut watch, rest) = parse_watch_opts(&args, Some(4));
 	let entries = config::load_service_entries();
-	let names = resolve_target_names(&rest, &entries);
+
+	// service.process targets stop just that process
+	let (dotted, plain): (Vec<String>, Vec<String>) =
+		rest.into_iter().partition(|a| a.contains('.') && !entries.contains_key(a.as_str()));
+	let mut names = if plain.is_empty() && !dotted.is_empty() {
+		Vec::new()
+	} else {
+		resolve_target_names(&plain, &entries)
+	};
+	let mut requests = Vec::new();
+	if !names.is_empty() {
+		requests.push(Request::Stop { names: names.clone(), drain_tasks });
+	}
+	for arg in &dotted {
+		let (service, process) = resolve_dot_target(arg, &entries);
+		if let Some(process) = process {
+			if !names.contains(&service) {
+				names.push(service.clone());
+			}
+			requests.push(Request::StopProcess { service, process });
+		}
+	}
 
 	if names.is_empty() {
 		eprintln!("no services to stop");
 		std::process::exit(1);
 	}
 
-	let response = send_request(&Request::Stop { names: names.clone() });
-	match response {
-		Response::Ok { message } => {
-			if let Some(msg) = message {
-				for line in msg.lines() {
-					eprintln!("{}", line);
-				}
-			}
-			std::thread::sleep(std::time::Duration::from_millis(500));
+	if dry_run {
+		print_dry_run("stop", &names, "nothing to stop", |service, proc| {
+			let targeted = requests.iter().any(|request| match request {
+				Request::Stop { names, .. } => names.contains(&service.name),
+				Request::StopProcess { service: s, process } => *s == service.name && *process == proc.name,
+				_ => false,
+			});
+			(targeted && proc.state.is_running()).then_some("stop")
+		});
+		return;
+	}
 
-			if !watch.enabled {
-				watch.enabled = true;
-				watch.duration = Some(4);
-			}
-			watch_status(&names, &watch);
-		}
-		Response::Error { message } => {
-			eprintln!("error: {}", message);
-			std::process::exit(1);
-		}
-		_ => {}
+	for request in &requests {
+		print_ok_or_exit(send_request(request));
+	}
+
+	std::thread::sleep(std::time::Duration::from_millis(500));
+	if !watch.enabled {
+		watch.enabled = true;
+		watch.duration = Some(4);
 	}
+	watch_status(&names, &watch);
 }
 
 fn cmd_reload(args: &[String]) {
-	let (mut watch, rest) = parse_watch_opts(args, Some(4));
+	let (drain_tasks, args) = take_drain_timeout(args);
+	let (dry_run, args) = take_dry_run(&args);
+	let (mut watch, rest) = parse_watch_opts(&args, Some(4));
 	let entries = config::load_service_entries();
 
 	let reload_all = rest.iter().any(|a| is_all_flag(a));
 	let rest: Vec<String> = rest.into_iter().filter(|a| !is_all_flag(a)).collect();
-	let names = resolve_target_names(&rest, &entries);
+
+	// service.process targets reload just that process (in place when it has a
+	// reload_signal); a bare name reloads the whole service. None means whole.
+	let mut targets: Vec<(String, Option<Vec<String>>)> = Vec::new();
+	for arg in &rest {
+		let (svc, proc) = resolve_dot_target(arg, &entries);
+		match (targets.iter_mut().find(|(name, _)| *name == svc), proc) {
+			(Some((_, Some(processes))), Some(p)) if !processes.contains(&p) => processes.push(p),
+			(Some((_, processes)), None) => *processes = None,
+			(Some(_), _) => {}
+			(None, proc) => targets.push((svc, proc.map(|p| vec![p]))),
+		}
+	}
+	let services: Vec<String> = targets.iter().map(|(name, _)| name.clone()).collect();
+	let names = resolve_target_names(&services, &entries);
 
 	if names.is_empty() {
 		eprintln!("no services to reload");
 		std::process::exit(1);
 	}
 
-	let response = send_request(&Request::Reload {
-		names: names.clone(),
-		all: reload_all,
-		processes: Vec::new(),
-	});
-	match response {
-		Response::Ok { message } => {
-			if let Some(msg) = message {
+	// Each targeted service gets only its own processes
+	let processes_of = |name: &str| targets.iter().find(|(n, _)| n == name).and_then(|(_, p)| p.clone()).unwrap_or_default();
+	let whole: Vec<String> = names.iter().filter(|name| processes_of(name).is_empty()).cloned().collect();
+	let mut requests = Vec::new();
+	if !whole.is_empty() {
+		requests.push(Request::Reload { names: whole, all: reload_all, processes: Vec::new(), drain_tasks });
+	}
+	for name in &names {
+		let processes = processes_of(name);
+		if !processes.is_empty() {
+			requests.push(Request::Reload { names: vec![name.clone()], all: reload_all, processes, drain_tasks });
+		}
+	}
+
+	if dry_run {
+		// A whole-service reload stops it, then starts what a start would;
+		// targeted processes are reloaded in place and the rest left alone
+		let without_daemon = format!("reload would start the daemon, then {}", names.join(", "));
+		let global_config = config::load_global_config();
+		let signalled: Vec<(String, String)> = names
+			.iter()
+			.filter_map(|name| entries.get(name))
+			.flat_map(|entry| config::load_service(entry, &global_config.defaults).processes.into_iter().map(|p| (entry.name.clone(), p)))
+			.filter(|(_, p)| p.reload_signal.is_some())
+			.map(|(service, p)| (service, p.name))
+			.collect();
+		print_dry_run("reload", &names, &without_daemon, |service, proc| {
+			let processes = processes_of(&service.name);
+			if !processes.is_empty() {
+				if !processes.contains(&proc.name) || !service.is_running() {
+					return None;
+				}
+				if !proc.state.is_running() {
+					return Some("start");
+				}
+				let signal = signalled.iter().any(|(s, p)| *s == service.name && *p == proc.name);
+				return Some(if signal { "signal" } else { "restart" });
+			}
+			let starts = reload_all || proc.autostart;
+			match (proc.state.is_running(), starts) {
+				(true, true) => Some("restart"),
+				(true, false) => Some("stop"),
+				(false, true) => Some("start"),
+				(false, false) => None,
+			}
+		});
+		return;
+	}
+
+	for request in &requests {
+		match send_request(request) {
+			Response::Ok { message: Some(msg) } => {
 				for line in msg.lines() {
 					eprintln!("{}", line);
 				}
 			}
-			std::thread::sleep(std::time::Duration::from_millis(500));
-
-			if !watch.enabled {
-				watch.enabled = true;
-				watch.duration = Some(4);
+			Response::Error { message, .. } => {
+				eprintln!("error: {}", message);
+				std::process::exit(1);
 			}
-			watch_status(&names, &watch);
+			_ => {}
 		}
-		Response::Error { message } => {
-			eprintln!("error: {}", message);
-			std::process::exit(1);
+	}
+
+	std::thread::sleep(std::time::Duration::from_millis(500));
+	if !watch.enabled {
+		watch.enabled = true;
+		watch.duration = Some(4);
+	}
+	watch_status(&names, &watch);
+}
+
+fn take_dry_run(args: &[String]) -> (bool, Vec<String>) {
+	let dry_run = args.iter().any(|a| a == "--dry-run");
+	(dry_run, args.iter().filter(|a| *a != "--dry-run").cloned().collect())
+}
+
+/// Prints what `verb` would do to each process of `names`, with its current
+/// state, without sending anything. `plan` returns the action for a process,
+/// or `None` when it would be left alone. Without a daemon there is no state to
+/// show, so `without_daemon` says what would happen instead.
+fn print_dry_run(
+	verb: &str,
+	names: &[String],
+	without_daemon: &str,
+	plan: impl Fn(&ServiceStatus, &ProcessStatus) -> Option<&'static str>,
+) {
+	// Asking for status must not start a daemon just for a dry run
+	if connect_daemon().is_none() {
+		eprintln!("daemon not running: {}", without_daemon);
+		eprintln!("{}", "dry run, nothing was sent".dimmed());
+		return;
+	}
+	let services = match send_request(&Request::Status) {
+		Response::Status { services, .. } => services,
+		_ => Vec::new(),
+	};
+	let affected: Vec<(&ServiceStatus, Vec<(&'static str, &ProcessStatus)>)> = services
+		.iter()
+		.filter(|s| names.contains(&s.name))
+		.map(|s| (s, s.processes.iter().filter_map(|p| plan(s, p).map(|action| (action, p))).collect::<Vec<_>>()))
+		.filter(|(_, procs)| !procs.is_empty())
+		.collect();
+
+	let width = affected.iter().flat_map(|(_, procs)| procs.iter().map(|(_, p)| p.name.len())).max().unwrap_or(0);
+	for (service, procs) in &affected {
+		println!(" {}", service.name.bold());
+		for (action, proc) in procs {
+			print!("   └ {:<7} ", action);
+			print_process_line(proc, width);
 		}
-		_ => {}
 	}
+	if affected.is_empty() {
+		eprintln!("nothing to {}", verb);
+	}
+	eprintln!("{}", "dry run, nothing was sent".dimmed());
+}
+
+/// How long stop and reload let running tasks finish before killing them.
+const TASK_DRAIN_SECS: u64 = 300;
+
+/// `--drain-timeout <secs>`, defaulting to `TASK_DRAIN_SECS`; 0 kills tasks at once.
+fn take_drain_timeout(args: &[String]) -> (Option<u64>, Vec<String>) {
+	let (value, rest) = take_flag_value(args, "--drain-timeout");
+	let secs = match value.as_deref().map(str::parse::<u64>) {
+		None => TASK_DRAIN_SECS,
+		Some(Ok(secs)) => secs,
+		Some(Err(_)) => {
+			eprintln!("error: --drain-timeout expects a number of seconds");
+			std::process::exit(1);
+		}
+	};
+	(Some(secs).filter(|&s| s > 0), rest)
 }
 
 fn cmd_restart(args: &[String]) {
@@ -515,38 +952,364 @@ fn cmd_restart(args: &[String]) {
 			eprintln!("registered services: {}", entries.keys().cloned().collect::<Vec<_>>().join(", "));
 			std::process::exit(1);
 		}
-	} else {
-		let (svc, proc) = resolve_dot_target(&rest[0], &entries);
-		(svc, proc.or_else(|| Some(rest[1].clone())))
+	} else {
+		let (svc, proc) = resolve_dot_target(&rest[0], &entries);
+		(svc, proc.or_else(|| Some(rest[1].clone())))
+	};
+
+	if let Some(process_name) = process {
+		let response = send_request(&Request::Restart {
+			service: service.clone(),
+			process: process_name.clone(),
+		});
+		match response {
+			Response::Ok { message } => {
+				if let Some(msg) = message {
+					eprintln!("{}", msg);
+				}
+				std::thread::sleep(std::time::Duration::from_millis(500));
+				watch_status(&[service], &watch);
+			}
+			Response::Error { message, .. } => {
+				eprintln!("error: {}", message);
+				std::process::exit(1);
+			}
+			_ => {}
+		}
+	} else {
+		let mut reload_args = vec![service];
+		reload_args.extend(reload_extra);
+		cmd_reload(&reload_args);
+	}
+}
+
+fn cmd_adopt(args: &[String]) {
+	let (Some(target), Some(pid)) = (args.first(), args.get(1).and_then(|p| p.parse::<u32>().ok())) else {
+		eprintln!("usage: ub adopt <name[.process]> <pid>");
+		std::process::exit(1);
+	};
+	let entries = config::load_service_entries();
+	let (service, process) = resolve_dot_target(target, &entries);
+	print_ok_or_exit(send_request(&Request::Adopt { service, process, pid }));
+}
+
+fn cmd_drain(args: &[String]) {
+	let entries = config::load_service_entries();
+	let (service, process) = match args.first().map(|target| resolve_dot_target(target, &entries)) {
+		Some((service, Some(process))) => (service, process),
+		Some((service, None)) if args.len() == 2 => (service, args[1].clone()),
+		_ => {
+			eprintln!("usage: ub drain <service.process>");
+			std::process::exit(1);
+		}
+	};
+	eprintln!("{}", format!("draining {}.{}...", service, process).dimmed());
+	print_ok_or_exit(send_request(&Request::Drain { service, process }));
+}
+
+/// Ctrl-], as in telnet: detaches `ub connect` and leaves the process running.
+const DETACH_KEY: u8 = 0x1d;
+
+fn cmd_connect(args: &[String]) {
+	use std::os::fd::AsFd;
+
+	let entries = config::load_service_entries();
+	let (service, process) = match args.first().map(|target| resolve_dot_target(target, &entries)) {
+		Some((service, Some(process))) => (service, process),
+		Some((service, None)) if args.len() == 2 => (service, args[1].clone()),
+		_ => {
+			eprintln!("usage: ub connect <service.process>");
+			std::process::exit(1);
+		}
+	};
+	if !io::IsTerminal::is_terminal(&io::stdin()) {
+		eprintln!("error: ub connect needs a terminal");
+		std::process::exit(1);
+	}
+
+	let mut size = terminal_size();
+	let request = Request::Connect {
+		service: service.clone(),
+		process: process.clone(),
+		rows: size.map(|(rows, _)| rows),
+		cols: size.map(|(_, cols)| cols),
+	};
+	let mut stream = ensure_daemon();
+	if let Err(e) = stream.write_all(&request.encode()) {
+		eprintln!("error: failed to send request: {}", e);
+		std::process::exit(1);
+	}
+	let mut responses = BufReader::new(stream.try_clone().expect("failed to clone socket")).lines();
+	match responses.next().and_then(|line| line.ok()).map(|line| serde_json::from_str(&line)) {
+		Some(Ok(Response::Ok { .. })) => {}
+		Some(Ok(Response::Error { message, .. })) => {
+			eprintln!("error: {}", message);
+			std::process::exit(1);
+		}
+		_ => {
+			eprintln!("error: no answer from daemon");
+			std::process::exit(1);
+		}
+	}
+	eprintln!("{}", format!("connected to {}.{}, Ctrl-] detaches", service, process).dimmed());
+
+	// Output is relayed on its own thread; the session's end is reported back here
+	let (ended_tx, ended) = std::sync::mpsc::channel();
+	std::thread::spawn(move || {
+		let mut stdout = io::stdout();
+		for line in responses {
+			let Ok(line) = line else { break };
+			match serde_json::from_str(&line) {
+				Ok(Response::Log { line, .. }) => {
+					let _ = stdout.write_all(line.as_bytes());
+					let _ = stdout.flush();
+				}
+				Ok(Response::Ok { message }) => {
+					let _ = ended_tx.send(message);
+					return;
+				}
+				_ => {}
+			}
+		}
+		let _ = ended_tx.send(Some("connection to daemon lost".to_string()));
+	});
+
+	let raw = RawTerminal::enable();
+	let stdin = io::stdin();
+	let mut buf = [0u8; 4096];
+	let message = loop {
+		if let Ok(message) = ended.try_recv() {
+			break message;
+		}
+		let now = terminal_size();
+		if let (Some((rows, cols)), true) = (now, now != size) {
+			size = now;
+			let _ = stream.write_all(&Request::Resize { rows, cols }.encode());
+		}
+		let mut fds = [nix::poll::PollFd::new(stdin.as_fd(), nix::poll::PollFlags::POLLIN)];
+		if !matches!(nix::poll::poll(&mut fds, nix::poll::PollTimeout::from(200u8)), Ok(n) if n > 0) {
+			continue;
+		}
+		let n = match stdin.lock().read(&mut buf) {
+			Ok(0) | Err(_) => break None,
+			Ok(n) => n,
+		};
+		let keys = &buf[..n];
+		let detach = keys.iter().position(|&b| b == DETACH_KEY);
+		let typed = &keys[..detach.unwrap_or(n)];
+		if !typed.is_empty() {
+			let request = Request::Input { data: typed.to_vec() };
+			if stream.write_all(&request.encode()).is_err() {
+				break Some("connection to daemon lost".to_string());
+			}
+		}
+		if detach.is_some() {
+			break None;
+		}
+	};
+	drop(raw);
+	eprintln!();
+	match message {
+		Some(message) => eprintln!("{}", message),
+		None => eprintln!("{}", format!("detached from {}.{}", service, process).dimmed()),
+	}
+}
+
+/// Rows and columns of the terminal on stdout.
+fn terminal_size() -> Option<(u16, u16)> {
+	let mut size = libc::winsize { ws_row: 0, ws_col: 0, ws_xpixel: 0, ws_ypixel: 0 };
+	// SAFETY: TIOCGWINSZ only writes into the winsize we pass
+	if unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } < 0 || size.ws_row == 0 {
+		return None;
+	}
+	Some((size.ws_row, size.ws_col))
+}
+
+/// Puts the terminal in raw mode, so every key goes to the connected process.
+/// The previous settings are restored on drop.
+struct RawTerminal {
+	saved: Option<nix::sys::termios::Termios>,
+}
+
+impl RawTerminal {
+	fn enable() -> Self {
+		use nix::sys::termios::{cfmakeraw, tcgetattr, tcsetattr, SetArg};
+		let stdin = io::stdin();
+		let Ok(saved) = tcgetattr(&stdin) else {
+			return Self { saved: None };
+		};
+		let mut raw = saved.clone();
+		cfmakeraw(&mut raw);
+		let _ = tcsetattr(&stdin, SetArg::TCSANOW, &raw);
+		Self { saved: Some(saved) }
+	}
+}
+
+impl Drop for RawTerminal {
+	fn drop(&mut self) {
+		if let Some(ref saved) = self.saved {
+			let _ = nix::sys::termios::tcsetattr(io::stdin(), nix::sys::termios::SetArg::TCSANOW, saved);
+		}
+	}
+}
+
+fn cmd_clear(args: &[String]) {
+	let entries = config::load_service_entries();
+	let names = resolve_target_names(args, &entries);
+
+	match send_request(&Request::Clear { names }) {
+		Response::Ok { message: Some(msg) } => {
+			for line in msg.lines() {
+				eprintln!("{}", line);
+			}
+		}
+		Response::Error { message, .. } => {
+			eprintln!("error: {}", message);
+			std::process::exit(1);
+		}
+		_ => {}
+	}
+}
+
+const BAR_FORMAT: &str = "▲{running} ●{stopped} ✖{crashed}";
+
+/// One line of process counts for status bars. It never starts the daemon; with
+/// no daemon every count is 0.
+fn cmd_bar(args: &[String]) {
+	let (format, args) = take_flag_value(args, "--format");
+	let color = args.iter().any(|a| a == "--color");
+	if let Some(arg) = args.iter().find(|a| *a != "--color") {
+		eprintln!("unknown option: {}", arg);
+		eprintln!("usage: ub bar [--format '{}'] [--color]", BAR_FORMAT);
+		std::process::exit(1);
+	}
+
+	let (mut running, mut stopped, mut crashed) = (0usize, 0usize, 0usize);
+	if let Some(mut stream) = connect_daemon() {
+		// A status bar polls on a timer; a hung daemon must not pile up blocked runs
+		let _ = stream.set_read_timeout(Some(std::time::Duration::from_secs(2)));
+		let request = Request::Status.encode();
+		let mut line = String::new();
+		if stream.write_all(&request).is_ok() && BufReader::new(&stream).read_line(&mut line).is_ok() {
+			if let Ok(Response::Status { services, .. }) = serde_json::from_str(&line) {
+				for proc in services.iter().flat_map(|s| &s.processes) {
+					match proc.state {
+						ProcessState::Running { .. } => running += 1,
+						ProcessState::Crashed { .. } | ProcessState::Failed { .. } => crashed += 1,
+						ProcessState::Stopped { .. } | ProcessState::Completed { .. } => stopped += 1,
+					}
+				}
+			}
+		}
+	}
+
+	let paint = |n: usize, paint: fn(&str) -> String| if color { paint(&n.to_string()) } else { n.to_string() };
+	let line = format
+		.as_deref()
+		.unwrap_or(BAR_FORMAT)
+		.replace("{running}", &paint(running, |s| s.green().to_string()))
+		.replace("{stopped}", &paint(stopped, |s| s.dimmed().to_string()))
+		.replace("{crashed}", &paint(crashed, |s| s.red().to_string()))
+		.replace("{total}", &(running + stopped + crashed).to_string());
+	println!("{}", line);
+}
+
+fn cmd_diff(args: &[String]) {
+	let entries = config::load_service_entries();
+	let service = match args.first() {
+		Some(name) => name.clone(),
+		None => get_current_project(&entries).unwrap_or_else(|| {
+			eprintln!("usage: ub diff <service>");
+			std::process::exit(1);
+		}),
+	};
+
+	let diffs = match send_request(&Request::Diff { service: service.clone() }) {
+		Response::Diff { diffs } => diffs,
+		Response::Error { code: Some(ErrorCode::NotRunning), .. } => {
+			eprintln!("{} is not running; a reload would start it as configured", service);
+			return;
+		}
+		Response::Error { message, .. } => {
+			eprintln!("error: {}", message);
+			std::process::exit(1);
+		}
+		_ => return,
 	};
+	if diffs.is_empty() {
+		eprintln!("{}: no changes", service);
+		return;
+	}
 
-	if let Some(process_name) = process {
-		let response = send_request(&Request::Restart {
-			service: service.clone(),
-			process: process_name.clone(),
-		});
-		match response {
-			Response::Ok { message } => {
-				if let Some(msg) = message {
-					eprintln!("{}", msg);
-				}
-				std::thread::sleep(std::time::Duration::from_millis(500));
-				watch_status(&[service], &watch);
-			}
-			Response::Error { message } => {
-				eprintln!("error: {}", message);
+	let width = diffs.iter().map(|d| d.process.len()).max().unwrap_or(0);
+	println!(" {}", service.bold());
+	for diff in &diffs {
+		let (mark, note) = match diff.change {
+			DiffChange::Added if diff.running => ("+".green().to_string(), "new, starts on reload"),
+			DiffChange::Added => ("+".green().to_string(), "new, not autostarted"),
+			DiffChange::Removed if diff.running => ("-".red().to_string(), "removed, stops on reload"),
+			DiffChange::Removed => ("-".red().to_string(), "removed"),
+			DiffChange::Changed if diff.running => ("~".yellow().to_string(), "changed, restarts on reload"),
+			DiffChange::Changed => ("~".yellow().to_string(), "changed"),
+		};
+		println!("   {} {:<width$}  {}", mark, diff.process, note.dimmed(), width = width);
+		for (field, running, configured) in &diff.fields {
+			println!("       {}: {} {} {}", field, running.red(), "→".dimmed(), configured.green());
+		}
+	}
+}
+
+fn cmd_events(args: &[String]) {
+	let mut service = None;
+	let mut limit = None;
+	let mut iter = args.iter();
+	while let Some(arg) = iter.next() {
+		match arg.as_str() {
+			"--service" | "-s" => service = iter.next().cloned(),
+			"-n" => limit = iter.next().and_then(|n| n.parse().ok()),
+			_ => {
+				eprintln!("usage: ub events [--service <name>] [-n <count>]");
 				std::process::exit(1);
 			}
-			_ => {}
 		}
-	} else {
-		let mut reload_args = vec![service];
-		reload_args.extend(reload_extra);
-		cmd_reload(&reload_args);
+	}
+
+	let events = match send_request(&Request::Events { service, limit }) {
+		Response::Events { events } => events,
+		Response::Error { message, .. } => {
+			eprintln!("error: {}", message);
+			std::process::exit(1);
+		}
+		_ => return,
+	};
+	if events.is_empty() {
+		eprintln!("no events");
+		return;
+	}
+
+	let now = unix_now();
+	let name_width = events.iter().map(|e| e.service.len() + e.process.len() + 1).max().unwrap_or(0);
+	for event in &events {
+		let ago = format!("{:>10}", format!("{} ago", format_uptime(now.saturating_sub(event.at))));
+		let state = match event.state.as_str() {
+			"Running" => "started".green().to_string(),
+			"Crashed" => "crashed".yellow().to_string(),
+			"Failed" => "failed".red().to_string(),
+			"Completed" => "completed".green().to_string(),
+			_ => "stopped".dimmed().to_string(),
+		};
+		let name = format!("{}.{}", event.service, event.process);
+		println!("{}  {:<width$}  {} {}", ago.dimmed(), name, state, event.detail.dimmed(), width = name_width);
 	}
 }
 
 fn cmd_logs(args: &[String]) {
+	let (colorizer, args) = take_color_levels(args);
+	let (date, args) = take_flag_value(&args, "--date");
+	let (file, args) = take_flag_value(&args, "--file");
+	let list = args.iter().any(|a| a == "--list");
+	let args: Vec<String> = args.into_iter().filter(|a| a != "--list").collect();
+	let args = args.as_slice();
 	let svc_entries = config::load_service_entries();
 
 	let (service, process) = if args.is_empty() {
@@ -562,55 +1325,34 @@ fn cmd_logs(args: &[String]) {
 		(svc, proc.or_else(|| args.get(1).map(|s| s.to_string())))
 	};
 
-	let log_dir = logs::service_log_dir(&service);
-	if !log_dir.exists() {
-		eprintln!("no logs for {}", service);
-		std::process::exit(1);
+	if list {
+		list_logs(&service, process.as_deref());
+		return;
 	}
-
-	let mut files: Vec<PathBuf> = Vec::new();
-	if let Ok(dir_entries) = std::fs::read_dir(&log_dir) {
-		for entry in dir_entries.flatten() {
-			let path = entry.path();
-			let name = path
-				.file_name()
-				.unwrap_or_default()
-				.to_string_lossy()
-				.to_string();
-			if !name.ends_with(".log") {
-				continue;
-			}
-			if let Some(ref proc_filter) = process {
-				if !name.starts_with(proc_filter.as_str()) {
-					continue;
-				}
-			}
-			files.push(path);
+	let path = if let Some(file) = file {
+		let path = logs::service_log_dir(&service).join(&file);
+		if !path.is_file() {
+			eprintln!("no log file {} (see ub logs {} --list)", path.display(), service);
+			std::process::exit(1);
 		}
-	}
-
-	files.sort();
-
-	if files.is_empty() {
-		eprintln!("no log files found");
-		std::process::exit(1);
-	}
-
-	let latest = files.last().unwrap();
-	let content = std::fs::read_to_string(latest).unwrap_or_default();
-
-	let lines: Vec<&str> = content.lines().collect();
-	let start = if lines.len() > 100 {
-		lines.len() - 100
+		path
+	} else if let Some(date) = date {
+		dated_log_file(&service, process.as_deref(), &date)
 	} else {
-		0
+		latest_log_file(&svc_entries, &service, process.as_deref())
 	};
-	for line in &lines[start..] {
-		println!("{}", line);
+	let lines = logs::read_last_lines(&path, 100).unwrap_or_default();
+	for line in &lines {
+		match &colorizer {
+			Some(c) => println!("{}", c.colorize(line)),
+			None => println!("{}", line),
+		}
 	}
 }
 
 fn cmd_tail(args: &[String]) {
+	let (colorizer, args) = take_color_levels(args);
+	let args = args.as_slice();
 	let svc_entries = config::load_service_entries();
 
 	let (service, process) = if args.is_empty() {
@@ -626,49 +1368,111 @@ fn cmd_tail(args: &[String]) {
 		(svc, proc.or_else(|| args.get(1).cloned()))
 	};
 
-	let log_dir = logs::service_log_dir(&service);
-	if !log_dir.exists() {
-		eprintln!("no logs for {}", service);
-		std::process::exit(1);
-	}
+	let latest = latest_log_file(&svc_entries, &service, process.as_deref());
+	let mut cmd = Command::new("tail");
+	cmd.args(["-f", "-n", "100"]);
+	cmd.arg(latest);
 
-	let mut files: Vec<PathBuf> = Vec::new();
-	if let Ok(dir_entries) = std::fs::read_dir(&log_dir) {
-		for entry in dir_entries.flatten() {
-			let path = entry.path();
-			let name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
-			if !name.ends_with(".log") {
-				continue;
-			}
-			if let Some(ref proc_filter) = process {
-				if !name.starts_with(proc_filter.as_str()) {
-					continue;
-				}
-			}
-			files.push(path);
+	let Some(colorizer) = colorizer else {
+		let status = cmd.status().unwrap_or_else(|e| {
+			eprintln!("error: {}", e);
+			std::process::exit(1);
+		});
+		std::process::exit(status.code().unwrap_or(1));
+	};
+
+	let mut child = cmd.stdout(std::process::Stdio::piped()).spawn().unwrap_or_else(|e| {
+		eprintln!("error: {}", e);
+		std::process::exit(1);
+	});
+	if let Some(stdout) = child.stdout.take() {
+		for line in BufReader::new(stdout).lines() {
+			let Ok(line) = line else { break };
+			println!("{}", colorizer.colorize(&line));
 		}
 	}
+	let status = child.wait().map(|s| s.code().unwrap_or(1)).unwrap_or(1);
+	std::process::exit(status);
+}
 
-	files.sort();
+/// Strips `--color-levels` from `args`. Returns a colorizer when it was given and
+/// `NO_COLOR` is unset.
+fn take_color_levels(args: &[String]) -> (Option<logs::LevelColorizer>, Vec<String>) {
+	let wanted = args.iter().any(|a| a == "--color-levels");
+	let rest = args.iter().filter(|a| *a != "--color-levels").cloned().collect();
+	let no_color = std::env::var("NO_COLOR").is_ok_and(|v| !v.is_empty());
+	if !wanted || no_color {
+		return (None, rest);
+	}
+	let global_config = config::load_global_config();
+	(Some(logs::LevelColorizer::new(global_config.logs.level_pattern.as_deref())), rest)
+}
 
+/// `ub logs <service> --list`: every log file with its date and size.
+fn list_logs(service: &str, process: Option<&str>) {
+	let files = logs::list_log_files(service, process);
 	if files.is_empty() {
-		eprintln!("no log files found");
+		eprintln!("no log files for {}", service);
 		std::process::exit(1);
 	}
+	for file in &files {
+		let date = file.date.map(|(y, m, d)| format!("20{:02}-{:02}-{:02}", y, m, d)).unwrap_or_else(|| "-".to_string());
+		println!("{}  {:>8}  {}", date, format_bytes(file.size), file.name);
+	}
+}
 
-	let latest = files.last().unwrap();
-	let mut cmd = Command::new("tail");
-	cmd.args(["-f", "-n", "100"]);
-	cmd.arg(latest);
-	let status = cmd.status().unwrap_or_else(|e| {
-		eprintln!("error: {}", e);
+/// The newest log file from `date` (`YY-MMDD`, as in log file names).
+fn dated_log_file(service: &str, process: Option<&str>, date: &str) -> PathBuf {
+	let Some(wanted) = logs::parse_log_date(&format!("_ {}.log", date)) else {
+		eprintln!("error: --date expects YY-MMDD, e.g. 26-0213");
 		std::process::exit(1);
-	});
-	std::process::exit(status.code().unwrap_or(1));
+	};
+	let mut files: Vec<logs::LogFileInfo> =
+		logs::list_log_files(service, process).into_iter().filter(|f| f.date == Some(wanted)).collect();
+	files.sort_by_key(|f| f.modified);
+	let Some(newest) = files.pop() else {
+		eprintln!("no log files for {} from {} (see ub logs {} --list)", service, date, service);
+		std::process::exit(1);
+	};
+	if !files.is_empty() {
+		let others: Vec<&str> = files.iter().map(|f| f.name.as_str()).collect();
+		eprintln!("{}", format!("showing {}; also from that day: {} (use --file)", newest.name, others.join(", ")).dimmed());
+	}
+	newest.path
+}
+
+/// Finds the log file to read for a service or process: the process's configured
+/// `log_file` if it has one, otherwise the newest file in the service log directory.
+fn latest_log_file(entries: &BTreeMap<String, ServiceEntry>, service: &str, process: Option<&str>) -> PathBuf {
+	if let (Some(proc_name), Some(entry)) = (process, entries.get(service)) {
+		let global_config = config::load_global_config();
+		let svc = config::load_service(entry, &global_config.defaults);
+		if let Some(path) = svc.processes.iter().find(|p| p.name == proc_name).and_then(|p| p.log_file.clone()) {
+			return path;
+		}
+	}
+
+	let log_dir = logs::service_log_dir(service);
+	if !log_dir.exists() {
+		eprintln!("no logs for {}", service);
+		std::process::exit(1);
+	}
+
+	match logs::newest(logs::list_log_files(service, process)) {
+		Some(file) => file.path,
+		None => {
+			eprintln!("no log files found");
+			std::process::exit(1);
+		}
+	}
 }
 
 fn cmd_echo(args: &[String]) {
 	let svc_entries = config::load_service_entries();
+	if args.iter().any(|a| a == "--all") {
+		echo_all(&svc_entries);
+		return;
+	}
 
 	let (service, process) = if args.is_empty() {
 		if let Some(current) = get_current_project(&svc_entries) {
@@ -683,39 +1487,204 @@ fn cmd_echo(args: &[String]) {
 		(svc, proc.or_else(|| args.get(1).cloned()))
 	};
 
-	loop {
-		let response = send_request(&Request::Logs {
-			service: service.clone(),
-			process: process.clone(),
-			follow: true,
-		});
+	// A whole service is interleaved line by line behind a padded, colored name
+	let mut prefixer = process.is_none().then(|| {
+		let global_config = config::load_global_config();
+		let names = svc_entries
+			.get(&service)
+			.map(|entry| config::load_service(entry, &global_config.defaults).processes)
+			.unwrap_or_default()
+			.into_iter()
+			.map(|p| p.name)
+			.collect();
+		LinePrefixer::new(names)
+	});
 
-		match response {
-			Response::Log { line } => {
-				print!("{}", line);
-				let _ = io::stdout().flush();
+	let request = Request::Logs { service: service.clone(), process: process.clone(), follow: true };
+	let mut stream = ensure_daemon();
+	if let Err(e) = stream.write_all(&request.encode()) {
+		eprintln!("error: failed to send request: {}", e);
+		std::process::exit(1);
+	}
+
+	let mut stdout = io::stdout();
+	for line in BufReader::new(&stream).lines() {
+		let Ok(line) = line else { break };
+		match serde_json::from_str(&line) {
+			Ok(Response::Log { line, process: from, .. }) => {
+				match (&mut prefixer, from) {
+					(Some(prefixer), Some(from)) => prefixer.write(&mut stdout, &from, &line),
+					_ => {
+						let _ = stdout.write_all(line.as_bytes());
+					}
+				}
+				let _ = stdout.flush();
+			}
+			Ok(Response::Ok { message: Some(msg) }) => {
+				if let Some(prefixer) = &mut prefixer {
+					prefixer.finish(&mut stdout);
+				}
+				eprintln!("{}", msg);
+				return;
+			}
+			Ok(Response::Error { code: Some(ErrorCode::NotRunning), .. }) if svc_entries.contains_key(&service) => {
+				echo_from_disk(&service, process.as_deref(), prefixer.as_mut());
+				return;
+			}
+			Ok(Response::Error { message, .. }) => {
+				eprintln!("error: {}", message);
+				std::process::exit(1);
+			}
+			_ => {}
+		}
+	}
+}
+
+/// What `ub echo` shows for a service the daemon isn't running: the end of
+/// each process's latest log file.
+fn echo_from_disk(service: &str, process: Option<&str>, prefixer: Option<&mut LinePrefixer>) {
+	eprintln!("{}", "(service not running, showing last logs)".dimmed());
+	let names: Vec<String> = match (process, &prefixer) {
+		(Some(process), _) => vec![process.to_string()],
+		(None, Some(prefixer)) => prefixer.names.clone(),
+		(None, None) => Vec::new(),
+	};
+	let files: Vec<(String, PathBuf)> = names
+		.into_iter()
+		.filter_map(|name| Some((name.clone(), logs::newest(logs::list_log_files(service, Some(&name)))?.path)))
+		.collect();
+	if files.is_empty() {
+		eprintln!("no logs for {}", service);
+		std::process::exit(1);
+	}
+
+	let mut stdout = io::stdout();
+	let mut prefixer = prefixer.filter(|_| files.len() > 1);
+	for (name, path) in files {
+		for line in logs::read_last_lines(&path, 100).unwrap_or_default() {
+			match &mut prefixer {
+				Some(prefixer) => prefixer.write(&mut stdout, &name, &format!("{}\n", line)),
+				None => println!("{}", line),
+			}
+		}
+	}
+}
+
+/// Interleaves the output of every running process, each line behind its
+/// `service.process`.
+fn echo_all(svc_entries: &BTreeMap<String, ServiceEntry>) {
+	let global_config = config::load_global_config();
+	let names = svc_entries
+		.iter()
+		.flat_map(|(name, entry)| {
+			config::load_service(entry, &global_config.defaults)
+				.processes
+				.into_iter()
+				.map(move |p| format!("{}.{}", name, p.name))
+		})
+		.collect();
+	let mut prefixer = LinePrefixer::new(names);
+
+	let mut stream = ensure_daemon();
+	if let Err(e) = stream.write_all(&Request::LogsAll { service: None }.encode()) {
+		eprintln!("error: failed to send request: {}", e);
+		std::process::exit(1);
+	}
+
+	let mut stdout = io::stdout();
+	for line in BufReader::new(&stream).lines() {
+		let Ok(line) = line else { break };
+		match serde_json::from_str(&line) {
+			Ok(Response::Log { line, process: Some(process), service: Some(service) }) => {
+				prefixer.write(&mut stdout, &format!("{}.{}", service, process), &line);
+				let _ = stdout.flush();
 			}
-			Response::Error { message } => {
+			Ok(Response::Error { message, .. }) => {
 				eprintln!("error: {}", message);
 				std::process::exit(1);
 			}
 			_ => {}
 		}
+	}
+	prefixer.finish(&mut stdout);
+}
+
+/// Prefixes each line of interleaved output with its process name, padded and
+/// colored. Colors follow the order of processes in the config, so they stay the
+/// same between runs. `NO_COLOR` turns color off.
+struct LinePrefixer {
+	names: Vec<String>,
+	width: usize,
+	color: bool,
+	partial: HashMap<String, String>,
+}
+
+impl LinePrefixer {
+	fn new(names: Vec<String>) -> Self {
+		Self {
+			width: names.iter().map(|n| n.len()).max().unwrap_or(0),
+			names,
+			color: !std::env::var("NO_COLOR").is_ok_and(|v| !v.is_empty()),
+			partial: HashMap::new(),
+		}
+	}
+
+	fn prefix(&self, process: &str) -> String {
+		let name = format!("{:<width$} |", process, width = self.width);
+		if !self.color {
+			return name;
+		}
+		const PALETTE: [owo_colors::AnsiColors; 6] = [
+			owo_colors::AnsiColors::Cyan,
+			owo_colors::AnsiColors::Yellow,
+			owo_colors::AnsiColors::Green,
+			owo_colors::AnsiColors::Magenta,
+			owo_colors::AnsiColors::Blue,
+			owo_colors::AnsiColors::BrightRed,
+		];
+		let index = self.names.iter().position(|n| n == process).unwrap_or_else(|| {
+			process.bytes().fold(0usize, |h, b| h.wrapping_mul(31).wrapping_add(b as usize))
+		});
+		name.color(PALETTE[index % PALETTE.len()]).to_string()
+	}
+
+	/// Writes the complete lines in `data`, holding back a trailing partial line.
+	fn write(&mut self, out: &mut impl Write, process: &str, data: &str) {
+		let partial = self.partial.entry(process.to_string()).or_default();
+		partial.push_str(data);
+		let Some(end) = partial.rfind('\n') else { return };
+		let complete: String = partial.drain(..=end).collect();
+		let prefix = self.prefix(process);
+		for line in complete.lines() {
+			let _ = writeln!(out, "{} {}", prefix, line);
+		}
+	}
 
-		std::thread::sleep(std::time::Duration::from_millis(100));
+	/// Writes whatever partial lines are left.
+	fn finish(&mut self, out: &mut impl Write) {
+		let partial = std::mem::take(&mut self.partial);
+		for (process, rest) in partial.into_iter().filter(|(_, rest)| !rest.is_empty()) {
+			let _ = writeln!(out, "{} {}", self.prefix(&process), rest);
+		}
 	}
 }
 
 fn cmd_show(args: &[String]) {
 	let entries = config::load_service_entries();
+	let resolved = args.iter().any(|a| a == "--resolved");
+	let args: Vec<String> = args.iter().filter(|a| *a != "--resolved").cloned().collect();
 
-	let filtered_args: Vec<String> = if args.len() >= 2 && args[1] == "show" {
+	let mut filtered_args: Vec<String> = if args.len() >= 2 && args[1] == "show" {
 		let mut new_args = vec![args[0].clone()];
 		new_args.extend_from_slice(&args[2..]);
 		new_args
 	} else {
 		args.to_vec()
 	};
+	if filtered_args.len() == 1 && filtered_args[0].contains('.') && !entries.contains_key(&filtered_args[0]) {
+		let (svc, proc) = resolve_dot_target(&filtered_args[0], &entries);
+		filtered_args = std::iter::once(svc).chain(proc).collect();
+	}
 
 	let (service_name, process_name) = if filtered_args.is_empty() {
 		if let Some(current) = get_current_project(&entries) {
@@ -750,11 +1719,20 @@ fn cmd_show(args: &[String]) {
 		}
 	};
 
+	if resolved {
+		let Some(proc_name) = process_name else {
+			eprintln!("--resolved needs a process: ub show <name.process> --resolved");
+			std::process::exit(1);
+		};
+		show_resolved(&service_name, &proc_name);
+		return;
+	}
+
 	let global_config = config::load_global_config();
 	let service = config::load_service(service_entry, &global_config.defaults);
 
 	if service.processes.is_empty() {
-		let services_path = service_entry.dir.join("services.toml");
+		let services_path = config::services_file(&service_entry.dir);
 		eprintln!("no services defined ({})", services_path.display());
 		std::process::exit(1);
 	}
@@ -767,7 +1745,7 @@ fn cmd_show(args: &[String]) {
 			std::process::exit(1);
 		}
 	} else {
-		let services_path = service_entry.dir.join("services.toml");
+		let services_path = config::services_file(&service_entry.dir);
 		println!("{}", services_path.display().to_string().dimmed());
 		println!();
 		for proc in &service.processes {
@@ -776,8 +1754,37 @@ fn cmd_show(args: &[String]) {
 				ServiceType::Service => String::new(),
 			};
 			let optional = if !proc.autostart { " (optional)".dimmed().to_string() } else { String::new() };
-			println!("{}{}{} {}", proc.name.cyan(), type_tag, optional, proc.command.dimmed());
+			let nice = proc.nice.map(|n| format!(" (nice {})", n).dimmed().to_string()).unwrap_or_default();
+			println!("{}{}{}{} {}", proc.name.cyan(), type_tag, optional, nice, proc.command.dimmed());
+		}
+	}
+}
+
+/// The argv and cwd the daemon last launched a process with.
+fn show_resolved(service: &str, process: &str) {
+	if connect_daemon().is_none() {
+		eprintln!("daemon not running");
+		std::process::exit(1);
+	}
+	let request = Request::ProcessStatus { service: service.to_string(), process: process.to_string() };
+	match send_request(&request) {
+		Response::ProcessStatus { status: Some(ProcessStatus { resolved: Some(resolved), .. }) } => {
+			println!("{} {:?}", "argv".cyan(), resolved.argv);
+			println!("{} {}", "cwd ".cyan(), resolved.cwd.display());
+		}
+		Response::ProcessStatus { status: Some(_) } => {
+			eprintln!("{}.{} hasn't been launched by the daemon yet", service, process);
+			std::process::exit(1);
+		}
+		Response::ProcessStatus { status: None } => {
+			eprintln!("{}.{} is not managed by the daemon", service, process);
+			std::process::exit(1);
+		}
+		Response::Error { message, .. } => {
+			eprintln!("error: {}", message);
+			std::process::exit(1);
 		}
+		_ => {}
 	}
 }
 
@@ -797,15 +1804,11 @@ fn cmd_daemon(args: &[String]) {
 				eprintln!("daemon already running");
 				return;
 			}
-			let extra_args: Vec<String> = args[1..].iter().cloned().collect();
+			let extra_args: Vec<String> = args[1..].to_vec();
 			let daemon_bin = find_daemon_binary();
-			let mut cmd = Command::new(&daemon_bin);
 			let mut spawn_args = vec!["daemon".to_string(), "run".to_string()];
 			spawn_args.extend(extra_args);
-			cmd.args(&spawn_args)
-				.stdout(std::process::Stdio::null())
-				.stderr(std::process::Stdio::null());
-			match cmd.spawn() {
+			match spawn_daemon(&daemon_bin, &spawn_args) {
 				Ok(_) => eprintln!("daemon started"),
 				Err(e) => {
 					eprintln!("error: {}", e);
@@ -822,17 +1825,147 @@ fn cmd_daemon(args: &[String]) {
 				_ => eprintln!("daemon not running"),
 			}
 		}
+		"reload" => match send_request(&Request::ReloadConfig) {
+			Response::Ok { message } => {
+				for line in message.unwrap_or_default().lines() {
+					eprintln!("{}", line);
+				}
+			}
+			Response::Error { message, .. } => {
+				eprintln!("error: {}", message);
+				std::process::exit(1);
+			}
+			_ => {}
+		},
+		"ping" => daemon_ping(&args[1..]),
+		"send" => {
+			// Raw JSON request from the argument or stdin, for scripting and debugging
+			let payload = match args.get(1) {
+				Some(json) => json.clone(),
+				None => {
+					let mut buf = String::new();
+					let _ = io::stdin().read_to_string(&mut buf);
+					buf
+				}
+			};
+			// The protocol is one request per line, so pretty-printed JSON is re-encoded
+			let payload = match serde_json::from_str::<serde_json::Value>(&payload) {
+				Ok(value) => value.to_string(),
+				Err(e) => {
+					eprintln!("error: invalid JSON: {}", e);
+					std::process::exit(1);
+				}
+			};
+			match send_raw(payload.as_bytes()) {
+				Ok(line) => println!("{}", line),
+				Err(e) => {
+					eprintln!("error: {}", e);
+					std::process::exit(1);
+				}
+			}
+		}
 		"status" => {
-			if connect_daemon().is_some() {
-				let pid = std::fs::read_to_string(protocol::pid_path()).unwrap_or_default();
-				eprintln!("daemon running (pid {})", pid.trim());
-			} else {
+			if connect_daemon().is_none() {
 				eprintln!("daemon not running");
+				return;
+			}
+			match send_request(&Request::DaemonInfo) {
+				Response::DaemonInfo {
+					pid,
+					version,
+					started_at,
+					config_reloads,
+					open_log_files,
+					fd_limit,
+					protocol,
+					subscribers,
+				} => {
+					let up = format_uptime(unix_now().saturating_sub(started_at));
+					eprintln!("daemon running (pid {})", pid);
+					eprintln!("  version         {} (protocol v{})", version, protocol);
+					if protocol != protocol::PROTOCOL_VERSION {
+						eprintln!(
+							"  {} this ub speaks protocol v{}; restart the daemon",
+							"warning:".yellow(),
+							protocol::PROTOCOL_VERSION
+						);
+					}
+					eprintln!("  started         {} ago (unix {})", up, started_at);
+					eprintln!("  config reloads  {}", config_reloads);
+					match fd_limit {
+						Some(limit) => eprintln!("  log files open  {} (fd limit {})", open_log_files, limit),
+						None => eprintln!("  log files open  {}", open_log_files),
+					}
+					let viewers: usize = subscribers.values().sum();
+					eprintln!("  live viewers    {}", viewers);
+					for (process, count) in subscribers.iter().filter(|(_, count)| **count > 0) {
+						eprintln!("    {:<14}{}", process, count);
+					}
+				}
+				// Daemons that predate daemon_info
+				_ => {
+					let pid = std::fs::read_to_string(protocol::pid_path()).unwrap_or_default();
+					eprintln!("daemon running (pid {})", pid.trim());
+				}
 			}
 		}
 		_ => {
-			eprintln!("usage: ub daemon [start|stop|status|run]");
+			eprintln!("usage: ub daemon [start|stop|status|reload|ping [--count N]|run|send <json>]");
+		}
+	}
+}
+
+/// Times ping round trips over one connection, so the figure is how long the
+/// daemon takes to answer a request rather than to accept a connection.
+fn daemon_ping(args: &[String]) {
+	let (count, _) = take_flag_value(args, "--count");
+	let count = match count.as_deref().map(str::parse::<u32>) {
+		None => 1,
+		Some(Ok(n)) if n > 0 => n,
+		Some(_) => {
+			eprintln!("error: --count expects a positive number");
+			std::process::exit(1);
+		}
+	};
+	let Some(stream) = connect_daemon() else {
+		eprintln!("daemon not running");
+		std::process::exit(1);
+	};
+	let _ = stream.set_read_timeout(Some(std::time::Duration::from_secs(5)));
+	let mut reader = BufReader::new(stream.try_clone().expect("failed to clone socket"));
+	let mut writer = stream;
+	let request = Request::Ping.encode();
+
+	let mut times = Vec::new();
+	for _ in 0..count {
+		let started = Instant::now();
+		let mut line = String::new();
+		let reply = writer.write_all(&request).and_then(|_| reader.read_line(&mut line));
+		let elapsed = started.elapsed();
+		match reply {
+			Ok(n) if n > 0 && matches!(serde_json::from_str(&line), Ok(Response::Pong)) => {}
+			Ok(0) => {
+				eprintln!("error: daemon closed the connection");
+				std::process::exit(1);
+			}
+			Ok(_) => {
+				eprintln!("error: unexpected reply: {}", line.trim());
+				std::process::exit(1);
+			}
+			Err(e) => {
+				eprintln!("error: no pong: {}", e);
+				std::process::exit(1);
+			}
 		}
+		println!("pong {:.2}ms", elapsed.as_secs_f64() * 1000.0);
+		times.push(elapsed.as_secs_f64() * 1000.0);
+	}
+
+	if times.len() > 1 {
+		let min = times.iter().cloned().fold(f64::INFINITY, f64::min);
+		let max = times.iter().cloned().fold(0.0, f64::max);
+		let avg = times.iter().sum::<f64>() / times.len() as f64;
+		println!("{} pings, min/avg/max {:.2}/{:.2}/{:.2}ms", times.len(), min, avg, max);
 	}
 }
 
@@ -842,14 +1975,17 @@ fn cmd_serve(args: &[String]) {
 	let has_daemon = args.iter().any(|a| a == "-d" || a == "--daemon");
 
 	if has_stop {
-		cmd_daemon(&["stop".to_string()].to_vec());
+		cmd_daemon(&["stop".to_string()]);
 	} else if has_status {
-		cmd_daemon(&["status".to_string()].to_vec());
+		cmd_daemon(&["status".to_string()]);
 	} else if has_daemon {
-		cmd_daemon(&vec!["start".to_string(), "--http".to_string()]);
+		cmd_daemon(&["start".to_string(), "--http".to_string()]);
 	} else {
 		// Foreground: run daemon in-process with --http
-		cmd_daemon(&vec!["run".to_string(), "--foreground".to_string(), "--http".to_string()]);
+		// --attach service.process passes through to the daemon
+		let mut run_args = vec!["run".to_string(), "--foreground".to_string(), "--http".to_string()];
+		run_args.extend(args.iter().cloned());
+		cmd_daemon(&run_args);
 	}
 }
 
@@ -898,11 +2034,11 @@ fn parse_watch_opts(args: &[String], default_duration: Option<u64>) -> (WatchOpt
 	(opts, rest)
 }
 
-fn fetch_status() -> (Vec<ServiceStatus>, Option<u16>) {
+fn fetch_status() -> (Vec<ServiceStatus>, Option<u16>, Option<String>) {
 	let response = send_request(&Request::Status);
 	match response {
-		Response::Status { services, http_port } => (services, http_port),
-		Response::Error { message } => {
+		Response::Status { services, http_port, version } => (services, http_port, version),
+		Response::Error { message, .. } => {
 			eprintln!("error: {}", message);
 			std::process::exit(1);
 		}
@@ -913,8 +2049,38 @@ fn fetch_status() -> (Vec<ServiceStatus>, Option<u16>) {
 	}
 }
 
+/// Removes `flag <value>` or `flag=value` from `args`, returning the value.
+fn take_flag_value(args: &[String], flag: &str) -> (Option<String>, Vec<String>) {
+	let mut value = None;
+	let mut rest = Vec::new();
+	let mut iter = args.iter();
+	while let Some(arg) = iter.next() {
+		if arg == flag {
+			match iter.next() {
+				Some(v) => value = Some(v.clone()),
+				None => {
+					eprintln!("error: {} needs a value", flag);
+					std::process::exit(1);
+				}
+			}
+		} else if let Some(v) = arg.strip_prefix(flag).and_then(|s| s.strip_prefix('=')) {
+			value = Some(v.to_string());
+		} else {
+			rest.push(arg.clone());
+		}
+	}
+	(value, rest)
+}
+
 fn render_status(args: &[String]) -> usize {
-	let (services, http_port) = fetch_status();
+	let (only, args) = take_flag_value(args, "--only");
+	let args = &args[..];
+	if let Some(filter) = only.as_deref().filter(|f| *f != "unhealthy") {
+		eprintln!("unknown --only filter: '{}' (expected 'unhealthy')", filter);
+		std::process::exit(1);
+	}
+	let only_unhealthy = only.is_some();
+	let (services, http_port, daemon_version) = fetch_status();
 	let entries = config::load_service_entries();
 
 	let (process_filter, resolved_args) = if let Some(first) = args.first() {
@@ -960,7 +2126,7 @@ fn render_status(args: &[String]) -> usize {
 	}
 
 	if let Some(ref proc_name) = process_filter {
-		for name in &sorted_filter {
+		if let Some(name) = sorted_filter.first() {
 			if let Some(status) = status_map.get(name) {
 				for proc in &status.processes {
 					if proc.name == *proc_name {
@@ -991,6 +2157,12 @@ fn render_status(args: &[String]) -> usize {
 		let entry = entries.get(name);
 		let status = status_map.get(name);
 		let running = status.map(|s| s.is_running()).unwrap_or(false);
+		let processes: Vec<&ProcessStatus> = status
+			.map(|s| s.processes.iter().filter(|p| !only_unhealthy || p.health == Some(Health::Unhealthy)).collect())
+			.unwrap_or_default();
+		if only_unhealthy && processes.is_empty() {
+			continue;
+		}
 
 		let detail = if let Some(entry) = entry {
 			if let Some(ref cmd) = entry.inline_command {
@@ -1006,15 +2178,18 @@ fn render_status(args: &[String]) -> usize {
 		println!(" {} {:<width$} {}", circle, name, detail, width = max_name_width);
 		lines += 1;
 
-		if let Some(status) = status {
-			for proc in &status.processes {
-				print!("   └ ");
-				print_process_line(proc, max_proc_name_width);
-				lines += 1;
-			}
+		for proc in processes {
+			print!("   └ ");
+			print_process_line(proc, max_proc_name_width);
+			lines += 1;
 		}
 	}
 
+	if only_unhealthy && lines == 0 {
+		println!(" {} no unhealthy processes", "●".green());
+		lines += 1;
+	}
+
 	if show_all || (resolved_args.is_empty() && current_project.is_none()) {
 		println!();
 		lines += 1;
@@ -1026,6 +2201,18 @@ fn render_status(args: &[String]) -> usize {
 		lines += 1;
 	}
 
+	let cli_version = env!("CARGO_PKG_VERSION");
+	if daemon_version.as_deref().unwrap_or("unknown") != cli_version {
+		println!();
+		println!(
+			" {} daemon is {}, cli is {} — restart daemon to match (ub daemon stop && ub daemon start)",
+			"!".yellow(),
+			daemon_version.as_deref().unwrap_or("older"),
+			cli_version
+		);
+		lines += 2;
+	}
+
 	lines
 }
 
@@ -1073,6 +2260,21 @@ fn format_uptime(secs: u64) -> String {
 	}
 }
 
+fn format_bytes(bytes: u64) -> String {
+	const UNITS: [&str; 4] = ["B", "K", "M", "G"];
+	let mut value = bytes as f64;
+	let mut unit = 0;
+	while value >= 1024.0 && unit < UNITS.len() - 1 {
+		value /= 1024.0;
+		unit += 1;
+	}
+	if unit == 0 || value >= 10.0 {
+		format!("{:.0}{}", value, UNITS[unit])
+	} else {
+		format!("{:.1}{}", value, UNITS[unit])
+	}
+}
+
 fn parse_dot_target(name: &str) -> (&str, Option<&str>) {
 	if let Some(dot) = name.find('.') {
 		(&name[..dot], Some(&name[dot + 1..]))
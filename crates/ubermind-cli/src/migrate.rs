@@ -0,0 +1,102 @@
+use crate::config;
+use crate::protocol::config_dir;
+use owo_colors::OwoColorize;
+use std::path::PathBuf;
+
+/// A file `ub migrate` would write.
+struct Planned {
+	path: PathBuf,
+	content: String,
+}
+
+/// `projects.toml` from the old `projects` (name: dir) and `commands`
+/// (name: command) files. Commands become `[name]` tables with `run`.
+fn generate_projects_toml(projects: &[(String, String)], commands: &[(String, String)]) -> String {
+	let mut doc = toml_edit::DocumentMut::new();
+	for (name, dir) in projects {
+		doc.insert(name, toml_edit::value(dir.as_str()));
+	}
+	for (name, command) in commands {
+		let mut table = toml_edit::Table::new();
+		table.insert("run", toml_edit::value(command.as_str()));
+		doc.insert(name, toml_edit::Item::Table(table));
+	}
+	doc.to_string()
+}
+
+/// `services.toml` with one `name = "command"` entry per Procfile line.
+fn generate_services_toml(procfile: &[(String, String)]) -> String {
+	let mut doc = toml_edit::DocumentMut::new();
+	for (name, command) in procfile {
+		doc.insert(name, toml_edit::value(command.as_str()));
+	}
+	doc.to_string()
+}
+
+fn read_pairs(path: &std::path::Path) -> Vec<(String, String)> {
+	std::fs::read_to_string(path).map(|c| config::parse_procfile(&c)).unwrap_or_default()
+}
+
+pub fn cmd_migrate(args: &[String]) {
+	let dry_run = args.iter().any(|a| a == "--dry-run" || a == "-n");
+	let dir = config_dir();
+	let projects = read_pairs(&dir.join("projects"));
+	let commands = read_pairs(&dir.join("commands"));
+
+	if projects.is_empty() && commands.is_empty() {
+		eprintln!("nothing to migrate: no projects or commands file in {}", dir.display());
+		return;
+	}
+
+	let mut planned = Vec::new();
+	let mut skipped = Vec::new();
+
+	let projects_toml = dir.join("projects.toml");
+	if projects_toml.exists() {
+		skipped.push(projects_toml);
+	} else {
+		planned.push(Planned { path: projects_toml, content: generate_projects_toml(&projects, &commands) });
+	}
+
+	let mut process_count = 0;
+	for (_, project_dir) in &projects {
+		let project_dir = config::expand_tilde(project_dir);
+		let procfile = read_pairs(&project_dir.join("Procfile"));
+		if procfile.is_empty() {
+			continue;
+		}
+		process_count += procfile.len();
+		let services_toml = project_dir.join("services.toml");
+		if services_toml.exists() {
+			skipped.push(services_toml);
+		} else {
+			planned.push(Planned { path: services_toml, content: generate_services_toml(&procfile) });
+		}
+	}
+
+	for file in &planned {
+		if dry_run {
+			println!("{} {}", "would create".cyan(), file.path.display());
+			for line in file.content.lines() {
+				println!("  {}", line.dimmed());
+			}
+			println!();
+		} else if let Err(e) = std::fs::write(&file.path, &file.content) {
+			eprintln!("error: failed to write {}: {}", file.path.display(), e);
+			std::process::exit(1);
+		} else {
+			eprintln!("created {}", file.path.display());
+		}
+	}
+	for path in &skipped {
+		eprintln!("{} {} (already exists)", "skipped".yellow(), path.display());
+	}
+
+	eprintln!(
+		"{} projects, {} commands, {} Procfile processes{}",
+		projects.len(),
+		commands.len(),
+		process_count,
+		if dry_run { "; nothing written (dry run)" } else { "" }
+	);
+}
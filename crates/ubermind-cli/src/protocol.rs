@@ -1,6 +1,11 @@
-use crate::types::ServiceStatus;
+use crate::config::GlobalConfig;
+use crate::types::{ProcessDiff, ProcessEvent, ProcessStatus, Service, ServiceStatus};
 use serde::{Deserialize, Serialize};
 
+/// Bumped whenever `Request` or `Response` change incompatibly. The CLI sends it
+/// as `protocol` next to `cmd`, and the daemon refuses requests from another version.
+pub const PROTOCOL_VERSION: u32 = 1;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "cmd", rename_all = "snake_case")]
 pub enum Request {
@@ -11,31 +16,229 @@ pub enum Request {
 		#[serde(default)]
 		processes: Vec<String>,
 	},
-	Stop { names: Vec<String> },
+	/// `drain_tasks` gives running tasks that many seconds to finish before
+	/// they are killed; other processes stop at once either way.
+	Stop {
+		names: Vec<String>,
+		#[serde(default)]
+		drain_tasks: Option<u64>,
+	},
+	/// Start one process of a service, leaving its siblings as they are.
+	/// `command` replaces the configured command for this launch only.
+	StartProcess {
+		service: String,
+		process: String,
+		#[serde(default)]
+		command: Option<String>,
+	},
+	/// Stop one process of a service, leaving its siblings as they are.
+	StopProcess { service: String, process: String },
 	Reload {
 		names: Vec<String>,
 		#[serde(default)]
 		all: bool,
 		#[serde(default)]
 		processes: Vec<String>,
+		#[serde(default)]
+		drain_tasks: Option<u64>,
 	},
 	Restart { service: String, process: String },
+	/// Run the process's drain command, wait its drain period, then stop it.
+	Drain { service: String, process: String },
 	Kill { service: String, process: String },
+	/// Take over a running process the daemon didn't start. `process` may be
+	/// omitted for a service with a single process.
+	Adopt {
+		service: String,
+		#[serde(default)]
+		process: Option<String>,
+		pid: u32,
+	},
+	Clear { names: Vec<String> },
+	/// Re-read projects.toml and services.toml and reconcile running services.
+	ReloadConfig,
+	/// The daemon's loaded config and the process definitions it would use.
+	GetConfig,
 	Status,
+	/// Status of one process, without scanning every service.
+	ProcessStatus { service: String, process: String },
 	Logs { service: String, process: Option<String>, follow: bool },
+	/// Follow the output of every running process, or only those of `service`.
+	/// Processes that start later are picked up; lines carry their service and process.
+	LogsAll {
+		#[serde(default)]
+		service: Option<String>,
+	},
+	/// How the service's config differs from what the daemon is running.
+	Diff { service: String },
+	/// Attach to the terminal of a `pty = true` process. The daemon answers
+	/// `Ok`, then relays its output as `Log` lines and takes `Input` and
+	/// `Resize` on the same connection until either side goes away.
+	Connect {
+		service: String,
+		process: String,
+		#[serde(default)]
+		rows: Option<u16>,
+		#[serde(default)]
+		cols: Option<u16>,
+	},
+	/// Keys typed into a connected terminal, as the raw bytes read: a
+	/// keystroke needn't be valid UTF-8 or arrive in one read.
+	Input { data: Vec<u8> },
+	/// The connected client's terminal changed size.
+	Resize { rows: u16, cols: u16 },
+	/// Recent process state changes kept in memory by the daemon.
+	Events {
+		#[serde(default)]
+		service: Option<String>,
+		#[serde(default)]
+		limit: Option<usize>,
+	},
+	/// When the daemon started and how often it has reloaded its config.
+	DaemonInfo,
 	Ping,
 	Shutdown,
 }
 
+impl Request {
+	/// The variant name as it appears in the `cmd` field.
+	pub fn kind(&self) -> &'static str {
+		match self {
+			Request::Start { .. } => "start",
+			Request::Stop { .. } => "stop",
+			Request::StartProcess { .. } => "start_process",
+			Request::StopProcess { .. } => "stop_process",
+			Request::Reload { .. } => "reload",
+			Request::Restart { .. } => "restart",
+			Request::Drain { .. } => "drain",
+			Request::Kill { .. } => "kill",
+			Request::Adopt { .. } => "adopt",
+			Request::Clear { .. } => "clear",
+			Request::ReloadConfig => "reload_config",
+			Request::GetConfig => "get_config",
+			Request::Status => "status",
+			Request::ProcessStatus { .. } => "process_status",
+			Request::Logs { .. } => "logs",
+			Request::LogsAll { .. } => "logs_all",
+			Request::Diff { .. } => "diff",
+			Request::Connect { .. } => "connect",
+			Request::Input { .. } => "input",
+			Request::Resize { .. } => "resize",
+			Request::Events { .. } => "events",
+			Request::DaemonInfo => "daemon_info",
+			Request::Ping => "ping",
+			Request::Shutdown => "shutdown",
+		}
+	}
+
+	/// The request as one line, tagged with `PROTOCOL_VERSION`.
+	pub fn encode(&self) -> Vec<u8> {
+		let mut value = serde_json::to_value(self).unwrap();
+		value["protocol"] = PROTOCOL_VERSION.into();
+		let mut data = serde_json::to_vec(&value).unwrap();
+		data.push(b'\n');
+		data
+	}
+
+	/// Parses a request line, checking `protocol` first so a client of another
+	/// version is told to restart the daemon instead of getting a parse error.
+	/// Lines without it (hand-written, or from older clients) are parsed as is.
+	pub fn decode(line: &str) -> Result<Request, String> {
+		let value: serde_json::Value = serde_json::from_str(line).map_err(|e| format!("invalid request: {}", e))?;
+		if let Some(client) = value.get("protocol").and_then(|v| v.as_u64()) {
+			if client != u64::from(PROTOCOL_VERSION) {
+				return Err(format!(
+					"protocol mismatch: client v{}, daemon v{}, restart daemon",
+					client, PROTOCOL_VERSION
+				));
+			}
+		}
+		serde_json::from_value(value).map_err(|e| format!("invalid request: {}", e))
+	}
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum Response {
 	Ok { message: Option<String> },
-	Status { services: Vec<ServiceStatus>, http_port: Option<u16> },
-	Log { line: String },
-	Error { message: String },
+	Status {
+		services: Vec<ServiceStatus>,
+		http_port: Option<u16>,
+		/// Daemon version; absent from daemons that predate it.
+		#[serde(default)]
+		version: Option<String>,
+	},
+	Config { config: GlobalConfig, services: Vec<Service> },
+	/// `None` when the service isn't managed by the daemon (so the process is stopped).
+	ProcessStatus { status: Option<ProcessStatus> },
+	/// Captured output. While following, `service` and `process` name where it
+	/// came from.
+	Log {
+		line: String,
+		#[serde(default, skip_serializing_if = "Option::is_none")]
+		process: Option<String>,
+		#[serde(default, skip_serializing_if = "Option::is_none")]
+		service: Option<String>,
+	},
+	Events { events: Vec<ProcessEvent> },
+	Diff { diffs: Vec<ProcessDiff> },
+	DaemonInfo {
+		pid: u32,
+		version: String,
+		/// Unix seconds.
+		started_at: u64,
+		/// Config reloads (SIGHUP or `ub daemon reload`) since the daemon started.
+		config_reloads: u64,
+		/// Log files currently held open; idle ones are closed until next written.
+		#[serde(default)]
+		open_log_files: usize,
+		/// The daemon's soft limit on open file descriptors, when known.
+		#[serde(default, skip_serializing_if = "Option::is_none")]
+		fd_limit: Option<u64>,
+		/// `PROTOCOL_VERSION` of the daemon; 0 from daemons that predate it.
+		#[serde(default)]
+		protocol: u32,
+		/// Live output subscribers per running process, keyed `service.process`.
+		#[serde(default)]
+		subscribers: std::collections::BTreeMap<String, usize>,
+	},
+	Error {
+		message: String,
+		/// What went wrong, for clients that handle some failures themselves;
+		/// absent for failures with no code of their own.
+		#[serde(default, skip_serializing_if = "Option::is_none")]
+		code: Option<ErrorCode>,
+	},
 	Progress { service: String, message: String },
 	Pong,
+	/// Sent on an idle stream every `daemon.keepalive_secs`; carries nothing and
+	/// is skipped by clients.
+	Keepalive,
+}
+
+impl Response {
+	pub fn error(message: impl Into<String>) -> Self {
+		Response::Error { message: message.into(), code: None }
+	}
+}
+
+/// Why a request failed, carried next to the message so clients never have
+/// to match on its wording. The HTTP API sends the same codes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+	UnknownService,
+	ProcessNotFound,
+	NotRunning,
+	AlreadyRunning,
+	PortInUse,
+	NotReady,
+	InvalidConfig,
+	SecretUnavailable,
+	/// A failure with no code of its own.
+	BadRequest,
+	/// The daemon failed while handling the request.
+	Internal,
 }
 
 pub const SOCKET_NAME: &str = "daemon.sock";
@@ -48,6 +251,12 @@ pub fn pid_path() -> std::path::PathBuf {
 	state_dir().join("daemon.pid")
 }
 
+/// Output of a daemon started in the background. Truncated on each start; past
+/// `logs.max_size_bytes` the daemon moves its contents to `daemon.log.1`.
+pub fn daemon_log_path() -> std::path::PathBuf {
+	state_dir().join("daemon.log")
+}
+
 pub fn state_dir() -> std::path::PathBuf {
 	if let Ok(dir) = std::env::var("XDG_STATE_HOME") {
 		std::path::PathBuf::from(dir).join("ubermind")
@@ -71,3 +280,45 @@ pub fn config_dir() -> std::path::PathBuf {
 fn home_dir() -> Option<std::path::PathBuf> {
 	std::env::var("HOME").ok().map(std::path::PathBuf::from)
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_request_protocol_version() {
+		let line = String::from_utf8(Request::Ping.encode()).unwrap();
+		assert!(matches!(Request::decode(&line), Ok(Request::Ping)));
+		assert!(matches!(Request::decode(r#"{"cmd":"stop","names":["web"]}"#), Ok(Request::Stop { .. })));
+
+		let newer = format!(r#"{{"cmd":"teleport","protocol":{}}}"#, PROTOCOL_VERSION + 1);
+		let err = Request::decode(&newer).unwrap_err();
+		assert!(err.starts_with("protocol mismatch"), "{}", err);
+	}
+
+	#[test]
+	fn test_process_state_reads_older_daemons() {
+		use crate::types::{ProcessState, StopReason};
+		let stopped = ProcessState::Stopped { reason: StopReason::Manual };
+		let json = serde_json::to_string(&stopped).unwrap();
+		assert_eq!(json, r#"{"Stopped":{"reason":"Manual"}}"#);
+		assert_eq!(serde_json::from_str::<ProcessState>(&json).unwrap(), stopped);
+
+		let legacy: ProcessState = serde_json::from_str(r#""Stopped""#).unwrap();
+		assert_eq!(legacy, ProcessState::Stopped { reason: StopReason::NotStarted });
+		let running: ProcessState = serde_json::from_str(r#"{"Running":{"pid":7,"uptime_secs":3}}"#).unwrap();
+		assert_eq!(running, ProcessState::Running { pid: 7, uptime_secs: 3 });
+		assert!(serde_json::from_str::<ProcessState>(r#""Running""#).is_err());
+	}
+
+	#[test]
+	fn test_error_code_is_optional() {
+		let coded = Response::Error { message: "web: not running".to_string(), code: Some(ErrorCode::NotRunning) };
+		let json = serde_json::to_string(&coded).unwrap();
+		assert_eq!(json, r#"{"type":"error","message":"web: not running","code":"not_running"}"#);
+
+		let legacy: Response = serde_json::from_str(r#"{"type":"error","message":"web: not running"}"#).unwrap();
+		assert!(matches!(legacy, Response::Error { code: None, .. }));
+		assert_eq!(serde_json::to_string(&Response::error("boom")).unwrap(), r#"{"type":"error","message":"boom"}"#);
+	}
+}
@@ -0,0 +1,65 @@
+use std::collections::HashMap;
+use std::process::Command;
+
+/// Env values of the form `keychain:<service>/<account>` are fetched from the OS
+/// secret store when the process is spawned, so they never sit in config files.
+const PREFIX: &str = "keychain:";
+
+/// Returns `env` with every keychain reference replaced by its secret. Errors name
+/// the variable and reference, never the value.
+pub fn resolve_env(env: &HashMap<String, String>) -> Result<HashMap<String, String>, String> {
+	env.iter()
+		.map(|(key, value)| match value.strip_prefix(PREFIX) {
+			Some(reference) => lookup(reference)
+				.map(|secret| (key.clone(), secret))
+				.map_err(|e| format!("secret for {} ({}{}): {}", key, PREFIX, reference, e)),
+			None => Ok((key.clone(), value.clone())),
+		})
+		.collect()
+}
+
+/// `resolve_env` off the async runtime, since each lookup runs a helper
+/// process. An env without keychain references is returned as is.
+pub async fn resolve_env_async(env: &HashMap<String, String>) -> Result<HashMap<String, String>, String> {
+	if !has_secrets(env) {
+		return Ok(env.clone());
+	}
+	let env = env.clone();
+	tokio::task::spawn_blocking(move || resolve_env(&env))
+		.await
+		.map_err(|e| format!("secret lookup failed: {}", e))?
+}
+
+pub fn has_secrets(env: &HashMap<String, String>) -> bool {
+	env.values().any(|v| v.starts_with(PREFIX))
+}
+
+fn lookup(reference: &str) -> Result<String, String> {
+	let (service, account) = reference
+		.split_once('/')
+		.filter(|(s, a)| !s.is_empty() && !a.is_empty())
+		.ok_or("expected keychain:<service>/<account>")?;
+
+	let mut cmd = if cfg!(target_os = "macos") {
+		let mut cmd = Command::new("security");
+		cmd.args(["find-generic-password", "-s", service, "-a", account, "-w"]);
+		cmd
+	} else {
+		// libsecret's CLI; stores created with `secret-tool store --label=... service S account A`
+		let mut cmd = Command::new("secret-tool");
+		cmd.args(["lookup", "service", service, "account", account]);
+		cmd
+	};
+	let output = cmd
+		.output()
+		.map_err(|e| format!("cannot run {}: {}", cmd.get_program().to_string_lossy(), e))?;
+	if !output.status.success() {
+		return Err("not found in the keychain".to_string());
+	}
+	let secret = String::from_utf8(output.stdout).map_err(|_| "secret is not valid UTF-8".to_string())?;
+	let secret = secret.strip_suffix('\n').unwrap_or(&secret).to_string();
+	if secret.is_empty() {
+		return Err("keychain entry is empty".to_string());
+	}
+	Ok(secret)
+}
@@ -1,6 +1,7 @@
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::Command;
+use crate::protocol::{self, Request};
 
 const REPO: &str = "mrgnw/ubermind";
 const CURRENT_VERSION: &str = env!("CARGO_PKG_VERSION");
@@ -56,15 +57,14 @@ pub fn cmd_self_update() {
 		std::process::exit(1);
 	}
 
-	for bin_name in &["ubermind"] {
-		let src = tmpdir.join(bin_name);
-		let dest = install_dir.join(bin_name);
-		if src.exists() {
-			if let Err(e) = replace_binary(&src, &dest) {
-				eprintln!("error: failed to install {}: {}", bin_name, e);
-				let _ = fs::remove_dir_all(&tmpdir);
-				std::process::exit(1);
-			}
+	let bin_name = "ubermind";
+	let src = tmpdir.join(bin_name);
+	let dest = install_dir.join(bin_name);
+	if src.exists() {
+		if let Err(e) = replace_binary(&src, &dest) {
+			eprintln!("error: failed to install {}: {}", bin_name, e);
+			let _ = fs::remove_dir_all(&tmpdir);
+			std::process::exit(1);
 		}
 	}
 
@@ -81,6 +81,78 @@ pub fn cmd_self_update() {
 	}
 }
 
+pub fn cmd_self_uninstall(args: &[String]) {
+	let purge = args.iter().any(|a| a == "--purge");
+	let yes = args.iter().any(|a| a == "--yes" || a == "-y");
+
+	let mut targets: Vec<PathBuf> = Vec::new();
+	let state_dir = protocol::state_dir();
+	if state_dir.exists() {
+		targets.push(state_dir);
+	}
+	let config_dir = protocol::config_dir();
+	if purge && config_dir.exists() {
+		targets.push(config_dir);
+	}
+	if let Ok(exe) = std::env::current_exe() {
+		let exe = exe.canonicalize().unwrap_or(exe);
+		if let Some(dir) = exe.parent() {
+			let ub = dir.join("ub");
+			let links_to_exe = fs::read_link(&ub).is_ok_and(|target| dir.join(target) == exe);
+			if links_to_exe {
+				targets.push(ub);
+			}
+		}
+		targets.push(exe);
+	}
+
+	let daemon_running = crate::connect_daemon().is_some();
+	eprintln!("this will:");
+	if daemon_running {
+		eprintln!("  stop the running daemon and its services");
+	}
+	for path in &targets {
+		eprintln!("  remove {}", path.display());
+	}
+	if !purge {
+		eprintln!("config in {} is kept (use --purge to remove it)", protocol::config_dir().display());
+	}
+
+	if !yes && !confirm("continue?") {
+		eprintln!("aborted");
+		return;
+	}
+
+	if daemon_running {
+		for name in crate::config::load_service_entries().keys() {
+			let _ = crate::send_request(&Request::Stop { names: vec![name.clone()], drain_tasks: None });
+		}
+		let _ = crate::send_request(&Request::Shutdown);
+		std::thread::sleep(std::time::Duration::from_millis(300));
+	}
+
+	for path in &targets {
+		let result = if path.is_dir() && !path.is_symlink() {
+			fs::remove_dir_all(path)
+		} else {
+			fs::remove_file(path)
+		};
+		match result {
+			Ok(()) => eprintln!("removed {}", path.display()),
+			Err(e) => eprintln!("error: failed to remove {}: {}", path.display(), e),
+		}
+	}
+}
+
+fn confirm(prompt: &str) -> bool {
+	eprint!("{} [y/N] ", prompt);
+	let mut answer = String::new();
+	if std::io::stdin().read_line(&mut answer).is_err() {
+		return false;
+	}
+	matches!(answer.trim(), "y" | "Y" | "yes")
+}
+
 fn fetch_latest_version() -> Result<String, String> {
 	let output = Command::new("curl")
 		.args([
@@ -137,7 +209,7 @@ fn detect_target() -> String {
 	format!("{}-{}", arch_part, os_part)
 }
 
-fn download(url: &str, dest: &PathBuf) -> Result<(), String> {
+fn download(url: &str, dest: &Path) -> Result<(), String> {
 	let status = Command::new("curl")
 		.args(["-fsSL", "-o", &dest.to_string_lossy(), url])
 		.status()
@@ -146,11 +218,11 @@ fn download(url: &str, dest: &PathBuf) -> Result<(), String> {
 	if status.success() {
 		Ok(())
 	} else {
-		Err(format!("download failed (HTTP error)"))
+		Err("download failed (HTTP error)".to_string())
 	}
 }
 
-fn replace_binary(src: &PathBuf, dest: &PathBuf) -> Result<(), String> {
+fn replace_binary(src: &Path, dest: &Path) -> Result<(), String> {
 	// Atomic-ish replacement: rename old, move new, remove old
 	let backup = dest.with_extension("old");
 	let _ = fs::remove_file(&backup);
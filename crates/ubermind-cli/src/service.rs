@@ -0,0 +1,201 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const SYSTEMD_UNIT: &str = "ubermind.service";
+
+/// Registers the daemon with the platform's service manager so it starts at login.
+trait ServiceManager {
+	fn name(&self) -> &'static str;
+	/// Where the service definition lives.
+	fn path(&self) -> PathBuf;
+	fn install(&self, exe: &Path) -> Result<(), String>;
+	fn uninstall(&self) -> Result<(), String>;
+}
+
+struct Launchd;
+
+impl Launchd {
+	fn label() -> String {
+		format!("{}daemon", crate::launchd::UBERMIND_PREFIX)
+	}
+}
+
+impl ServiceManager for Launchd {
+	fn name(&self) -> &'static str {
+		"launchd"
+	}
+
+	fn path(&self) -> PathBuf {
+		crate::launchd::user_agents_dir().join(format!("{}.plist", Self::label()))
+	}
+
+	fn install(&self, exe: &Path) -> Result<(), String> {
+		let label = Self::label();
+		let log_dir = crate::launchd::launchd_log_dir();
+		let mut dict = plist::Dictionary::new();
+		dict.insert("Label".to_string(), plist::Value::String(label.clone()));
+		dict.insert(
+			"ProgramArguments".to_string(),
+			plist::Value::Array(
+				[exe.to_string_lossy().as_ref(), "daemon", "run", "--http"]
+					.iter()
+					.map(|s| plist::Value::String(s.to_string()))
+					.collect(),
+			),
+		);
+		dict.insert("RunAtLoad".to_string(), plist::Value::Boolean(true));
+		dict.insert("KeepAlive".to_string(), plist::Value::Boolean(true));
+		dict.insert(
+			"StandardOutPath".to_string(),
+			plist::Value::String(log_dir.join(format!("{}.log", label)).to_string_lossy().to_string()),
+		);
+		dict.insert(
+			"StandardErrorPath".to_string(),
+			plist::Value::String(log_dir.join(format!("{}.err", label)).to_string_lossy().to_string()),
+		);
+		if let Ok(path) = std::env::var("PATH") {
+			let mut env = plist::Dictionary::new();
+			env.insert("PATH".to_string(), plist::Value::String(path));
+			dict.insert("EnvironmentVariables".to_string(), plist::Value::Dictionary(env));
+		}
+
+		let plist_path = self.path();
+		let _ = std::fs::create_dir_all(crate::launchd::user_agents_dir());
+		let _ = std::fs::create_dir_all(&log_dir);
+		plist::Value::Dictionary(dict)
+			.to_file_xml(&plist_path)
+			.map_err(|e| format!("failed to write {}: {}", plist_path.display(), e))?;
+
+		let target = format!("gui/{}", crate::launchd::get_uid());
+		run("launchctl", &["bootstrap", &target, &plist_path.to_string_lossy()])
+	}
+
+	fn uninstall(&self) -> Result<(), String> {
+		let target = format!("gui/{}/{}", crate::launchd::get_uid(), Self::label());
+		// Not loaded is fine; the plist is still removed
+		let _ = run("launchctl", &["bootout", &target]);
+		remove_file(&self.path())
+	}
+}
+
+struct Systemd;
+
+impl Systemd {
+	fn unit(exe: &Path) -> String {
+		let mut unit = String::new();
+		unit.push_str("[Unit]\n");
+		unit.push_str("Description=ubermind process daemon\n\n");
+		unit.push_str("[Service]\n");
+		unit.push_str(&format!("ExecStart={} daemon run --http\n", exe.display()));
+		if let Ok(path) = std::env::var("PATH") {
+			unit.push_str(&format!("Environment=PATH={}\n", path));
+		}
+		unit.push_str("Restart=on-failure\n\n");
+		unit.push_str("[Install]\n");
+		unit.push_str("WantedBy=default.target\n");
+		unit
+	}
+}
+
+impl ServiceManager for Systemd {
+	fn name(&self) -> &'static str {
+		"systemd"
+	}
+
+	fn path(&self) -> PathBuf {
+		let config = std::env::var("XDG_CONFIG_HOME")
+			.map(PathBuf::from)
+			.unwrap_or_else(|_| PathBuf::from(std::env::var("HOME").unwrap_or_else(|_| "/tmp".to_string())).join(".config"));
+		config.join("systemd").join("user").join(SYSTEMD_UNIT)
+	}
+
+	fn install(&self, exe: &Path) -> Result<(), String> {
+		let path = self.path();
+		if let Some(dir) = path.parent() {
+			let _ = std::fs::create_dir_all(dir);
+		}
+		std::fs::write(&path, Self::unit(exe)).map_err(|e| format!("failed to write {}: {}", path.display(), e))?;
+		run("systemctl", &["--user", "daemon-reload"])?;
+		run("systemctl", &["--user", "enable", "--now", SYSTEMD_UNIT])
+	}
+
+	fn uninstall(&self) -> Result<(), String> {
+		let _ = run("systemctl", &["--user", "disable", "--now", SYSTEMD_UNIT]);
+		remove_file(&self.path())?;
+		run("systemctl", &["--user", "daemon-reload"])
+	}
+}
+
+fn service_manager() -> Option<Box<dyn ServiceManager>> {
+	if cfg!(target_os = "macos") {
+		Some(Box::new(Launchd))
+	} else if cfg!(target_os = "linux") {
+		Some(Box::new(Systemd))
+	} else {
+		None
+	}
+}
+
+fn run(program: &str, args: &[&str]) -> Result<(), String> {
+	let output = Command::new(program)
+		.args(args)
+		.output()
+		.map_err(|e| format!("failed to run {}: {}", program, e))?;
+	if output.status.success() {
+		Ok(())
+	} else {
+		Err(format!(
+			"{} {} failed: {}",
+			program,
+			args.join(" "),
+			String::from_utf8_lossy(&output.stderr).trim()
+		))
+	}
+}
+
+fn remove_file(path: &Path) -> Result<(), String> {
+	match std::fs::remove_file(path) {
+		Ok(()) => Ok(()),
+		Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+		Err(e) => Err(format!("failed to remove {}: {}", path.display(), e)),
+	}
+}
+
+fn manager_or_exit() -> Box<dyn ServiceManager> {
+	service_manager().unwrap_or_else(|| {
+		eprintln!("error: no supported service manager on this platform");
+		std::process::exit(1);
+	})
+}
+
+pub fn cmd_install_service() {
+	let manager = manager_or_exit();
+	let exe = match std::env::current_exe() {
+		Ok(exe) => exe.canonicalize().unwrap_or(exe),
+		Err(e) => {
+			eprintln!("error: cannot locate the ubermind binary: {}", e);
+			std::process::exit(1);
+		}
+	};
+	match manager.install(&exe) {
+		Ok(()) => eprintln!("installed {} service {}", manager.name(), manager.path().display()),
+		Err(e) => {
+			eprintln!("error: {}", e);
+			if manager.path().exists() {
+				eprintln!("the service file was written to {}", manager.path().display());
+			}
+			std::process::exit(1);
+		}
+	}
+}
+
+pub fn cmd_uninstall_service() {
+	let manager = manager_or_exit();
+	match manager.uninstall() {
+		Ok(()) => eprintln!("removed {} service {}", manager.name(), manager.path().display()),
+		Err(e) => {
+			eprintln!("error: {}", e);
+			std::process::exit(1);
+		}
+	}
+}
@@ -2,7 +2,7 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::path::PathBuf;
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default, schemars::JsonSchema)]
 #[serde(rename_all = "lowercase")]
 pub enum ServiceType {
 	#[default]
@@ -10,6 +10,16 @@ pub enum ServiceType {
 	Task,
 }
 
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Default, schemars::JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ReloadPolicy {
+	/// Stop the old instance, then start the new one.
+	#[default]
+	Restart,
+	/// Start the new instance, wait for its readiness probe, then stop the old one.
+	Graceful,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Service {
 	pub name: String,
@@ -17,7 +27,7 @@ pub struct Service {
 	pub processes: Vec<ProcessDef>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct ProcessDef {
 	pub name: String,
 	pub command: String,
@@ -29,10 +39,115 @@ pub struct ProcessDef {
 	pub max_retries: u32,
 	#[serde(default = "default_restart_delay")]
 	pub restart_delay_secs: u64,
+	/// Only crashes within this many seconds count towards `max_retries`.
+	#[serde(default)]
+	pub max_retries_window_secs: Option<u64>,
+	/// Each restart delay is stretched by a random fraction up to this.
+	#[serde(default)]
+	pub restart_jitter: f64,
+	/// Each consecutive restart waits this many times longer than the one before.
+	#[serde(default = "default_restart_backoff")]
+	pub restart_backoff: f64,
+	/// A crash after this many seconds of uptime starts the retry count over.
+	#[serde(default)]
+	pub healthy_after_secs: Option<u64>,
+	/// Exit codes that count as a clean exit rather than a crash or failure.
+	#[serde(default = "default_success_exit_codes")]
+	pub success_exit_codes: Vec<i32>,
+	/// Signals sent to the process group on stop, each followed by a wait in
+	/// seconds before the next; stops early once the group is gone.
+	#[serde(default = "default_stop_signals")]
+	pub stop_signals: Vec<(String, u64)>,
 	#[serde(default)]
 	pub env: HashMap<String, String>,
+	/// Only `env` and the daemon's PATH reach the process, not the rest of the
+	/// daemon's environment.
+	#[serde(default)]
+	pub env_clear: bool,
 	#[serde(default = "default_true")]
 	pub autostart: bool,
+	#[serde(default)]
+	pub reload_policy: ReloadPolicy,
+	/// TCP port on 127.0.0.1 that accepts connections once the process is ready.
+	#[serde(default)]
+	pub ready_port: Option<u16>,
+	#[serde(default = "default_ready_timeout")]
+	pub ready_timeout_secs: u64,
+	/// Multi-service starts bring up lower priorities first, each tier ready
+	/// before the next begins.
+	#[serde(default)]
+	pub start_priority: i32,
+	/// Signal sent to the process group for an in-place reload instead of a restart.
+	#[serde(default)]
+	pub reload_signal: Option<String>,
+	/// Ports the process is expected to listen on, checked for conflicts before start.
+	#[serde(default)]
+	pub ports: Vec<u16>,
+	/// When false, stdout/stderr are discarded instead of captured and logged.
+	#[serde(default = "default_true")]
+	pub capture: bool,
+	/// Run attached to a pseudo-terminal, for programs that buffer or drop
+	/// color without one. Output is captured from the terminal.
+	#[serde(default)]
+	pub pty: bool,
+	/// Absolute path to write this process's log to instead of the dated file in the
+	/// log directory. Such files are never rotated or expired; cleanup is up to the user.
+	#[serde(default)]
+	pub log_file: Option<PathBuf>,
+	/// Cap on the total size of the service's log directory, enforced when old logs
+	/// are expired. The largest value set on any of a service's processes applies.
+	#[serde(default)]
+	pub max_log_total_bytes: Option<u64>,
+	/// Rotate this process's log at this size instead of `logs.max_size_bytes`.
+	#[serde(default)]
+	pub max_log_size: Option<u64>,
+	/// Niceness applied to the process before exec.
+	#[serde(default)]
+	pub nice: Option<i32>,
+	/// File mode creation mask applied to the process before exec.
+	#[serde(default)]
+	pub umask: Option<u32>,
+	/// What `ub drain` does before stopping the process.
+	#[serde(default)]
+	pub drain: Option<DrainConfig>,
+	/// Shell command run once the process has failed for good: out of
+	/// retries, or for a task, on its first failed run.
+	#[serde(default)]
+	pub on_failure: Option<String>,
+}
+
+/// Taking a process out of rotation ahead of a stop, e.g. deregistering it from
+/// a load balancer while it keeps serving in-flight requests.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default, schemars::JsonSchema)]
+pub struct DrainConfig {
+	/// Shell command run in the service directory first; if it fails the
+	/// process is left running.
+	#[serde(default)]
+	pub run: Option<String>,
+	/// Seconds the process keeps running after `run` before it is stopped.
+	#[serde(default)]
+	pub wait_secs: u64,
+}
+
+impl Service {
+	/// The cap on the service's log directory. services.toml has no service-level
+	/// table, so any process may set it; the directory is shared, so one value
+	/// applies to all of them: the largest, so no process loses history another
+	/// asked to keep. None when no process sets one.
+	pub fn max_log_total_bytes(&self) -> Option<u64> {
+		self.processes.iter().filter_map(|p| p.max_log_total_bytes).max()
+	}
+}
+
+impl ProcessDef {
+	/// Graceful reloads need a readiness probe to know when to retire the old instance.
+	pub fn reloads_gracefully(&self) -> bool {
+		self.reload_policy == ReloadPolicy::Graceful && self.ready_port.is_some()
+	}
+
+	pub fn is_success(&self, exit_code: i32) -> bool {
+		self.success_exit_codes.contains(&exit_code)
+	}
 }
 
 fn default_true() -> bool {
@@ -44,19 +159,160 @@ fn default_max_retries() -> u32 {
 fn default_restart_delay() -> u64 {
 	1
 }
+pub fn default_restart_backoff() -> f64 {
+	1.0
+}
+pub fn default_success_exit_codes() -> Vec<i32> {
+	vec![0]
+}
+pub fn default_stop_signals() -> Vec<(String, u64)> {
+	vec![("SIGTERM".to_string(), 3), ("SIGKILL".to_string(), 0)]
+}
+pub fn default_ready_timeout() -> u64 {
+	30
+}
 
+/// Deserializing also accepts the bare `"Stopped"` sent by daemons from before
+/// stop reasons, so a newer CLI can still read their status.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(remote = "Self")]
 pub enum ProcessState {
 	Running { pid: u32, uptime_secs: u64 },
-	Stopped,
+	Stopped { reason: StopReason },
 	Crashed { exit_code: i32, retries: u32 },
-	Failed { exit_code: i32 },
+	/// A task that failed, or a process that gave up restarting; `finished_at`
+	/// is unix seconds (0 from daemons that didn't record it).
+	Failed {
+		exit_code: i32,
+		#[serde(default)]
+		finished_at: u64,
+	},
+	/// A task that ran to completion; `finished_at` is unix seconds.
+	Completed { exit_code: i32, finished_at: u64 },
+}
+
+/// A process state change as kept in the daemon's event log.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProcessEvent {
+	pub service: String,
+	pub process: String,
+	pub state: String,
+	/// Exit code, stop reason or pid, depending on the state.
+	pub detail: String,
+	/// Unix seconds.
+	pub at: u64,
+}
+
+/// How a process in services.toml differs from the one the daemon is running.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProcessDiff {
+	pub process: String,
+	pub change: DiffChange,
+	/// Running now (for `added`: would start on reload).
+	pub running: bool,
+	/// For `changed`: each field that differs, as (field, running value, configured value).
+	#[serde(default)]
+	pub fields: Vec<(String, String, String)>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DiffChange {
+	Added,
+	Removed,
+	Changed,
+}
+
+/// Why a process is in the `Stopped` state.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Default)]
+pub enum StopReason {
+	/// Hasn't run since the daemon started managing it, or was cleared.
+	#[default]
+	NotStarted,
+	/// Stopped, killed or removed on request.
+	Manual,
+	/// A service that exited on its own with status 0.
+	CleanExit,
+}
+
+impl StopReason {
+	pub fn label(&self) -> &'static str {
+		match self {
+			StopReason::NotStarted => "off",
+			StopReason::Manual => "stopped",
+			StopReason::CleanExit => "exited",
+		}
+	}
+}
+
+impl Serialize for ProcessState {
+	fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		ProcessState::serialize(self, serializer)
+	}
+}
+
+impl<'de> Deserialize<'de> for ProcessState {
+	fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		#[derive(Deserialize)]
+		enum Legacy {
+			Stopped,
+		}
+		#[derive(Deserialize)]
+		#[serde(untagged)]
+		enum Wire {
+			Current(#[serde(with = "ProcessState")] ProcessState),
+			Legacy(Legacy),
+		}
+		Ok(match Wire::deserialize(deserializer)? {
+			Wire::Current(state) => state,
+			Wire::Legacy(Legacy::Stopped) => ProcessState::Stopped { reason: StopReason::default() },
+		})
+	}
 }
 
 impl ProcessState {
 	pub fn is_running(&self) -> bool {
 		matches!(self, ProcessState::Running { .. })
 	}
+
+	/// A finished task outcome that sticks around until `ub clear`.
+	pub fn is_task_record(&self) -> bool {
+		matches!(self, ProcessState::Completed { .. } | ProcessState::Failed { .. })
+	}
+
+	/// The state's particulars, for the event log.
+	pub fn detail(&self) -> String {
+		match self {
+			ProcessState::Running { pid, .. } => format!("pid {}", pid),
+			ProcessState::Stopped { reason } => match reason {
+				StopReason::NotStarted => "not started".to_string(),
+				StopReason::Manual => "manual".to_string(),
+				StopReason::CleanExit => "exit 0".to_string(),
+			},
+			ProcessState::Crashed { exit_code, retries } => format!("exit {}, retry {}", exit_code, retries),
+			ProcessState::Failed { exit_code, .. } | ProcessState::Completed { exit_code, .. } => {
+				format!("exit {}", exit_code)
+			}
+		}
+	}
+
+	pub fn label(&self) -> &'static str {
+		match self {
+			ProcessState::Running { .. } => "Running",
+			ProcessState::Stopped { .. } => "Stopped",
+			ProcessState::Crashed { .. } => "Crashed",
+			ProcessState::Failed { .. } => "Failed",
+			ProcessState::Completed { .. } => "Completed",
+		}
+	}
+}
+
+/// Seconds since the Unix epoch.
+pub fn unix_now() -> u64 {
+	std::time::SystemTime::now()
+		.duration_since(std::time::UNIX_EPOCH)
+		.map(|d| d.as_secs())
+		.unwrap_or(0)
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -83,4 +339,46 @@ pub struct ProcessStatus {
 	pub service_type: ServiceType,
 	#[serde(default)]
 	pub ports: Vec<u16>,
+	#[serde(default)]
+	pub oom_killed: bool,
+	#[serde(default)]
+	pub restart_count: u32,
+	/// Unix seconds of the latest relaunch.
+	#[serde(default)]
+	pub last_restart: Option<u64>,
+	/// Summed over the process group; only sampled by a full status request.
+	#[serde(default)]
+	pub cpu_percent: Option<f32>,
+	#[serde(default)]
+	pub rss_bytes: Option<u64>,
+	/// Unix milliseconds when the running instance started.
+	#[serde(default)]
+	pub started_at_ms: Option<u64>,
+	/// False when output can't be written to the log file and is only kept in memory.
+	#[serde(default = "default_true")]
+	pub log_file_ok: bool,
+	/// Result of probing `ready_port`; None when the process has no probe.
+	#[serde(default)]
+	pub health: Option<Health>,
+	/// What the latest launch actually ran; only filled in for a single-process
+	/// status request.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub resolved: Option<ResolvedCommand>,
+}
+
+/// The argv and working directory a process was spawned with, after variable
+/// expansion and shell wrapping.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResolvedCommand {
+	pub argv: Vec<String>,
+	pub cwd: PathBuf,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Health {
+	/// Has a probe but is not running, so it was not checked.
+	Unknown,
+	Healthy,
+	Unhealthy,
 }
@@ -2,7 +2,7 @@ use axum::Json;
 use axum::Router;
 use axum::extract::Path;
 use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
-use axum::http::StatusCode;
+use axum::http::{HeaderValue, Method, StatusCode};
 use axum::response::IntoResponse;
 use axum::routing::{get, post};
 use std::env;
@@ -10,7 +10,7 @@ use std::fs;
 use std::net::SocketAddr;
 use std::path::PathBuf;
 use std::process::Command;
-use tower_http::cors::CorsLayer;
+use tower_http::cors::{Any, CorsLayer};
 use tower_http::services::ServeDir;
 
 use crate::services;
@@ -42,7 +42,7 @@ pub async fn start(port: u16, static_dir: Option<PathBuf>) {
         .route("/ws/echo/{name}", get(ws_echo))
         .route("/api/serve/status", get(api_serve_status))
         .route("/api/serve/logs", get(api_serve_logs))
-        .layer(CorsLayer::permissive());
+        .layer(cors_layer(&services::cors_origins()));
 
     let app = if let Some(dir) = static_dir {
         api.fallback_service(ServeDir::new(dir).append_index_html_on_directories(true))
@@ -57,6 +57,26 @@ pub async fn start(port: u16, static_dir: Option<PathBuf>) {
     axum::serve(listener, app).await.unwrap();
 }
 
+fn cors_layer(origins: &[String]) -> CorsLayer {
+    if origins.is_empty() {
+        return CorsLayer::permissive();
+    }
+    let origins: Vec<HeaderValue> = origins
+        .iter()
+        .filter_map(|o| match o.parse() {
+            Ok(v) => Some(v),
+            Err(_) => {
+                log::warn!("ignoring invalid cors origin: {o}");
+                None
+            }
+        })
+        .collect();
+    CorsLayer::new()
+        .allow_origin(origins)
+        .allow_methods([Method::GET, Method::POST])
+        .allow_headers(Any)
+}
+
 async fn api_services() -> Json<Vec<services::ServiceInfo>> {
     Json(services::list_services())
 }
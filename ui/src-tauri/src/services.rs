@@ -60,6 +60,23 @@ fn commands_config_path() -> PathBuf {
     config_dir().join("commands")
 }
 
+/// Origins allowed to call the HTTP API, from `daemon.cors_origins` in config.toml.
+/// Empty means any origin is allowed.
+pub fn cors_origins() -> Vec<String> {
+    let Ok(content) = fs::read_to_string(config_dir().join("config.toml")) else {
+        return Vec::new();
+    };
+    let Ok(value) = content.parse::<toml::Value>() else {
+        return Vec::new();
+    };
+    value
+        .get("daemon")
+        .and_then(|d| d.get("cors_origins"))
+        .and_then(|o| o.as_array())
+        .map(|list| list.iter().filter_map(|o| o.as_str().map(String::from)).collect())
+        .unwrap_or_default()
+}
+
 pub struct Service {
     pub name: String,
     pub dir: PathBuf,
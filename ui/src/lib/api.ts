@@ -17,11 +17,24 @@ async function tauriInvoke<T>(
   return invoke<T>(cmd, args);
 }
 
+/** A failed API request. `code` is the daemon's machine-readable error code,
+ * the same one the socket protocol sends, e.g. "unknown_service",
+ * "not_running" or "port_in_use". */
+export class ApiError extends Error {
+  constructor(
+    message: string,
+    public code: string | undefined,
+    public status: number,
+  ) {
+    super(message);
+  }
+}
+
 async function httpGet<T>(path: string): Promise<T> {
   const res = await fetch(`${apiBase()}${path}`);
   if (!res.ok) {
     const body = await res.json().catch(() => ({ error: res.statusText }));
-    throw new Error(body.error || res.statusText);
+    throw new ApiError(body.error || res.statusText, body.code, res.status);
   }
   return res.json();
 }
@@ -30,7 +43,7 @@ async function httpPost<T>(path: string): Promise<T> {
   const res = await fetch(`${apiBase()}${path}`, { method: "POST" });
   if (!res.ok) {
     const body = await res.json().catch(() => ({ error: res.statusText }));
-    throw new Error(body.error || res.statusText);
+    throw new ApiError(body.error || res.statusText, body.code, res.status);
   }
   return res.json();
 }
@@ -102,9 +115,18 @@ export async function reloadService(name: string): Promise<string> {
   return res.message;
 }
 
-export async function echoService(name: string): Promise<string> {
+export async function echoService(
+  name: string,
+  bytes?: number,
+): Promise<string> {
   if (isTauri()) return tauriInvoke("echo_service", { name });
-  return httpGetText(`/api/services/${name}/echo`);
+  const query = bytes === undefined ? "" : `?bytes=${bytes}`;
+  const snapshot = await httpGet<Record<string, string>>(
+    `/api/services/${name}/snapshot${query}`,
+  );
+  return Object.entries(snapshot)
+    .map(([process, output]) => `--- ${process} ---\n${output}`)
+    .join("\n");
 }
 
 export async function getPanes(name: string): Promise<TmuxPane[]> {
@@ -145,6 +167,6 @@ export async function killProcess(
 
 export function echoWebSocketUrl(name: string): string {
   if (typeof window === "undefined")
-    return `ws://localhost:${API_PORT}/ws/echo/${name}`;
-  return `ws://${window.location.hostname}:${API_PORT}/ws/echo/${name}`;
+    return `ws://localhost:${API_PORT}/ws/echo/${name}?tagged=true`;
+  return `ws://${window.location.hostname}:${API_PORT}/ws/echo/${name}?tagged=true`;
 }